@@ -0,0 +1,239 @@
+// Datei: src/barebox.rs
+//! barebox bootchooser backend.
+//!
+//! Reads and writes the `bootstate.<x>.priority` and
+//! `bootstate.<x>.remaining_attempts` variables of each bootchooser target
+//! through the `barebox-state` tool, following RAUC's barebox integration.
+
+use crate::cmd::{run_checked, run_stdout, CmdRunner, RealCmdRunner};
+use crate::config::HealthConfig;
+use crate::marker::{SlotBootState, SlotMarker, SlotNames};
+use crate::rauc::{MarkState, SlotIdentifier};
+
+/// Priority RAUC gives the primary target.
+pub const PRIORITY_PRIMARY: u32 = 20;
+/// Priority RAUC gives the remaining enabled targets.
+pub const PRIORITY_SECONDARY: u32 = 10;
+
+/// Bootchooser variables of one target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetState {
+    /// Boot priority; 0 disables the target.
+    pub priority: u32,
+    /// Boot attempts left before bootchooser skips the target.
+    pub remaining_attempts: u32,
+}
+
+/// Bootchooser variable of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Priority,
+    RemainingAttempts,
+}
+
+impl Field {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Priority => "priority",
+            Self::RemainingAttempts => "remaining_attempts",
+        }
+    }
+
+    fn get(self, s: TargetState) -> u32 {
+        match self {
+            Self::Priority => s.priority,
+            Self::RemainingAttempts => s.remaining_attempts,
+        }
+    }
+}
+
+/// Slot marker using `barebox-state -g/-s`.
+///
+/// - good: `remaining_attempts` = boot attempts
+/// - bad: `priority` = 0, `remaining_attempts` = 0
+/// - active: priority 20 for the target, 10 for the other enabled targets,
+///   `remaining_attempts` reset
+pub struct BareboxMarker<'a> {
+    runner: Box<dyn CmdRunner + 'a>,
+    boot_attempts: u32,
+    names: SlotNames,
+}
+
+impl<'a> BareboxMarker<'a> {
+    /// Creates a marker running `barebox-state` via `runner`.
+    ///
+    /// The bootchooser targets are the bootnames in `names`.
+    #[must_use]
+    pub fn new(runner: impl CmdRunner + 'a, boot_attempts: u32, names: SlotNames) -> Self {
+        Self {
+            runner: Box::new(runner),
+            boot_attempts,
+            names,
+        }
+    }
+
+    fn targets(&self) -> Result<Vec<String>, String> {
+        let mut targets: Vec<String> = self.names.bootnames.values().cloned().collect();
+        targets.sort();
+        targets.dedup();
+        if targets.is_empty() {
+            return Err("no bootchooser targets known (no bootnames in RAUC system.conf)".into());
+        }
+        Ok(targets)
+    }
+
+    /// Reads priority and remaining attempts of all targets.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if `barebox-state` fails or prints unexpected
+    /// output.
+    pub fn read_targets(&self) -> Result<Vec<(String, TargetState)>, String> {
+        let targets = self.targets()?;
+        let mut args = Vec::new();
+        for t in &targets {
+            args.push("-g".to_string());
+            args.push(format!("bootstate.{t}.priority"));
+            args.push("-g".to_string());
+            args.push(format!("bootstate.{t}.remaining_attempts"));
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let stdout = run_stdout(&*self.runner, "barebox-state", &args, "barebox-state -g")?;
+
+        let values = stdout
+            .lines()
+            .map(|l| {
+                l.trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("unexpected barebox-state output `{l}`"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.len() != targets.len() * 2 {
+            return Err(format!(
+                "barebox-state returned {} values, expected {}",
+                values.len(),
+                targets.len() * 2
+            ));
+        }
+
+        Ok(targets
+            .into_iter()
+            .zip(values.chunks(2))
+            .map(|(t, v)| {
+                (
+                    t,
+                    TargetState {
+                        priority: v[0],
+                        remaining_attempts: v[1],
+                    },
+                )
+            })
+            .collect())
+    }
+
+    fn write_vars(&self, vars: &[(&str, Field, u32)]) -> Result<(), String> {
+        let mut args = Vec::new();
+        for (t, field, v) in vars {
+            args.push("-s".to_string());
+            args.push(format!("bootstate.{t}.{}={v}", field.name()));
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        run_checked(&*self.runner, "barebox-state", &args, "barebox-state -s")
+    }
+}
+
+impl BareboxMarker<'static> {
+    /// Creates the marker from the `[barebox]` and `[commands]` sections of
+    /// `cfg`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if a program path in `[commands]` is invalid.
+    pub fn from_config(cfg: &HealthConfig) -> Result<Self, String> {
+        Ok(Self::new(
+            RealCmdRunner::new(&cfg.commands)?,
+            cfg.barebox.boot_attempts,
+            SlotNames::detect(&cfg.rauc_system_conf),
+        ))
+    }
+}
+
+/// Target names sorted by descending priority.
+fn by_priority(targets: &[(String, TargetState)]) -> Vec<String> {
+    let mut sorted: Vec<_> = targets.iter().collect();
+    sorted.sort_by_key(|(_, s)| std::cmp::Reverse(s.priority));
+    sorted.into_iter().map(|(t, _)| t.clone()).collect()
+}
+
+impl SlotMarker for BareboxMarker<'_> {
+    fn mark(&self, state: MarkState, slot: &SlotIdentifier) -> Result<(), String> {
+        let targets = self.read_targets()?;
+        let bootname = self.names.resolve(slot, &by_priority(&targets))?;
+        let Some(current) = targets
+            .iter()
+            .find(|(t, _)| *t == bootname)
+            .map(|(_, s)| *s)
+        else {
+            return Err(format!("`{bootname}` is not a bootchooser target"));
+        };
+
+        let mut vars: Vec<(&str, Field, u32)> = Vec::new();
+        match state {
+            MarkState::Good => {
+                vars.push((&bootname, Field::RemainingAttempts, self.boot_attempts));
+            }
+            MarkState::Bad => {
+                vars.push((&bootname, Field::Priority, 0));
+                vars.push((&bootname, Field::RemainingAttempts, 0));
+            }
+            MarkState::Active => {
+                for (t, s) in &targets {
+                    let priority = if *t == bootname {
+                        PRIORITY_PRIMARY
+                    } else if s.priority > 0 {
+                        PRIORITY_SECONDARY
+                    } else {
+                        0
+                    };
+                    vars.push((t, Field::Priority, priority));
+                }
+                vars.push((&bootname, Field::RemainingAttempts, self.boot_attempts));
+            }
+        }
+
+        // Skip writes that would not change anything.
+        vars.retain(|(t, field, v)| {
+            targets
+                .iter()
+                .find(|(n, _)| n == t)
+                .is_none_or(|(_, s)| field.get(*s) != *v)
+        });
+        if vars.is_empty() {
+            log::info!(
+                "barebox: slot {bootname} already {state} (priority {}, {} attempts left)",
+                current.priority,
+                current.remaining_attempts
+            );
+            return Ok(());
+        }
+
+        self.write_vars(&vars)?;
+        log::info!("barebox: marked slot {bootname} as {state}");
+        Ok(())
+    }
+
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String> {
+        let targets = self.read_targets()?;
+        let order = by_priority(&targets);
+        Ok(targets
+            .iter()
+            .map(|(t, s)| SlotBootState {
+                bootname: t.clone(),
+                order: (s.priority > 0)
+                    .then(|| order.iter().position(|o| o == t))
+                    .flatten(),
+                remaining_attempts: s.remaining_attempts,
+            })
+            .collect())
+    }
+}
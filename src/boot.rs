@@ -0,0 +1,370 @@
+// Datei: src/boot.rs
+//! Classification of the current boot as trial boot (first boot after an
+//! update) or normal boot.
+
+use crate::cli::RecordUpdateArgs;
+use crate::config::{HealthConfig, MarkerBackend, TrialBootDetection};
+use crate::marker::SlotMarker;
+use crate::rauc::{MarkState, RaucStatus, SlotIdentifier};
+use crate::state::{ConfirmedSlot, PendingUpdate};
+use std::fmt;
+
+/// Kind of the current boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootKind {
+    /// First boot(s) of a freshly installed slot; the strict gate applies.
+    Trial,
+    /// Boot of a slot that has already been confirmed.
+    Normal,
+}
+
+impl fmt::Display for BootKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Trial => f.write_str("trial boot"),
+            Self::Normal => f.write_str("normal boot"),
+        }
+    }
+}
+
+/// The bootloader booted another slot than the last installation wrote to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rollback {
+    /// Bootname of the slot that was actually booted.
+    pub booted: String,
+    /// RAUC slot name the last update was installed to.
+    pub expected_slot: String,
+    /// Bootname of `expected_slot`, if known.
+    pub expected_bootname: Option<String>,
+}
+
+impl fmt::Display for Rollback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "booted slot {} but last update was installed to {}",
+            self.booted, self.expected_slot
+        )?;
+        if let Some(b) = &self.expected_bootname {
+            write!(f, " ({b})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of [`assess_boot`]: the boot kind, why, and what is needed to
+/// confirm the slot later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootAssessment {
+    pub kind: BootKind,
+    /// Human readable reason for the classification.
+    pub reason: String,
+    /// Name of the booted RAUC slot, if known.
+    pub booted_slot: Option<String>,
+    /// Installation timestamp of the booted slot, if known.
+    pub installed: Option<jiff::Timestamp>,
+    /// Set if the bootloader fell back from the freshly installed slot.
+    pub rollback: Option<Rollback>,
+}
+
+impl BootAssessment {
+    /// Assessment used when trial boot detection is disabled.
+    #[must_use]
+    pub fn detection_disabled() -> Self {
+        Self {
+            kind: BootKind::Trial,
+            reason: "trial boot detection disabled, applying strict gate".to_string(),
+            booted_slot: None,
+            installed: None,
+            rollback: None,
+        }
+    }
+}
+
+/// Extracts the bootname from the `rauc.slot=` kernel parameter.
+#[must_use]
+pub fn bootname_from_cmdline(cmdline: &str) -> Option<String> {
+    cmdline
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix("rauc.slot="))
+        .filter(|b| !b.is_empty())
+        .map(str::to_string)
+}
+
+/// Compares the booted slot with the target of the pending update.
+///
+/// The booted bootname is taken from `cmdline_bootname` (`rauc.slot=`) and
+/// falls back to RAUC's `booted` field. The expected bootname comes from the
+/// pending record or is looked up in `status`.
+///
+/// # Returns
+///
+/// `Some` if both are known and differ, i.e. the bootloader did not boot the
+/// freshly installed slot.
+#[must_use]
+pub fn detect_rollback(
+    pending: Option<&PendingUpdate>,
+    status: Option<&RaucStatus>,
+    cmdline_bootname: Option<&str>,
+) -> Option<Rollback> {
+    let pending = pending?;
+    let booted = cmdline_bootname
+        .map(str::to_string)
+        .or_else(|| status.and_then(|s| s.booted.clone()))?;
+    let expected_bootname = pending.bootname.clone().or_else(|| {
+        status
+            .and_then(|s| s.slot(&pending.slot))
+            .and_then(|s| s.bootname.clone())
+    });
+
+    let fell_back = match &expected_bootname {
+        Some(expected) => *expected != booted,
+        // Without a bootname compare RAUC slot names instead.
+        None => status
+            .and_then(RaucStatus::booted_slot)
+            .is_some_and(|s| s.name != pending.slot),
+    };
+
+    fell_back.then(|| Rollback {
+        booted,
+        expected_slot: pending.slot.clone(),
+        expected_bootname,
+    })
+}
+
+/// Classifies the current boot.
+///
+/// # Behavior
+///
+/// - A pending-update record always means trial boot.
+/// - Otherwise the booted slot from `status` is compared with the last
+///   confirmation: a slot whose installation timestamp has not been
+///   confirmed yet is on trial.
+/// - A slot without RAUC installation record (e.g. factory image) counts as
+///   normal boot.
+/// - Without RAUC status the boot is treated as trial boot, so that an
+///   unknown state never weakens the gate.
+#[must_use]
+pub fn assess_boot(
+    pending: Option<&PendingUpdate>,
+    status: Option<&RaucStatus>,
+    confirmed: Option<&ConfirmedSlot>,
+    cmdline_bootname: Option<&str>,
+) -> BootAssessment {
+    let booted = status.and_then(RaucStatus::booted_slot);
+    let booted_slot = booted.map(|s| s.name.clone());
+    let installed = booted.and_then(|s| s.installed);
+    let rollback = detect_rollback(pending, status, cmdline_bootname);
+    let assessment = |kind, reason: String| BootAssessment {
+        kind,
+        reason,
+        booted_slot: booted_slot.clone(),
+        installed,
+        rollback: rollback.clone(),
+    };
+
+    if let Some(p) = pending {
+        return assessment(
+            BootKind::Trial,
+            format!(
+                "pending update record for slot {} (recorded {})",
+                p.slot, p.timestamp
+            ),
+        );
+    }
+
+    let Some(slot) = booted else {
+        return assessment(
+            BootKind::Trial,
+            "booted slot unknown, treating as trial boot".to_string(),
+        );
+    };
+
+    let Some(installed) = slot.installed else {
+        return assessment(
+            BootKind::Normal,
+            format!("slot {} has no RAUC installation record", slot.name),
+        );
+    };
+
+    let installed_str = installed.to_string();
+    let is_confirmed = confirmed.is_some_and(|c| {
+        c.slot == slot.name && c.installed.as_deref() == Some(installed_str.as_str())
+    });
+    if is_confirmed {
+        assessment(
+            BootKind::Normal,
+            format!(
+                "slot {} (installed {installed_str}) already confirmed",
+                slot.name
+            ),
+        )
+    } else {
+        assessment(
+            BootKind::Trial,
+            format!(
+                "slot {} installed {installed_str} not confirmed yet",
+                slot.name
+            ),
+        )
+    }
+}
+
+/// Production variant of [`assess_boot`] reading the state directory,
+/// `/proc/cmdline` and RAUC status. Without `rauc.slot=` on the command line
+/// the D-Bus backend takes the booted slot from RAUC's `BootSlot` property.
+///
+/// Read errors are logged and treated as missing information. With trial
+/// boot detection disabled and no pending update, RAUC is not queried.
+#[must_use]
+pub fn assess_current_boot(cfg: &HealthConfig) -> BootAssessment {
+    let pending = crate::state::read_pending_update(&cfg.state_dir).unwrap_or_else(|e| {
+        log::warn!("{e}");
+        None
+    });
+    if pending.is_none() && cfg.trial_boot_detection == TrialBootDetection::Off {
+        return BootAssessment::detection_disabled();
+    }
+    let cmdline = std::fs::read_to_string("/proc/cmdline")
+        .map_err(|e| log::warn!("failed to read /proc/cmdline: {e}"))
+        .ok();
+    // Without `rauc.slot=` the D-Bus backend asks RAUC for `BootSlot`
+    let booted = cmdline
+        .as_deref()
+        .and_then(bootname_from_cmdline)
+        .or_else(|| {
+            if cfg.marker_backend != MarkerBackend::Dbus {
+                return None;
+            }
+            crate::dbus::Connection::system()
+                .and_then(|mut c| crate::rauc_dbus::boot_slot(&mut c))
+                .map_err(|e| log::warn!("failed to read RAUC BootSlot: {e}"))
+                .ok()
+        });
+    let status = crate::cmd::RealCmdRunner::new(&cfg.commands)
+        .and_then(|runner| crate::rauc::status_with(&runner))
+        .map_err(|e| log::warn!("failed to query RAUC status: {e}"))
+        .ok();
+    let confirmed = crate::state::read_confirmed_slot(&cfg.state_dir).unwrap_or_else(|e| {
+        log::warn!("{e}");
+        None
+    });
+    assess_boot(
+        pending.as_ref(),
+        status.as_ref(),
+        confirmed.as_ref(),
+        booted.as_deref(),
+    )
+}
+
+/// Handles a detected rollback: optionally marks the abandoned slot bad so
+/// it is not retried, and removes the pending-update record (not with
+/// `dry_run`).
+///
+/// # Errors
+///
+/// Returns `Err(String)` if marking the abandoned slot or clearing the
+/// record fails.
+pub fn handle_rollback(
+    cfg: &HealthConfig,
+    rollback: &Rollback,
+    marker: &dyn SlotMarker,
+) -> Result<(), String> {
+    if cfg.mark_abandoned_slot_bad {
+        log::warn!(
+            "Marking abandoned slot {} as BAD so it is not retried",
+            rollback.expected_slot
+        );
+        marker.mark(
+            MarkState::Bad,
+            &SlotIdentifier::Named(rollback.expected_slot.clone()),
+        )?;
+    }
+    if cfg.dry_run {
+        log::info!("dry run: would clear pending update");
+        return Ok(());
+    }
+    crate::state::clear_pending_update(&cfg.state_dir)
+}
+
+/// Records that the slot from `assessment` passed the strict gate and
+/// removes the pending-update record. With `dry_run` this is only logged.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the state directory cannot be updated.
+pub fn confirm_boot(cfg: &HealthConfig, assessment: &BootAssessment) -> Result<(), String> {
+    if cfg.dry_run {
+        log::info!(
+            "dry run: would confirm slot {}",
+            assessment.booted_slot.as_deref().unwrap_or("(unknown)")
+        );
+        return Ok(());
+    }
+    if let Some(slot) = &assessment.booted_slot {
+        crate::state::write_confirmed_slot(
+            &cfg.state_dir,
+            &ConfirmedSlot {
+                slot: slot.clone(),
+                installed: assessment.installed.map(|t| t.to_string()),
+                timestamp: jiff::Timestamp::now().to_string(),
+            },
+        )?;
+    }
+    crate::state::clear_pending_update(&cfg.state_dir)
+}
+
+/// Determines the target slot of an installation from RAUC's handler
+/// environment (`RAUC_TARGET_SLOTS`, `RAUC_SLOT_NAME_<n>`,
+/// `RAUC_SLOT_BOOTNAME_<n>`).
+///
+/// # Returns
+///
+/// Slot name and bootname of the first target slot that has a bootname.
+#[must_use]
+pub fn target_slot_from_env(env: &dyn Fn(&str) -> Option<String>) -> Option<(String, String)> {
+    let targets = env("RAUC_TARGET_SLOTS")?;
+    targets.split_whitespace().find_map(|n| {
+        let bootname = env(&format!("RAUC_SLOT_BOOTNAME_{n}")).filter(|b| !b.is_empty())?;
+        let name = env(&format!("RAUC_SLOT_NAME_{n}"))?;
+        Some((name, bootname))
+    })
+}
+
+/// Implementation of the `record-update` subcommand.
+///
+/// With `dry_run` (or `dry_run` in the config) the record is only logged.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if no target slot is given or found in the
+/// environment, or if the record cannot be written.
+pub fn record_update(args: &RecordUpdateArgs, dry_run: bool) -> Result<(), String> {
+    let mut cfg = crate::config::load(args.config.as_deref())?;
+    cfg.dry_run |= dry_run;
+
+    let (slot, bootname) = if let Some(slot) = &args.slot {
+        (slot.clone(), args.bootname.clone())
+    } else {
+        let (slot, bootname) = target_slot_from_env(&|k| std::env::var(k).ok())
+            .ok_or("no --slot given and no bootable target slot in RAUC environment")?;
+        (slot, Some(args.bootname.clone().unwrap_or(bootname)))
+    };
+
+    let record = PendingUpdate {
+        slot,
+        bootname,
+        bundle_version: args.bundle_version.clone(),
+        timestamp: jiff::Timestamp::now().to_string(),
+    };
+    if cfg.dry_run {
+        log::info!(
+            "dry run: would record pending update for slot {}",
+            record.slot
+        );
+        return Ok(());
+    }
+    crate::state::write_pending_update(&cfg.state_dir, &record)?;
+    log::info!("Recorded pending update for slot {}", record.slot);
+    Ok(())
+}
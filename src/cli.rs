@@ -1,3 +1,4 @@
+use crate::rauc::SlotIdentifier;
 use clap::Args;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
@@ -6,27 +7,111 @@ use std::path::PathBuf;
 #[command(name = "rauc-health")]
 #[command(about = "Simple RAUC health helper for OpenRC", long_about = None)]
 pub struct Cli {
+    /// Only log what would be marked or recorded; the check itself still
+    /// runs and returns its exit code
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
-    /// Mark the currently booted RAUC slot as good
-    MarkGood,
-    /// Mark the currently booted RAUC slot as bad
-    MarkBad,
-    /// Check `OpenRC` runlevel 'default' and mark RAUC slot good/bad
+    /// Mark a RAUC slot (default: the booted one) as good
+    MarkGood(MarkArgs),
+    /// Mark a RAUC slot (default: the booted one) as bad
+    MarkBad(MarkArgs),
+    /// Mark a RAUC slot (default: the booted one) as primary boot target
+    MarkActive(MarkArgs),
+    /// Check the configured `OpenRC` runlevels (default: 'default') and mark
+    /// RAUC slot good/bad
     CheckOpenrc(CheckOpenrcArgs),
+    /// Show the RAUC slot status together with the last health decision
+    Status(StatusArgs),
+    /// Record a freshly installed slot (for RAUC's post-install handler)
+    RecordUpdate(RecordUpdateArgs),
+    /// Lint the config against the `OpenRC` services of a root file system
+    /// and print the result as JSON (exit code 1 on errors)
+    ValidateConfig(ValidateConfigArgs),
+    /// Print the effective config (base file and drop-ins merged) with the
+    /// origin of every value
+    PrintConfig(PrintConfigArgs),
+    /// Show the detected board and slot facts, which `[[variant]]` sections
+    /// applied, and the resulting effective config
+    Explain(ExplainArgs),
+}
+#[derive(Args, Debug, Clone)]
+pub struct MarkArgs {
+    /// Config file selecting the marker backend (default: rauc CLI)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Slot to mark: `booted`, `other` or a RAUC slot name like `rootfs.1`
+    #[arg(default_value = "booted")]
+    pub slot: SlotIdentifier,
 }
+
 #[derive(Args, Debug, Clone)]
 pub struct CheckOpenrcArgs {
     #[arg(long)]
     pub config: Option<PathBuf>,
 
-    #[arg(long, default_value_t = 30)]
-    pub timeout_secunds: u64,
+    /// Seconds to wait for healthy services (overrides
+    /// `RAUC_HEALTH_TIMEOUT_SECONDS` and `timeout_seconds` in the config)
+    #[arg(long, alias = "timeout-secunds")]
+    pub timeout_seconds: Option<u64>,
+
+    /// Interval between `rc-status` polls (overrides
+    /// `RAUC_HEALTH_POLL_INTERVAL_MS` and `poll_interval_ms` in the config)
+    #[arg(long)]
+    pub poll_interval_ms: Option<u64>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StatusArgs {
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RecordUpdateArgs {
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// RAUC slot the update was installed to (default: taken from the
+    /// `RAUC_TARGET_SLOTS`/`RAUC_SLOT_*` handler environment)
+    #[arg(long)]
+    pub slot: Option<String>,
+
+    /// Bootname of the slot
+    #[arg(long)]
+    pub bootname: Option<String>,
+
+    /// Version of the installed bundle
+    #[arg(long)]
+    pub bundle_version: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ValidateConfigArgs {
+    #[arg(long)]
+    pub config: Option<PathBuf>,
 
-    #[arg(long, default_value_t = 500)]
-    pub poll_interval_ms: u64,
+    /// Root file system containing `/etc/init.d` and `/etc/runlevels`
+    /// (e.g. an image staging directory)
+    #[arg(long, default_value = "/")]
+    pub root: PathBuf,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PrintConfigArgs {
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExplainArgs {
+    #[arg(long)]
+    pub config: Option<PathBuf>,
 }
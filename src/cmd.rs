@@ -1,5 +1,18 @@
 // Datei: src/cmd.rs
-use std::process::{Command, ExitStatus};
+use crate::config::CommandsConfig;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::time::{Duration, Instant};
+
+/// Default cap for captured stdout/stderr, per stream.
+pub const OUTPUT_LIMIT: usize = 1024 * 1024;
+
+/// Number of trailing stderr bytes kept in a [`CmdError::Failed`].
+pub const STDERR_TAIL: usize = 1024;
+
+/// Timeout for commands run via [`run_checked`] and [`run_stdout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_mins(1);
 
 /// Abstraction for running external commands.
 ///
@@ -19,11 +32,238 @@ pub trait CmdRunner {
     /// The returned error string should include the provided `ctx` to describe
     /// what was being attempted.
     fn status(&self, program: &str, args: &[&str], ctx: &str) -> Result<ExitStatus, String>;
+
+    /// Runs `program` with `args` and captures its stdout and stderr.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` under the same conditions as [`CmdRunner::status`].
+    /// A non-zero exit status is **not** an error; it is reported in
+    /// [`Output::status`].
+    fn output(&self, program: &str, args: &[&str], ctx: &str) -> Result<Output, String>;
+
+    /// Like [`CmdRunner::output`], but keeps at most `limit` bytes per stream
+    /// (the beginning of stdout and the end of stderr) and gives up after
+    /// `timeout`.
+    ///
+    /// The default implementation truncates the result of
+    /// [`CmdRunner::output`] and ignores `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CmdError::Spawn`] under the same conditions as
+    /// [`CmdRunner::output`] and [`CmdError::TimedOut`] if the command did
+    /// not finish in time.
+    fn output_capped(
+        &self,
+        program: &str,
+        args: &[&str],
+        ctx: &str,
+        limit: usize,
+        timeout: Duration,
+    ) -> Result<Output, CmdError> {
+        let _ = timeout;
+        let mut output = self.output(program, args, ctx).map_err(CmdError::Spawn)?;
+        output.stdout.truncate(limit);
+        let excess = output.stderr.len().saturating_sub(limit);
+        output.stderr.drain(..excess);
+        Ok(output)
+    }
+}
+
+/// Structured failure of an external command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CmdError {
+    /// The command could not be started; the message includes the context.
+    Spawn(String),
+    /// The command ran but did not exit successfully.
+    Failed {
+        program: String,
+        args: Vec<String>,
+        /// Exit code, if the command exited normally.
+        code: Option<i32>,
+        /// Terminating signal, if the command was killed.
+        signal: Option<i32>,
+        /// Last [`STDERR_TAIL`] bytes of stderr, trimmed.
+        stderr_tail: String,
+    },
+    /// The command did not finish within `timeout`; its process group was
+    /// killed.
+    TimedOut {
+        program: String,
+        args: Vec<String>,
+        timeout: Duration,
+    },
+}
+
+impl CmdError {
+    fn failed(program: &str, args: &[&str], status: ExitStatus, stderr: &[u8]) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+
+        let tail = &stderr[stderr.len().saturating_sub(STDERR_TAIL)..];
+        Self::Failed {
+            program: program.to_string(),
+            args: args.iter().map(|a| (*a).to_string()).collect(),
+            code: status.code(),
+            signal,
+            stderr_tail: String::from_utf8_lossy(tail).trim().to_string(),
+        }
+    }
+
+    fn timed_out(program: &str, args: &[&str], timeout: Duration) -> Self {
+        Self::TimedOut {
+            program: program.to_string(),
+            args: args.iter().map(|a| (*a).to_string()).collect(),
+            timeout,
+        }
+    }
+
+    /// Returns the captured stderr tail (empty unless [`CmdError::Failed`]).
+    #[must_use]
+    pub fn stderr_tail(&self) -> &str {
+        match self {
+            Self::Failed { stderr_tail, .. } => stderr_tail,
+            Self::Spawn(_) | Self::TimedOut { .. } => "",
+        }
+    }
+}
+
+fn write_cmdline(
+    f: &mut std::fmt::Formatter<'_>,
+    program: &str,
+    args: &[String],
+) -> std::fmt::Result {
+    write!(f, "`{program}")?;
+    for a in args {
+        write!(f, " {a}")?;
+    }
+    f.write_str("`")
+}
+
+impl std::fmt::Display for CmdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spawn(e) => f.write_str(e),
+            Self::Failed {
+                program,
+                args,
+                code,
+                signal,
+                stderr_tail,
+            } => {
+                write_cmdline(f, program, args)?;
+                match (code, signal) {
+                    (Some(c), _) => write!(f, " exited with code {c}")?,
+                    (None, Some(s)) => write!(f, " exited with signal {s}")?,
+                    (None, None) => f.write_str(" exited unsuccessfully")?,
+                }
+                if !stderr_tail.is_empty() {
+                    write!(f, ": {stderr_tail}")?;
+                }
+                Ok(())
+            }
+            Self::TimedOut {
+                program,
+                args,
+                timeout,
+            } => {
+                write_cmdline(f, program, args)?;
+                write!(f, " timed out after {timeout:?}")
+            }
+        }
+    }
+}
+
+impl From<CmdError> for String {
+    fn from(e: CmdError) -> Self {
+        e.to_string()
+    }
+}
+
+impl<T: CmdRunner + ?Sized> CmdRunner for &T {
+    fn status(&self, program: &str, args: &[&str], ctx: &str) -> Result<ExitStatus, String> {
+        (**self).status(program, args, ctx)
+    }
+
+    fn output(&self, program: &str, args: &[&str], ctx: &str) -> Result<Output, String> {
+        (**self).output(program, args, ctx)
+    }
+
+    fn output_capped(
+        &self,
+        program: &str,
+        args: &[&str],
+        ctx: &str,
+        limit: usize,
+        timeout: Duration,
+    ) -> Result<Output, CmdError> {
+        (**self).output_capped(program, args, ctx, limit, timeout)
+    }
 }
 
 /// Real implementation of [`CmdRunner`] backed by [`std::process::Command`].
-#[derive(Debug, Default, Clone, Copy)]
-pub struct RealCmdRunner;
+///
+/// Known programs (`rauc`, `rc-status`, `barebox-state`) run from the
+/// absolute paths of its [`CommandsConfig`], all others are looked up in its
+/// `PATH`. Every command runs with an empty environment except `PATH` and
+/// `LC_ALL=C`, and with stdin closed.
+#[derive(Debug, Default, Clone)]
+pub struct RealCmdRunner {
+    commands: CommandsConfig,
+}
+
+impl RealCmdRunner {
+    /// Creates a runner for the program paths and `PATH` in `commands`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if a program path is not absolute.
+    pub fn new(commands: &CommandsConfig) -> Result<Self, String> {
+        for (key, path) in [
+            ("rauc", &commands.rauc),
+            ("rc_status", &commands.rc_status),
+            ("barebox_state", &commands.barebox_state),
+        ] {
+            if !path.is_absolute() {
+                return Err(format!(
+                    "commands.{key} must be an absolute path, got `{}`",
+                    path.display()
+                ));
+            }
+        }
+        Ok(Self {
+            commands: commands.clone(),
+        })
+    }
+
+    /// Builds the [`Command`] for `program` in the hardened environment.
+    fn command(&self, program: &str, args: &[&str]) -> Command {
+        let commands = &self.commands;
+        let path = match program {
+            "rauc" => commands.rauc.clone(),
+            "rc-status" => commands.rc_status.clone(),
+            "barebox-state" => commands.barebox_state.clone(),
+            other => PathBuf::from(other),
+        };
+        log::debug!(
+            "exec: PATH={} LC_ALL=C {} {}",
+            commands.path,
+            path.display(),
+            args.join(" ")
+        );
+
+        let mut cmd = Command::new(path);
+        cmd.args(args)
+            .env_clear()
+            .env("PATH", &commands.path)
+            .env("LC_ALL", "C")
+            .stdin(Stdio::null());
+        cmd
+    }
+}
 
 impl CmdRunner for RealCmdRunner {
     /// Executes the command on the host system and returns its [`ExitStatus`].
@@ -33,37 +273,231 @@ impl CmdRunner for RealCmdRunner {
     /// Returns `Err(String)` if [`Command::status`] fails (i.e., the process
     /// could not be started). The error message includes `ctx`.
     fn status(&self, program: &str, args: &[&str], ctx: &str) -> Result<ExitStatus, String> {
-        Command::new(program)
-            .args(args)
+        self.command(program, args)
             .status()
             .map_err(|e| format!("failed to execute `{ctx}`: {e}"))
     }
+
+    /// Executes the command and captures its output.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if [`Command::output`] fails. The error message
+    /// includes `ctx`.
+    fn output(&self, program: &str, args: &[&str], ctx: &str) -> Result<Output, String> {
+        self.command(program, args)
+            .output()
+            .map_err(|e| format!("failed to execute `{ctx}`: {e}"))
+    }
+
+    /// Executes the command in its own process group with piped output,
+    /// reading both streams concurrently and discarding everything beyond
+    /// `limit`. After `timeout` the whole process group is killed; so is
+    /// anything left of it once the command exits, as background processes
+    /// holding the pipes would otherwise keep the readers waiting.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CmdError::Spawn`] if the process cannot be started or waited
+    /// for, or reading its output fails (the message includes `ctx`), and
+    /// [`CmdError::TimedOut`] if it had to be killed.
+    fn output_capped(
+        &self,
+        program: &str,
+        args: &[&str],
+        ctx: &str,
+        limit: usize,
+        timeout: Duration,
+    ) -> Result<Output, CmdError> {
+        let mut cmd = self.command(program, args);
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| CmdError::Spawn(format!("failed to execute `{ctx}`: {e}")))?;
+
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let (stdout, stderr, status) = std::thread::scope(|s| {
+            let out = s.spawn(|| read_capped(stdout, limit, true));
+            let err = s.spawn(|| read_capped(stderr, limit, false));
+            let status = wait_until(&mut child, Instant::now() + timeout);
+            let join = |h: std::thread::ScopedJoinHandle<'_, _>| {
+                h.join().unwrap_or_else(|_| Ok(Vec::new()))
+            };
+            (join(out), join(err), status)
+        });
+        let spawn_err = |what: &str, e: std::io::Error| {
+            CmdError::Spawn(format!("failed to {what} `{ctx}`: {e}"))
+        };
+
+        let Some(status) = status.map_err(|e| spawn_err("wait for", e))? else {
+            log::warn!("`{ctx}` did not finish within {timeout:?}, killed");
+            return Err(CmdError::timed_out(program, args, timeout));
+        };
+        Ok(Output {
+            status,
+            stdout: stdout.map_err(|e| spawn_err("read output of", e))?,
+            stderr: stderr.map_err(|e| spawn_err("read output of", e))?,
+        })
+    }
+}
+
+/// Waits for `child` until `deadline`; then kills its process group, reaps
+/// it and returns `Ok(None)`. If `child` exits in time, the rest of its
+/// process group is killed as well.
+fn wait_until(child: &mut Child, deadline: Instant) -> std::io::Result<Option<ExitStatus>> {
+    let mut delay = Duration::from_millis(1);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            // Usually the group is gone already (ESRCH)
+            let _ = kill_group(child);
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        std::thread::sleep(delay.min(deadline - now));
+        delay = (delay * 2).min(Duration::from_millis(50));
+    }
+
+    if let Err(e) = kill_group(child) {
+        log::warn!("failed to kill timed out command: {e}");
+        child.kill()?;
+    }
+    child.wait()?;
+    Ok(None)
+}
+
+/// Kills the process group led by `child` (only `child` itself on other
+/// platforms).
+fn kill_group(child: &mut Child) -> std::io::Result<()> {
+    #[cfg(unix)]
+    return rustix::process::kill_process_group(
+        rustix::process::Pid::from_child(child),
+        rustix::process::Signal::KILL,
+    )
+    .map_err(std::io::Error::from);
+    #[cfg(not(unix))]
+    child.kill()
+}
+
+/// Reads `reader` to the end, keeping its first (`head`) or last `limit` bytes.
+fn read_capped(reader: Option<impl Read>, limit: usize, head: bool) -> std::io::Result<Vec<u8>> {
+    let Some(mut reader) = reader else {
+        return Ok(Vec::new());
+    };
+    let mut kept = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if head {
+            let room = limit.saturating_sub(kept.len());
+            kept.extend_from_slice(&buf[..n.min(room)]);
+        } else {
+            kept.extend_from_slice(&buf[..n]);
+            if kept.len() > limit.saturating_mul(2) {
+                kept.drain(..kept.len() - limit);
+            }
+        }
+    }
+    if !head {
+        let excess = kept.len().saturating_sub(limit);
+        kept.drain(..excess);
+    }
+    Ok(kept)
+}
+
+/// Runs a command with capped output capture and `timeout`, and requires a
+/// successful exit.
+///
+/// # Errors
+///
+/// Returns [`CmdError::Spawn`] if the command could not be started,
+/// [`CmdError::TimedOut`] if it did not finish in time and
+/// [`CmdError::Failed`] if it exited unsuccessfully.
+pub fn check_output(
+    runner: &dyn CmdRunner,
+    program: &str,
+    args: &[&str],
+    ctx: &str,
+    timeout: Duration,
+) -> Result<Output, CmdError> {
+    let output = runner.output_capped(program, args, ctx, OUTPUT_LIMIT, timeout)?;
+    if !output.status.success() {
+        return Err(CmdError::failed(
+            program,
+            args,
+            output.status,
+            &output.stderr,
+        ));
+    }
+    Ok(output)
 }
 
 /// Runs a command and treats any non-success exit status as an error.
 ///
-/// This is useful when a non-zero exit code should abort the current operation.
+/// The command is killed after [`DEFAULT_TIMEOUT`]. This is useful when a
+/// non-zero exit code should abort the current operation.
 ///
 /// # Errors
 ///
 /// Returns `Err(String)` if:
-/// - the command could not be started (propagates the error from [`CmdRunner::status`])
-/// - the command ran but exited unsuccessfully (non-zero exit status)
-///
-/// In both cases, the error message includes the provided `ctx`.
+/// - the command could not be started (the message includes the provided `ctx`)
+/// - the command ran but exited unsuccessfully; the message names the command
+///   line, exit code or signal and the tail of its stderr (see [`CmdError`])
 pub fn run_checked(
     runner: &dyn CmdRunner,
     program: &str,
     args: &[&str],
     ctx: &str,
 ) -> Result<(), String> {
-    let status = runner.status(program, args, ctx)?;
-    if !status.success() {
-        return Err(format!("`{ctx}` exited with {status}"));
-    }
+    check_output(runner, program, args, ctx, DEFAULT_TIMEOUT)?;
     Ok(())
 }
 
+/// Runs a command, requires a successful exit status and returns its stdout.
+///
+/// The command is killed after [`DEFAULT_TIMEOUT`].
+///
+/// # Errors
+///
+/// Returns `Err(String)` if:
+/// - the command could not be started, timed out or exited unsuccessfully
+///   (see [`run_checked`])
+/// - stdout is not valid UTF-8
+pub fn run_stdout(
+    runner: &dyn CmdRunner,
+    program: &str,
+    args: &[&str],
+    ctx: &str,
+) -> Result<String, String> {
+    run_stdout_timeout(runner, program, args, ctx, DEFAULT_TIMEOUT)
+}
+
+/// Like [`run_stdout`], with an explicit `timeout`.
+///
+/// # Errors
+///
+/// Same as [`run_stdout`].
+pub fn run_stdout_timeout(
+    runner: &dyn CmdRunner,
+    program: &str,
+    args: &[&str],
+    ctx: &str,
+    timeout: Duration,
+) -> Result<String, String> {
+    let output = check_output(runner, program, args, ctx, timeout)?;
+    String::from_utf8(output.stdout).map_err(|_| format!("`{ctx}` output was not valid UTF-8"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,7 +505,7 @@ mod tests {
     #[test]
     #[cfg(unix)]
     fn real_runner_status_ok_on_true() {
-        let r = RealCmdRunner;
+        let r = RealCmdRunner::default();
         let st = r.status("true", &[], "true").unwrap();
         assert!(st.success());
     }
@@ -79,7 +513,7 @@ mod tests {
     #[test]
     #[cfg(unix)]
     fn real_runner_status_ok_on_false_but_not_success() {
-        let r = RealCmdRunner;
+        let r = RealCmdRunner::default();
         let st = r.status("false", &[], "false").unwrap();
         assert!(!st.success());
     }
@@ -87,7 +521,7 @@ mod tests {
     #[test]
     #[cfg(unix)]
     fn run_checked_ok_when_command_succeeds() {
-        let r = RealCmdRunner;
+        let r = RealCmdRunner::default();
         let res = run_checked(&r, "true", &[], "true");
         assert!(res.is_ok());
     }
@@ -95,7 +529,7 @@ mod tests {
     #[test]
     #[cfg(unix)]
     fn run_checked_err_when_command_fails() {
-        let r = RealCmdRunner;
+        let r = RealCmdRunner::default();
         let err = run_checked(&r, "false", &[], "false").unwrap_err();
         assert!(err.contains("`false` exited with"));
     }
@@ -103,9 +537,153 @@ mod tests {
     #[test]
     #[cfg(unix)]
     fn run_checked_err_when_command_missing() {
-        let r = RealCmdRunner;
+        let r = RealCmdRunner::default();
         let err =
             run_checked(&r, "__definitely_not_a_real_command__", &[], "missing cmd").unwrap_err();
         assert!(err.contains("failed to execute `missing cmd`"));
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_stdout_returns_captured_stdout() {
+        let r = RealCmdRunner::default();
+        let out = run_stdout(&r, "echo", &["hello"], "echo").unwrap();
+        assert_eq!(out, "hello\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_stdout_err_when_command_fails() {
+        let r = RealCmdRunner::default();
+        let err = run_stdout(&r, "false", &[], "false").unwrap_err();
+        assert!(err.contains("`false` exited with"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn check_output_reports_code_and_stderr_tail() {
+        let r = RealCmdRunner::default();
+        let err = check_output(
+            &r,
+            "sh",
+            &["-c", "echo oops >&2; exit 3"],
+            "sh",
+            DEFAULT_TIMEOUT,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            CmdError::Failed {
+                program: "sh".into(),
+                args: vec!["-c".into(), "echo oops >&2; exit 3".into()],
+                code: Some(3),
+                signal: None,
+                stderr_tail: "oops".into(),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "`sh -c echo oops >&2; exit 3` exited with code 3: oops"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn check_output_reports_signal() {
+        let r = RealCmdRunner::default();
+        let err = check_output(&r, "sh", &["-c", "kill -9 $$"], "sh", DEFAULT_TIMEOUT).unwrap_err();
+        assert!(matches!(
+            err,
+            CmdError::Failed {
+                code: None,
+                signal: Some(9),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn output_capped_keeps_stdout_head_and_stderr_tail() {
+        let r = RealCmdRunner::default();
+        let script = "seq 1 20000; seq 1 20000 >&2";
+        let out = r
+            .output_capped("sh", &["-c", script], "sh", 16, DEFAULT_TIMEOUT)
+            .unwrap();
+        assert!(out.status.success());
+        assert_eq!(out.stdout, b"1\n2\n3\n4\n5\n6\n7\n8\n");
+        assert!(out.stderr.ends_with(b"19999\n20000\n"));
+        assert_eq!(out.stderr.len(), 16);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn timeout_kills_the_whole_process_group() {
+        let r = RealCmdRunner::default();
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("survived");
+        // the background sleep keeps stdout open; both must be killed
+        let script = format!("(sleep 2; touch {}) & sleep 10", marker.display());
+        let start = Instant::now();
+
+        let err =
+            check_output(&r, "sh", &["-c", &script], "sh", Duration::from_millis(200)).unwrap_err();
+
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(matches!(err, CmdError::TimedOut { .. }));
+        assert!(err.to_string().ends_with("timed out after 200ms"));
+        std::thread::sleep(Duration::from_millis(2500));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn background_process_holding_the_pipes_does_not_block() {
+        let r = RealCmdRunner::default();
+        let start = Instant::now();
+
+        let out = run_stdout(&r, "sh", &["-c", "sleep 10 & echo started"], "sh").unwrap();
+
+        assert_eq!(out, "started\n");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn environment_is_scrubbed() {
+        let r = RealCmdRunner::default();
+        let out = run_stdout(&r, "env", &[], "env").unwrap();
+        let mut vars: Vec<&str> = out.lines().collect();
+        vars.sort_unstable();
+        assert_eq!(vars, ["LC_ALL=C", "PATH=/usr/sbin:/usr/bin:/sbin:/bin"]);
+
+        let out = run_stdout(&r, "sh", &["-c", "cat; echo done"], "sh").unwrap();
+        assert_eq!(out, "done\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn known_programs_run_from_the_configured_paths() {
+        let commands = CommandsConfig {
+            rauc: "/bin/echo".into(),
+            ..Default::default()
+        };
+        let r = RealCmdRunner::new(&commands).unwrap();
+        assert_eq!(
+            run_stdout(&r, "rauc", &["status"], "rauc").unwrap(),
+            "status\n"
+        );
+    }
+
+    #[test]
+    fn relative_program_paths_are_rejected() {
+        let commands = CommandsConfig {
+            rauc: "bin/rauc".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            RealCmdRunner::new(&commands).unwrap_err(),
+            "commands.rauc must be an absolute path, got `bin/rauc`"
+        );
+    }
 }
@@ -1,7 +1,9 @@
-use serde::Deserialize;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct HealthConfig {
     #[serde(default)]
     pub required_services: Vec<String>,
@@ -14,6 +16,481 @@ pub struct HealthConfig {
 
     #[serde(default = "default_ignore_prefixes")]
     pub ignore_prefixes: Vec<String>,
+
+    /// Maximale Wartezeit, bis alle Dienste laufen.
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+
+    /// Abstand zwischen zwei `rc-status`-Abfragen.
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+
+    /// Backend, über das der Slot markiert wird.
+    #[serde(default)]
+    pub marker_backend: MarkerBackend,
+
+    /// Nichts verändern: Markieren und Zustandsänderungen werden nur
+    /// protokolliert, die Prüfung läuft vollständig (wie `--dry-run`).
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Verzeichnis für persistente Zustandsdaten (z. B. letzte Entscheidung).
+    #[serde(default = "default_state_dir")]
+    pub state_dir: PathBuf,
+
+    /// Ob Update-Boots (Trial-Boots) von normalen Boots unterschieden werden.
+    #[serde(default)]
+    pub trial_boot_detection: TrialBootDetection,
+
+    /// Verhalten bei normalen Boots (nur wirksam mit `trial_boot_detection = "auto"`).
+    #[serde(default)]
+    pub normal_boot_policy: NormalBootPolicy,
+
+    /// Geprüfte Runlevels (z. B. `sysinit`, `boot`, `default` oder eigene).
+    /// Dienste der dynamischen Runlevels (`hotplugged`, `needed/wanted`,
+    /// `manual`) zählen nur für ausdrücklich genannte Dienste.
+    #[serde(default = "default_runlevels")]
+    pub runlevels: Vec<String>,
+
+    /// Welche Dienste geprüft werden: nur `required_services` oder alle der
+    /// geprüften Runlevels.
+    #[serde(default)]
+    pub check_mode: CheckMode,
+
+    /// Dienst, als der `rauc-health` selbst läuft (`RC_SVCNAME`); er steht
+    /// während der Prüfung auf `starting` und wird daher nie geprüft. Wird
+    /// nicht aus der Konfiguration gelesen.
+    #[serde(skip)]
+    pub caller: Option<String>,
+
+    /// Herkunft der Einzelwerte (Schlüsselpfad → Datei, `variant …`,
+    /// `env …` oder `default`) für Logmeldungen; setzt `check-openrc` beim
+    /// Laden. Wird nicht aus der Konfiguration gelesen.
+    #[serde(skip)]
+    pub sources: BTreeMap<String, String>,
+
+    /// Umgang mit einem Boot, bei dem alle erforderlichen, aber nicht alle
+    /// optionalen Dienste laufen.
+    #[serde(default)]
+    pub degraded_policy: DegradedPolicy,
+
+    /// Quelle der Dienstzustände: `rc-status` oder direkt der Zustandsbaum
+    /// von `OpenRC`.
+    #[serde(default)]
+    pub service_source: ServiceSource,
+
+    /// Root-Dateisystem, unter dem `service_source = "state-tree"`
+    /// `run/openrc` und `etc/runlevels` liest.
+    #[serde(default = "default_openrc_root")]
+    pub openrc_root: PathBuf,
+
+    /// Reaktion auf die einzelnen Dienstzustände von `rc-status`.
+    #[serde(default)]
+    pub service_states: ServiceStatesConfig,
+
+    /// Nach einem Rollback den verlassenen (neu installierten) Slot als BAD markieren.
+    #[serde(default)]
+    pub mark_abandoned_slot_bad: bool,
+
+    /// RAUC-`system.conf`, aus der Bootloader-Backends die Bootnamen der
+    /// Slots lesen.
+    #[serde(default = "default_rauc_system_conf")]
+    pub rauc_system_conf: PathBuf,
+
+    /// Wiederholungen, solange RAUC beim Markieren nicht erreichbar ist
+    /// (`cli`- und `dbus`-Backend).
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Absolute Pfade der aufgerufenen Programme und Suchpfad für alle
+    /// übrigen.
+    #[serde(default)]
+    pub commands: CommandsConfig,
+
+    /// Zustandsdatei für `marker_backend = "file"`.
+    #[serde(default = "default_marker_file")]
+    pub marker_file: PathBuf,
+
+    /// Einstellungen für `marker_backend = "uboot"`.
+    #[serde(default)]
+    pub uboot: UbootConfig,
+
+    /// Einstellungen für `marker_backend = "grub"`.
+    #[serde(default)]
+    pub grub: GrubConfig,
+
+    /// Einstellungen für `marker_backend = "barebox"`.
+    #[serde(default)]
+    pub barebox: BareboxConfig,
+
+    /// Bedingte Abschnitte `[[variant]]`, die je nach Board, RAUC-Compatible,
+    /// gebootetem Slot oder Bundle-Version über die Basis gemischt werden
+    /// (siehe [`crate::variant`]).
+    #[serde(default, rename = "variant")]
+    pub variants: Vec<VariantConfig>,
+}
+
+/// Wiederholungsstrategie für Markier-Aufrufe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Maximale Anzahl Versuche (inklusive des ersten).
+    #[serde(default = "default_retry_attempts")]
+    pub attempts: u32,
+
+    /// Wartezeit vor der ersten Wiederholung; verdoppelt sich danach.
+    #[serde(default = "default_retry_backoff_ms")]
+    pub backoff_ms: u64,
+
+    /// Obergrenze für die gesamte Zeit aller Versuche.
+    #[serde(default = "default_retry_max_total_seconds")]
+    pub max_total_seconds: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: default_retry_attempts(),
+            backoff_ms: default_retry_backoff_ms(),
+            max_total_seconds: default_retry_max_total_seconds(),
+        }
+    }
+}
+
+/// Programme, die `rauc-health` ausführt.
+///
+/// Alle Befehle laufen mit geleerter Umgebung, festem `PATH`, `LC_ALL=C` und
+/// geschlossenem stdin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandsConfig {
+    /// Absoluter Pfad von `rauc`.
+    #[serde(default = "default_rauc_command")]
+    pub rauc: PathBuf,
+
+    /// Absoluter Pfad von `rc-status`.
+    #[serde(default = "default_rc_status_command")]
+    pub rc_status: PathBuf,
+
+    /// Absoluter Pfad von `barebox-state`.
+    #[serde(default = "default_barebox_state_command")]
+    pub barebox_state: PathBuf,
+
+    /// `PATH` der gestarteten Prozesse.
+    #[serde(default = "default_command_path")]
+    pub path: String,
+}
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        Self {
+            rauc: default_rauc_command(),
+            rc_status: default_rc_status_command(),
+            barebox_state: default_barebox_state_command(),
+            path: default_command_path(),
+        }
+    }
+}
+
+/// Einstellungen für das U-Boot-Backend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UbootConfig {
+    /// `fw_env.config` mit Lage und Größe der (redundanten) Umgebung.
+    #[serde(default = "default_fw_env_config")]
+    pub fw_env_config: PathBuf,
+
+    /// Bootversuche, die ein GOOD oder aktiv markierter Slot erhält
+    /// (`BOOT_<x>_LEFT`).
+    #[serde(default = "default_boot_attempts")]
+    pub boot_attempts: u32,
+}
+
+impl Default for UbootConfig {
+    fn default() -> Self {
+        Self {
+            fw_env_config: default_fw_env_config(),
+            boot_attempts: default_boot_attempts(),
+        }
+    }
+}
+
+/// Einstellungen für das GRUB-Backend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GrubConfig {
+    /// Pfad des `grubenv`-Blocks mit `ORDER`, `<x>_OK` und `<x>_TRY`.
+    #[serde(default = "default_grubenv")]
+    pub grubenv: PathBuf,
+}
+
+impl Default for GrubConfig {
+    fn default() -> Self {
+        Self {
+            grubenv: default_grubenv(),
+        }
+    }
+}
+
+/// Einstellungen für das barebox-Backend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BareboxConfig {
+    /// Bootversuche, die ein GOOD oder aktiv markierter Slot erhält
+    /// (`bootstate.<x>.remaining_attempts`).
+    #[serde(default = "default_boot_attempts")]
+    pub boot_attempts: u32,
+}
+
+impl Default for BareboxConfig {
+    fn default() -> Self {
+        Self {
+            boot_attempts: default_boot_attempts(),
+        }
+    }
+}
+
+/// Bedingter Abschnitt `[[variant]]`.
+///
+/// Treffen alle Bedingungen unter `match` zu, wird `config` beim Laden wie
+/// ein Drop-in über die Basis gemischt; Abschnitte gelten in der Reihenfolge
+/// ihres Auftretens.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariantConfig {
+    /// Name des Abschnitts für Log und `explain`.
+    pub name: String,
+
+    /// Bedingungen, die alle zutreffen müssen.
+    #[serde(rename = "match")]
+    pub when: VariantMatch,
+
+    /// Einstellungen des Abschnitts, aufgebaut wie ein Drop-in.
+    #[serde(default)]
+    pub config: toml::Table,
+}
+
+/// Bedingungen eines `[[variant]]`-Abschnitts.
+///
+/// Werte dürfen mit `*` enden, um nur den Anfang zu vergleichen.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariantMatch {
+    /// Ein Eintrag aus `/proc/device-tree/compatible`.
+    #[serde(default)]
+    pub device_tree_compatible: Option<String>,
+
+    /// `/proc/device-tree/model`.
+    #[serde(default)]
+    pub device_tree_model: Option<String>,
+
+    /// `compatible` aus dem `[system]`-Abschnitt der RAUC-`system.conf`.
+    #[serde(default)]
+    pub rauc_compatible: Option<String>,
+
+    /// Gebooteter Slot, als RAUC-Slot-Name (`rootfs.1`) oder Bootname (`B`).
+    #[serde(default)]
+    pub slot: Option<String>,
+
+    /// Version des Bundles im gebooteten Slot (laut `rauc status`).
+    #[serde(default)]
+    pub bundle_version: Option<String>,
+}
+
+impl VariantMatch {
+    /// Gibt an, ob keine einzige Bedingung gesetzt ist.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Verfügbare Backends zum Markieren des RAUC-Slots.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MarkerBackend {
+    /// Ruft `rauc status mark-good|mark-bad` als Prozess auf.
+    #[default]
+    Cli,
+    /// Spricht den RAUC-Dienst direkt über D-Bus an; fällt auf die CLI
+    /// zurück, wenn der Bus nicht erreichbar ist.
+    Dbus,
+    /// Schreibt `BOOT_ORDER`/`BOOT_<x>_LEFT` direkt in die U-Boot-Umgebung.
+    Uboot,
+    /// Schreibt `ORDER`/`<x>_OK`/`<x>_TRY` direkt in den GRUB-`grubenv`-Block.
+    Grub,
+    /// Setzt die bootchooser-Variablen über das `barebox-state`-Werkzeug.
+    Barebox,
+    /// Fake-Backend: speichert die Slot-Zustände in `marker_file`
+    /// (Entwicklungsrechner, Integrationstests).
+    File,
+    /// Markiert nichts, protokolliert nur, was markiert würde.
+    DryRun,
+}
+
+/// Erkennung des ersten Boots nach einem Update.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrialBootDetection {
+    /// Jeder Boot wird wie ein Trial-Boot streng geprüft.
+    #[default]
+    Off,
+    /// Trial-Boot anhand des Pending-Update-Eintrags im `state_dir` bzw. des
+    /// RAUC-Slot-Status erkennen.
+    Auto,
+}
+
+/// Umfang der Dienstprüfung.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckMode {
+    /// Nur die Dienste aus `required_services` müssen laufen.
+    #[default]
+    Required,
+    /// Jeder Dienst der geprüften Runlevels muss laufen, außer er ist
+    /// optional oder passt zu `ignore_exact`/`ignore_prefixes`; fehlende
+    /// `required_services` gelten weiterhin als Fehler.
+    All,
+}
+
+/// Reaktion auf den Zustand eines geprüften Dienstes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StatePolicy {
+    /// Als noch nicht bereit werten und bis zum Timeout weiter abfragen.
+    Wait,
+    /// Sofort als ausgefallen werten, ohne den Timeout abzuwarten.
+    Fail,
+    /// Als laufend werten.
+    #[serde(rename = "ok")]
+    Healthy,
+}
+
+/// Reaktion je Dienstzustand (`[service_states]`).
+///
+/// Standard: nur `started` ist in Ordnung, `crashed` und `failed` führen
+/// sofort zur Entscheidung, alle übrigen Zustände werden abgewartet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceStatesConfig {
+    /// Dienst läuft.
+    #[serde(default = "state_healthy")]
+    pub started: StatePolicy,
+
+    /// Dienst startet gerade.
+    #[serde(default = "state_wait")]
+    pub starting: StatePolicy,
+
+    /// Dienst wird gerade beendet.
+    #[serde(default = "state_wait")]
+    pub stopping: StatePolicy,
+
+    /// Dienst ist (noch) nicht gestartet.
+    #[serde(default = "state_wait")]
+    pub stopped: StatePolicy,
+
+    /// Der Prozess des Dienstes ist abgestürzt.
+    #[serde(default = "state_fail")]
+    pub crashed: StatePolicy,
+
+    /// Der Start ist fehlgeschlagen.
+    #[serde(default = "state_fail")]
+    pub failed: StatePolicy,
+
+    /// Dienst ist gestartet, aber inaktiv.
+    #[serde(default = "state_wait")]
+    pub inactive: StatePolicy,
+
+    /// Start ist eingeplant.
+    #[serde(default = "state_wait")]
+    pub scheduled: StatePolicy,
+
+    /// Von Hotplug gestartet.
+    #[serde(default = "state_wait")]
+    pub hotplugged: StatePolicy,
+
+    /// Jeder andere Zustand; wird protokolliert.
+    #[serde(default = "state_wait")]
+    pub unknown: StatePolicy,
+}
+
+impl Default for ServiceStatesConfig {
+    fn default() -> Self {
+        Self {
+            started: state_healthy(),
+            starting: state_wait(),
+            stopping: state_wait(),
+            stopped: state_wait(),
+            crashed: state_fail(),
+            failed: state_fail(),
+            inactive: state_wait(),
+            scheduled: state_wait(),
+            hotplugged: state_wait(),
+            unknown: state_wait(),
+        }
+    }
+}
+
+fn state_healthy() -> StatePolicy {
+    StatePolicy::Healthy
+}
+
+fn state_wait() -> StatePolicy {
+    StatePolicy::Wait
+}
+
+fn state_fail() -> StatePolicy {
+    StatePolicy::Fail
+}
+
+/// Woher `check-openrc` die Dienstzustände bezieht.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServiceSource {
+    /// Bei jeder Abfrage `rc-status --nocolor --all` ausführen.
+    #[default]
+    RcStatus,
+    /// `/run/openrc` und `/etc/runlevels` direkt lesen, ohne Prozess zu
+    /// starten.
+    StateTree,
+}
+
+/// Umgang mit ausgefallenen optionalen Diensten (Ergebnis „degraded“).
+///
+/// `check-openrc` endet in jedem Fall mit einem eigenen Exit-Code, sofern
+/// nicht BAD markiert wird.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DegradedPolicy {
+    /// Slot trotzdem GOOD markieren.
+    #[default]
+    MarkGood,
+    /// Slot nicht markieren.
+    Unmarked,
+    /// Slot wie bei ausgefallenen Pflichtdiensten BAD markieren (bei
+    /// normalen Boots nur mit `normal_boot_policy = "strict"`).
+    MarkBad,
+}
+
+/// Health-Gate für normale Boots (kein Update seit der letzten Bestätigung).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NormalBootPolicy {
+    /// Weder prüfen noch markieren.
+    Skip,
+    /// Prüfen und bei Erfolg GOOD markieren, aber nie BAD markieren.
+    #[default]
+    Lenient,
+    /// Wie ein Trial-Boot: GOOD oder BAD markieren.
+    Strict,
+}
+
+fn default_runlevels() -> Vec<String> {
+    vec![crate::openrc::RUNLEVEL.into()]
+}
+
+fn default_openrc_root() -> PathBuf {
+    PathBuf::from("/")
 }
 
 fn default_ignore_exact() -> Vec<String> {
@@ -24,15 +501,400 @@ fn default_ignore_prefixes() -> Vec<String> {
     vec!["getty.".into(), "agetty.".into()]
 }
 
-/// Parsed eine `HealthConfig` aus einem TOML-formatierten String.
+fn default_timeout_seconds() -> u64 {
+    30
+}
+
+fn default_poll_interval_ms() -> u64 {
+    500
+}
+
+fn default_state_dir() -> PathBuf {
+    PathBuf::from("/var/lib/rauc-health")
+}
+
+fn default_retry_attempts() -> u32 {
+    5
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    500
+}
+
+fn default_retry_max_total_seconds() -> u64 {
+    30
+}
+
+fn default_rauc_command() -> PathBuf {
+    PathBuf::from("/usr/bin/rauc")
+}
+
+fn default_rc_status_command() -> PathBuf {
+    PathBuf::from("/bin/rc-status")
+}
+
+fn default_barebox_state_command() -> PathBuf {
+    PathBuf::from("/usr/bin/barebox-state")
+}
+
+fn default_command_path() -> String {
+    "/usr/sbin:/usr/bin:/sbin:/bin".into()
+}
+
+fn default_marker_file() -> PathBuf {
+    PathBuf::from("/var/lib/rauc-health/slots.toml")
+}
+
+fn default_rauc_system_conf() -> PathBuf {
+    PathBuf::from("/etc/rauc/system.conf")
+}
+
+fn default_fw_env_config() -> PathBuf {
+    PathBuf::from("/etc/fw_env.config")
+}
+
+fn default_grubenv() -> PathBuf {
+    PathBuf::from("/boot/grub/grubenv")
+}
+
+fn default_boot_attempts() -> u32 {
+    3
+}
+
+/// Parsed eine `HealthConfig` aus einem TOML-formatierten String und prüft
+/// sie mit [`validate`]; Warnungen werden nur protokolliert.
 ///
 /// # Errors
 ///
 /// Diese Funktion gibt einen Fehler zurück, wenn:
-/// * Der String kein gültiges TOML-Format aufweist.
-/// * Erforderliche Felder (wie `runlevel`) fehlen oder ungültige Typen enthalten.
+/// * Der String kein gültiges TOML-Format aufweist, unbekannte Schlüssel
+///   enthält oder Werte den falschen Typ haben; die Meldung enthält die
+///   Diagnose des TOML-Parsers mit Zeile und Spalte.
+/// * [`validate`] Fehler findet (eine Zeile pro Fehler).
 pub fn from_toml_str(s: &str) -> Result<HealthConfig, String> {
-    toml::from_str::<HealthConfig>(s).map_err(|_e| "invalid config TOML".to_string())
+    let cfg = toml::from_str::<HealthConfig>(s).map_err(|e| format!("invalid config TOML: {e}"))?;
+    let (errors, warnings): (Vec<_>, Vec<_>) = validate(&cfg, s)
+        .into_iter()
+        .partition(|i| i.severity == Severity::Error);
+    for w in &warnings {
+        log::warn!("config: {w}");
+    }
+    if errors.is_empty() {
+        Ok(cfg)
+    } else {
+        Err(errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+/// Schwere eines [`ConfigIssue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    /// Die Konfiguration wird nicht geladen.
+    Error,
+    /// Die Konfiguration funktioniert, tut aber vermutlich nicht das Gewollte.
+    Warning,
+}
+
+/// Problem in einer Konfiguration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigIssue {
+    /// Schwere des Problems.
+    pub severity: Severity,
+    /// Beschreibung des Problems.
+    pub message: String,
+    /// Datei, in der das Problem steht, falls bekannt.
+    pub file: Option<String>,
+    /// Zeile (1-basiert), falls bekannt.
+    pub line: Option<usize>,
+    /// Spalte (1-basiert), falls bekannt.
+    pub column: Option<usize>,
+}
+
+impl ConfigIssue {
+    /// Erzeugt ein Problem an der Fundstelle `position`.
+    #[must_use]
+    pub fn new(severity: Severity, message: String, position: Position) -> Self {
+        Self {
+            severity,
+            message,
+            file: position.file,
+            line: position.line,
+            column: position.column,
+        }
+    }
+
+    /// Erzeugt ein Problem an der Byte-Position `span` in `source`.
+    #[must_use]
+    pub fn at(
+        severity: Severity,
+        message: String,
+        source: &str,
+        span: Option<std::ops::Range<usize>>,
+    ) -> Self {
+        Self::new(severity, message, Position::in_source(None, source, span))
+    }
+
+    /// Wandelt einen Fehler des TOML-Parsers in ein Problem mit Position um.
+    #[must_use]
+    pub fn from_toml_error(e: &toml::de::Error, source: &str) -> Self {
+        Self::at(
+            Severity::Error,
+            e.message().trim().to_string(),
+            source,
+            e.span(),
+        )
+    }
+}
+
+impl std::fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}: ")?;
+        }
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "line {line}, column {column}: {}", self.message)
+            }
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Fundstelle in einer Konfigurationsdatei; `None` heißt unbekannt.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Position {
+    /// Datei.
+    pub file: Option<String>,
+    /// Zeile (1-basiert).
+    pub line: Option<usize>,
+    /// Spalte (1-basiert).
+    pub column: Option<usize>,
+}
+
+impl Position {
+    /// Fundstelle der Byte-Position `span` in `source`, dem Text von `file`.
+    #[must_use]
+    pub fn in_source(
+        file: Option<String>,
+        source: &str,
+        span: Option<std::ops::Range<usize>>,
+    ) -> Self {
+        let (line, column) = span.map_or((None, None), |span| {
+            let (line, column) = line_col(source, span.start);
+            (Some(line), Some(column))
+        });
+        Self { file, line, column }
+    }
+}
+
+/// Findet die Einträge einer Konfiguration in den Dateien, aus denen sie
+/// stammen (siehe [`validate`]).
+///
+/// Ein `str` ist der TOML-Text einer einzelnen Datei ohne bekannten Namen;
+/// [`crate::dropin::LayeredConfig`] kennt für jeden Eintrag die Datei, aus
+/// der er stammt.
+pub trait Locate {
+    /// Fundstelle des `nth` Eintrags `value` der Liste `key`.
+    fn entry(&self, key: &str, value: &str, nth: usize) -> Position;
+
+    /// Fundstelle des Namens des `nth` Abschnitts `[[variant]]` namens `name`.
+    fn variant(&self, name: &str, nth: usize) -> Position;
+}
+
+impl Locate for str {
+    fn entry(&self, key: &str, value: &str, nth: usize) -> Position {
+        Position::in_source(None, self, locate(self, key, value, nth))
+    }
+
+    fn variant(&self, name: &str, nth: usize) -> Position {
+        Position::in_source(None, self, locate_variant(self, name, nth))
+    }
+}
+
+/// Zeile und Spalte (1-basiert) des Byte-Offsets `offset` in `source`.
+pub(crate) fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit_once('\n')
+        .map_or(before, |(_, l)| l)
+        .chars()
+        .count()
+        + 1;
+    (line, column)
+}
+
+/// Byte-Position des `nth` Eintrags `value` in der Liste `key` von `source`.
+///
+/// In einem Drop-in zählen auch die Einträge von `replace` und `extend`
+/// (in dieser Reihenfolge), wenn `key` eine Tabelle ist.
+#[must_use]
+pub fn locate(source: &str, key: &str, value: &str, nth: usize) -> Option<std::ops::Range<usize>> {
+    let doc = toml_edit::ImDocument::parse(source).ok()?;
+    let item = doc.get(key)?;
+    let lists: Vec<&toml_edit::Array> = if let Some(list) = item.as_array() {
+        vec![list]
+    } else {
+        let ops = item.as_table_like()?;
+        ["replace", "extend"]
+            .iter()
+            .filter_map(|op| ops.get(op)?.as_array())
+            .collect()
+    };
+    let span = lists
+        .into_iter()
+        .flat_map(toml_edit::Array::iter)
+        .filter(|v| v.as_str() == Some(value))
+        .nth(nth)?
+        .span();
+    span
+}
+
+/// Prüft die Dienst- und Runlevel-Listen von `cfg` auf inhaltliche Fehler:
+/// leere Namen, doppelte Einträge, eine leere `runlevels`-Liste und
+/// Überschneidungen von `required_services` und `optional_services`.
+/// Erforderliche Dienste, die durch `ignore_exact` oder `ignore_prefixes`
+/// verdeckt werden, sind ebenfalls Fehler. Abschnitte `[[variant]]`
+/// brauchen einen eindeutigen Namen und mindestens eine Bedingung.
+///
+/// `source` findet die Einträge in den Dateien, aus denen `cfg` stammt
+/// (siehe [`Locate`]); für eine einzelne Datei ist das ihr TOML-Text, ein
+/// leerer Text, wenn er unbekannt ist.
+#[must_use]
+pub fn validate<L: Locate + ?Sized>(cfg: &HealthConfig, source: &L) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |severity, message: String, key: &str, value: &str, nth: usize| {
+        issues.push(ConfigIssue::new(
+            severity,
+            message,
+            source.entry(key, value, nth),
+        ));
+    };
+
+    for (key, list) in [
+        ("required_services", &cfg.required_services),
+        ("optional_services", &cfg.optional_services),
+        ("ignore_exact", &cfg.ignore_exact),
+        ("ignore_prefixes", &cfg.ignore_prefixes),
+        ("runlevels", &cfg.runlevels),
+    ] {
+        for (i, name) in list.iter().enumerate() {
+            let nth = list[..i].iter().filter(|n| *n == name).count();
+            if name.trim().is_empty() {
+                issue(
+                    Severity::Error,
+                    format!("{key}: empty name"),
+                    key,
+                    name,
+                    nth,
+                );
+            } else if nth > 0 {
+                issue(
+                    Severity::Error,
+                    format!("{key}: duplicate `{name}`"),
+                    key,
+                    name,
+                    nth,
+                );
+            }
+        }
+    }
+    if cfg.runlevels.is_empty() {
+        issue(
+            Severity::Error,
+            "runlevels: no runlevel to check".to_string(),
+            "runlevels",
+            "",
+            0,
+        );
+    }
+
+    for name in &cfg.required_services {
+        if name.trim().is_empty() {
+            continue;
+        }
+        if cfg.ignore_exact.contains(name) {
+            issue(
+                Severity::Error,
+                format!("required service `{name}` is ignored by ignore_exact"),
+                "required_services",
+                name,
+                0,
+            );
+        } else if let Some(prefix) = cfg
+            .ignore_prefixes
+            .iter()
+            .find(|p| !p.is_empty() && name.starts_with(p.as_str()))
+        {
+            issue(
+                Severity::Error,
+                format!("required service `{name}` is ignored by ignore_prefixes `{prefix}`"),
+                "required_services",
+                name,
+                0,
+            );
+        }
+        if cfg.optional_services.contains(name) {
+            issue(
+                Severity::Error,
+                format!("`{name}` is listed in both required_services and optional_services"),
+                "optional_services",
+                name,
+                0,
+            );
+        }
+    }
+
+    issues.extend(validate_variants(cfg, source));
+    issues
+}
+
+/// Prüft die `[[variant]]`-Abschnitte von `cfg` (siehe [`validate`]).
+fn validate_variants<L: Locate + ?Sized>(cfg: &HealthConfig, source: &L) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    for (i, variant) in cfg.variants.iter().enumerate() {
+        let name = &variant.name;
+        let nth = cfg.variants[..i].iter().filter(|v| v.name == *name).count();
+        let position = source.variant(name, nth);
+        let mut error = |message: String| {
+            issues.push(ConfigIssue::new(Severity::Error, message, position.clone()));
+        };
+        if name.trim().is_empty() {
+            error("variant: empty name".to_string());
+        } else if cfg.variants[..i].iter().any(|v| v.name == *name) {
+            error(format!("variant: duplicate `{name}`"));
+        }
+        if variant.when.is_empty() {
+            error(format!("variant `{name}`: no match conditions"));
+        }
+        if variant.config.contains_key("variant") {
+            error(format!(
+                "variant `{name}`: variant sections cannot be nested"
+            ));
+        }
+    }
+    issues
+}
+
+/// Byte-Bereich des `name` im `nth`-ten `[[variant]]`-Abschnitt namens
+/// `name` in `source`.
+#[must_use]
+pub fn locate_variant(source: &str, name: &str, nth: usize) -> Option<std::ops::Range<usize>> {
+    let doc = toml_edit::ImDocument::parse(source).ok()?;
+    let span = doc
+        .get("variant")?
+        .as_array_of_tables()?
+        .iter()
+        .filter_map(|t| t.get("name"))
+        .filter(|n| n.as_str() == Some(name))
+        .nth(nth)?
+        .span();
+    span
 }
 
 /// Lädt die Konfiguration direkt aus einer Datei vom Dateisystem.
@@ -51,6 +913,172 @@ pub fn from_file(path: &Path) -> Result<HealthConfig, String> {
     Ok(cfg)
 }
 
+/// Präfix der Umgebungsvariablen, die Einzelwerte der Konfiguration
+/// überschreiben (siehe [`env_var_name`]).
+pub const ENV_PREFIX: &str = "RAUC_HEALTH_";
+
+/// Tabellen, die sich nicht über die Umgebung überschreiben lassen: Die
+/// Programme und der `PATH` der als root gestarteten Befehle stammen nur aus
+/// den Konfigurationsdateien.
+pub const ENV_PROTECTED: [&str; 1] = ["commands"];
+
+/// Standard-Orte der Konfigurationsdatei, wenn kein `--config` angegeben ist.
+/// Die erste vorhandene Datei gewinnt.
+pub const SEARCH_PATH: &[&str] = &[
+    "/etc/rauc-health.toml",
+    "/usr/lib/rauc-health/rauc-health.toml",
+];
+
+/// Sucht die Konfigurationsdatei in [`SEARCH_PATH`]; `exists` prüft, ob eine
+/// Datei vorhanden ist.
+#[must_use]
+pub fn find_config(exists: &dyn Fn(&Path) -> bool) -> Option<PathBuf> {
+    SEARCH_PATH.iter().map(PathBuf::from).find(|p| exists(p))
+}
+
+/// Wie [`find_config`], aber unterhalb des Root-Dateisystems `root` (z. B.
+/// eines Images im Build).
+#[must_use]
+pub fn find_config_in(root: &Path) -> Option<PathBuf> {
+    SEARCH_PATH
+        .iter()
+        .map(|p| root.join(p.trim_start_matches('/')))
+        .find(|p| p.is_file())
+}
+
+/// Name der Umgebungsvariablen für den Schlüssel `path`, z. B.
+/// `retry.attempts` → `RAUC_HEALTH_RETRY_ATTEMPTS`.
+#[must_use]
+pub fn env_var_name(path: &str) -> String {
+    format!("{ENV_PREFIX}{}", path.replace('.', "_").to_uppercase())
+}
+
+/// Überschreibt jeden skalaren Wert in `table` (der serialisierten
+/// Konfiguration), für den eine Umgebungsvariable nach [`env_var_name`]
+/// gesetzt ist; `env` liefert den Wert einer Variablen. Listen und die
+/// Tabellen aus [`ENV_PROTECTED`] werden nicht überschrieben; gesetzte
+/// Variablen für Letztere werden mit einer Warnung ignoriert.
+///
+/// # Returns
+///
+/// Die überschriebenen Schlüssel zusammen mit ihrer Variablen.
+///
+/// # Errors
+///
+/// Gibt einen Fehler zurück, wenn ein Wert nicht zum Typ des Schlüssels passt
+/// (z. B. keine Zahl oder ein unbekanntes Backend).
+pub fn apply_env_table(
+    table: &mut toml::Table,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, String)>, String> {
+    let mut applied = Vec::new();
+    override_scalars(table, "", env, &mut applied)?;
+
+    // Werte wie Backend-Namen prüft erst die Deserialisierung.
+    if !applied.is_empty() {
+        if let Err(e) = HealthConfig::deserialize(toml::Value::Table(table.clone())) {
+            let vars: Vec<&str> = applied.iter().map(|(_, v)| v.as_str()).collect();
+            return Err(format!("{}: {}", vars.join(", "), e.message()));
+        }
+    }
+    Ok(applied)
+}
+
+fn override_scalars(
+    table: &mut toml::Table,
+    prefix: &str,
+    env: &dyn Fn(&str) -> Option<String>,
+    applied: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    for (key, value) in table.iter_mut() {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        if let toml::Value::Table(t) = value {
+            override_scalars(t, &path, env, applied)?;
+            continue;
+        }
+        let var = env_var_name(&path);
+        let Some(raw) = env(&var) else { continue };
+        if ENV_PROTECTED
+            .iter()
+            .any(|t| path.starts_with(&format!("{t}.")))
+        {
+            log::warn!("{var} ignored: {path} can only be set in the config file");
+            continue;
+        }
+        let new = match value {
+            toml::Value::Integer(_) => raw
+                .trim()
+                .parse::<i64>()
+                .map(toml::Value::Integer)
+                .map_err(|_| format!("{var}: expected a number, got `{raw}`"))?,
+            toml::Value::Boolean(_) => match raw.trim() {
+                "true" | "1" | "yes" => toml::Value::Boolean(true),
+                "false" | "0" | "no" => toml::Value::Boolean(false),
+                _ => return Err(format!("{var}: expected true or false, got `{raw}`")),
+            },
+            toml::Value::String(_) => toml::Value::String(raw),
+            _ => continue,
+        };
+        log::debug!("{var} overrides {path} = {new}");
+        *value = new;
+        applied.push((path, var));
+    }
+    Ok(())
+}
+
+/// Lädt die Konfiguration aus `path` (ohne Pfad aus [`SEARCH_PATH`], sonst
+/// die Defaults), mischt die Drop-ins und die zum System passenden
+/// `[[variant]]`-Abschnitte darüber (siehe [`crate::dropin`]) und wendet
+/// zuletzt die `RAUC_HEALTH_*`-Umgebungsvariablen an.
+///
+/// Prüft die wirksame Konfiguration keinen einzigen Dienst (nur
+/// `check_mode = "required"`), wird gewarnt: dann gilt jeder Boot als
+/// gesund.
+///
+/// # Errors
+///
+/// Gibt die Fehler von [`from_file`] und [`crate::dropin::load_layered`]
+/// zurück.
+pub fn load(path: Option<&Path>) -> Result<HealthConfig, String> {
+    load_layered(path).map(|layered| layered.config)
+}
+
+/// Wie [`load`], liefert aber zusätzlich Dateien und Herkunft jedes Werts
+/// (siehe [`crate::dropin::LayeredConfig`]).
+///
+/// # Errors
+///
+/// Siehe [`load`].
+pub fn load_layered(path: Option<&Path>) -> Result<crate::dropin::LayeredConfig, String> {
+    let layered = crate::dropin::load_layered(
+        path,
+        &|k| std::env::var(k).ok(),
+        &crate::variant::SystemFacts::detect,
+    )?;
+    let cfg = &layered.config;
+    if cfg.check_mode == CheckMode::Required && checked_services(cfg).is_empty() {
+        log::warn!(
+            "effective config has no required services to check; every boot will be judged healthy"
+        );
+    }
+    Ok(layered)
+}
+
+/// Die Pflichtdienste, die nach Abzug der Ignorier-Regeln tatsächlich
+/// geprüft werden.
+#[must_use]
+pub fn checked_services(cfg: &HealthConfig) -> Vec<&str> {
+    cfg.required_services
+        .iter()
+        .filter(|s| !crate::openrc::is_ignored_service(s, cfg))
+        .map(String::as_str)
+        .collect()
+}
+
 impl Default for HealthConfig {
     fn default() -> Self {
         Self {
@@ -58,6 +1086,30 @@ impl Default for HealthConfig {
             optional_services: vec![],
             ignore_exact: default_ignore_exact(),
             ignore_prefixes: default_ignore_prefixes(),
+            timeout_seconds: default_timeout_seconds(),
+            poll_interval_ms: default_poll_interval_ms(),
+            marker_backend: MarkerBackend::default(),
+            dry_run: false,
+            state_dir: default_state_dir(),
+            trial_boot_detection: TrialBootDetection::default(),
+            normal_boot_policy: NormalBootPolicy::default(),
+            runlevels: default_runlevels(),
+            check_mode: CheckMode::default(),
+            caller: None,
+            sources: BTreeMap::new(),
+            degraded_policy: DegradedPolicy::default(),
+            service_source: ServiceSource::default(),
+            openrc_root: default_openrc_root(),
+            service_states: ServiceStatesConfig::default(),
+            mark_abandoned_slot_bad: false,
+            rauc_system_conf: default_rauc_system_conf(),
+            retry: RetryConfig::default(),
+            commands: CommandsConfig::default(),
+            marker_file: default_marker_file(),
+            uboot: UbootConfig::default(),
+            grub: GrubConfig::default(),
+            barebox: BareboxConfig::default(),
+            variants: Vec::new(),
         }
     }
 }
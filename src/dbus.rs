@@ -0,0 +1,1065 @@
+// Datei: src/dbus.rs
+//! Minimal D-Bus client speaking the wire protocol over a Unix socket.
+//!
+//! Only the parts needed by rauc-health are implemented: `EXTERNAL`
+//! authentication, method calls with replies, and enough server-side
+//! primitives (name requests, replying to calls) to host a fake service in
+//! tests. Unix file descriptor passing is not supported.
+
+use std::fmt;
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+/// Default address of the system bus if `DBUS_SYSTEM_BUS_ADDRESS` is unset.
+pub const DEFAULT_SYSTEM_BUS_ADDRESS: &str = "unix:path=/var/run/dbus/system_bus_socket";
+
+/// Default timeout for a single method call.
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(25);
+
+const BUS_NAME: &str = "org.freedesktop.DBus";
+const BUS_PATH: &str = "/org/freedesktop/DBus";
+const PROPERTIES_IFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// Upper bound for a single message (the specification allows 128 MiB).
+const MAX_MESSAGE_LEN: usize = 128 * 1024 * 1024;
+/// Upper bound for nesting of containers inside a message.
+const MAX_DEPTH: usize = 64;
+
+/// Error returned by D-Bus operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The bus could not be reached or the connection broke down.
+    Transport(String),
+    /// The peer answered with a D-Bus error reply.
+    Remote {
+        /// Error name, e.g. `org.freedesktop.DBus.Error.ServiceUnknown`.
+        name: String,
+        /// Human readable error message sent along with the reply.
+        message: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(msg) => write!(f, "D-Bus transport error: {msg}"),
+            Self::Remote { name, message } => write!(f, "{name}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn transport(ctx: &str, e: impl fmt::Display) -> Error {
+    Error::Transport(format!("{ctx}: {e}"))
+}
+
+/// A single D-Bus value together with its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    Int16(i16),
+    Uint16(u16),
+    Int32(i32),
+    Uint32(u32),
+    Int64(i64),
+    Uint64(u64),
+    Double(f64),
+    Str(String),
+    ObjectPath(String),
+    Signature(String),
+    Variant(Box<Value>),
+    /// Array with the signature of its element type and the elements.
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+}
+
+impl Value {
+    /// Returns the D-Bus type signature of this value.
+    #[must_use]
+    pub fn signature(&self) -> String {
+        match self {
+            Self::Byte(_) => "y".into(),
+            Self::Bool(_) => "b".into(),
+            Self::Int16(_) => "n".into(),
+            Self::Uint16(_) => "q".into(),
+            Self::Int32(_) => "i".into(),
+            Self::Uint32(_) => "u".into(),
+            Self::Int64(_) => "x".into(),
+            Self::Uint64(_) => "t".into(),
+            Self::Double(_) => "d".into(),
+            Self::Str(_) => "s".into(),
+            Self::ObjectPath(_) => "o".into(),
+            Self::Signature(_) => "g".into(),
+            Self::Variant(_) => "v".into(),
+            Self::Array(elem, _) => format!("a{elem}"),
+            Self::Struct(fields) => {
+                let inner: String = fields.iter().map(Value::signature).collect();
+                format!("({inner})")
+            }
+            Self::DictEntry(k, v) => format!("{{{}{}}}", k.signature(), v.signature()),
+        }
+    }
+
+    /// Returns the contained string for `s`, `o` and `g` values.
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) | Self::ObjectPath(s) | Self::Signature(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the contained integer for all unsigned/signed integer types
+    /// that fit into a `u64`.
+    #[must_use]
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::Byte(v) => Some(u64::from(v)),
+            Self::Uint16(v) => Some(u64::from(v)),
+            Self::Uint32(v) => Some(u64::from(v)),
+            Self::Uint64(v) => Some(v),
+            Self::Int16(v) => u64::try_from(v).ok(),
+            Self::Int32(v) => u64::try_from(v).ok(),
+            Self::Int64(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    /// Removes any number of variant wrappers.
+    #[must_use]
+    pub fn unwrap_variant(&self) -> &Value {
+        let mut v = self;
+        while let Self::Variant(inner) = v {
+            v = inner;
+        }
+        v
+    }
+}
+
+/// Kind of a D-Bus message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    MethodCall,
+    MethodReturn,
+    Error,
+    Signal,
+}
+
+impl MessageType {
+    fn code(self) -> u8 {
+        match self {
+            Self::MethodCall => 1,
+            Self::MethodReturn => 2,
+            Self::Error => 3,
+            Self::Signal => 4,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::MethodCall),
+            2 => Some(Self::MethodReturn),
+            3 => Some(Self::Error),
+            4 => Some(Self::Signal),
+            _ => None,
+        }
+    }
+}
+
+/// A decoded (or to-be-encoded) D-Bus message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub msg_type: MessageType,
+    pub serial: u32,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    fn empty(msg_type: MessageType) -> Self {
+        Self {
+            msg_type,
+            serial: 0,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            body: Vec::new(),
+        }
+    }
+
+    /// Creates a method call message.
+    #[must_use]
+    pub fn method_call(
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        body: Vec<Value>,
+    ) -> Self {
+        Self {
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            destination: Some(destination.to_string()),
+            body,
+            ..Self::empty(MessageType::MethodCall)
+        }
+    }
+
+    /// Creates a successful reply to `call`.
+    #[must_use]
+    pub fn method_return(call: &Message, body: Vec<Value>) -> Self {
+        Self {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            body,
+            ..Self::empty(MessageType::MethodReturn)
+        }
+    }
+
+    /// Creates an error reply to `call`.
+    #[must_use]
+    pub fn error_reply(call: &Message, name: &str, message: &str) -> Self {
+        Self {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            error_name: Some(name.to_string()),
+            body: vec![Value::Str(message.to_string())],
+            ..Self::empty(MessageType::Error)
+        }
+    }
+
+    fn body_signature(&self) -> String {
+        self.body.iter().map(Value::signature).collect()
+    }
+
+    /// Encodes the message in little endian wire format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is too large to be encoded.
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut body = Writer::default();
+        for v in &self.body {
+            body.value(v)?;
+        }
+
+        let mut fields = Vec::new();
+        let mut field = |code: u8, v: Value| {
+            fields.push(Value::Struct(vec![
+                Value::Byte(code),
+                Value::Variant(Box::new(v)),
+            ]));
+        };
+        if let Some(p) = &self.path {
+            field(1, Value::ObjectPath(p.clone()));
+        }
+        if let Some(i) = &self.interface {
+            field(2, Value::Str(i.clone()));
+        }
+        if let Some(m) = &self.member {
+            field(3, Value::Str(m.clone()));
+        }
+        if let Some(e) = &self.error_name {
+            field(4, Value::Str(e.clone()));
+        }
+        if let Some(r) = self.reply_serial {
+            field(5, Value::Uint32(r));
+        }
+        if let Some(d) = &self.destination {
+            field(6, Value::Str(d.clone()));
+        }
+        if let Some(s) = &self.sender {
+            field(7, Value::Str(s.clone()));
+        }
+        let sig = self.body_signature();
+        if !sig.is_empty() {
+            field(8, Value::Signature(sig));
+        }
+
+        let body_len = u32::try_from(body.buf.len())
+            .map_err(|_| Error::Transport("message body too large".into()))?;
+
+        let mut w = Writer::default();
+        w.buf.extend_from_slice(&[b'l', self.msg_type.code(), 0, 1]);
+        w.buf.extend_from_slice(&body_len.to_le_bytes());
+        w.buf.extend_from_slice(&self.serial.to_le_bytes());
+        w.value(&Value::Array("(yv)".into(), fields))?;
+        w.align(8);
+        w.buf.extend_from_slice(&body.buf);
+        Ok(w.buf)
+    }
+
+    /// Decodes a complete message from `buf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message is malformed. Both byte orders are
+    /// accepted, as every sender uses its native one.
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let big_endian = big_endian(buf)?;
+        let mut r = Reader {
+            buf,
+            pos: 0,
+            big_endian,
+        };
+        r.u8()?;
+        let msg_type = MessageType::from_code(r.u8()?)
+            .ok_or_else(|| Error::Transport("unknown message type".into()))?;
+        let _flags = r.u8()?;
+        let _version = r.u8()?;
+        let body_len = r.u32()? as usize;
+        let serial = r.u32()?;
+
+        let mut msg = Message::empty(msg_type);
+        msg.serial = serial;
+        let mut signature = String::new();
+
+        let Value::Array(_, fields) = r.value("a(yv)", 0)? else {
+            return Err(Error::Transport("malformed header".into()));
+        };
+        for f in fields {
+            let Value::Struct(parts) = f else { continue };
+            let [Value::Byte(code), v] = parts.as_slice() else {
+                continue;
+            };
+            let v = v.unwrap_variant();
+            match code {
+                1 => msg.path = v.as_str().map(str::to_string),
+                2 => msg.interface = v.as_str().map(str::to_string),
+                3 => msg.member = v.as_str().map(str::to_string),
+                4 => msg.error_name = v.as_str().map(str::to_string),
+                5 => msg.reply_serial = v.as_u64().and_then(|s| u32::try_from(s).ok()),
+                6 => msg.destination = v.as_str().map(str::to_string),
+                7 => msg.sender = v.as_str().map(str::to_string),
+                8 => signature = v.as_str().unwrap_or_default().to_string(),
+                _ => {}
+            }
+        }
+        r.align(8)?;
+
+        let body_end = r
+            .pos
+            .checked_add(body_len)
+            .filter(|end| *end <= buf.len())
+            .ok_or_else(|| Error::Transport("truncated message body".into()))?;
+        let mut rest = signature.as_str();
+        while !rest.is_empty() {
+            let (ty, tail) = split_type(rest)?;
+            msg.body.push(r.value(ty, 0)?);
+            rest = tail;
+        }
+        if r.pos != body_end {
+            return Err(Error::Transport("message body length mismatch".into()));
+        }
+        Ok(msg)
+    }
+}
+
+/// Returns the first complete type of `sig` and the remaining signature.
+fn split_type(sig: &str) -> Result<(&str, &str), Error> {
+    let bytes = sig.as_bytes();
+    let mut depth = 0usize;
+    for (i, c) in bytes.iter().enumerate() {
+        match c {
+            b'a' => continue,
+            b'(' | b'{' => depth += 1,
+            b')' | b'}' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| Error::Transport(format!("invalid signature `{sig}`")))?;
+            }
+            _ => {}
+        }
+        if depth == 0 {
+            return Ok((&sig[..=i], &sig[i + 1..]));
+        }
+    }
+    Err(Error::Transport(format!("invalid signature `{sig}`")))
+}
+
+fn alignment(sig: &str) -> usize {
+    match sig.as_bytes().first() {
+        Some(b'n' | b'q') => 2,
+        Some(b'b' | b'i' | b'u' | b's' | b'o' | b'a' | b'h') => 4,
+        Some(b'x' | b't' | b'd' | b'(' | b'{') => 8,
+        _ => 1,
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, n: usize) {
+        while !self.buf.len().is_multiple_of(n) {
+            self.buf.push(0);
+        }
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn string(&mut self, s: &str) -> Result<(), Error> {
+        let len = u32::try_from(s.len()).map_err(|_| Error::Transport("string too long".into()))?;
+        self.u32(len);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+        Ok(())
+    }
+
+    fn signature(&mut self, s: &str) -> Result<(), Error> {
+        let len =
+            u8::try_from(s.len()).map_err(|_| Error::Transport("signature too long".into()))?;
+        self.buf.push(len);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+        Ok(())
+    }
+
+    fn value(&mut self, v: &Value) -> Result<(), Error> {
+        match v {
+            Value::Byte(b) => self.buf.push(*b),
+            Value::Bool(b) => self.u32(u32::from(*b)),
+            Value::Int16(n) => {
+                self.align(2);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Uint16(n) => {
+                self.align(2);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Int32(n) => {
+                self.align(4);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Uint32(n) => self.u32(*n),
+            Value::Int64(n) => {
+                self.align(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Uint64(n) => {
+                self.align(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Double(d) => {
+                self.align(8);
+                self.buf.extend_from_slice(&d.to_le_bytes());
+            }
+            Value::Str(s) | Value::ObjectPath(s) => self.string(s)?,
+            Value::Signature(s) => self.signature(s)?,
+            Value::Variant(inner) => {
+                self.signature(&inner.signature())?;
+                self.value(inner)?;
+            }
+            Value::Array(elem, items) => {
+                self.align(4);
+                let len_pos = self.buf.len();
+                self.buf.extend_from_slice(&[0; 4]);
+                self.align(alignment(elem));
+                let start = self.buf.len();
+                for item in items {
+                    self.value(item)?;
+                }
+                let len = u32::try_from(self.buf.len() - start)
+                    .map_err(|_| Error::Transport("array too long".into()))?;
+                self.buf[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.align(8);
+                for f in fields {
+                    self.value(f)?;
+                }
+            }
+            Value::DictEntry(k, val) => {
+                self.align(8);
+                self.value(k)?;
+                self.value(val)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Byte order flag of the message in `buf`: `true` for `B`, `false` for `l`.
+fn big_endian(buf: &[u8]) -> Result<bool, Error> {
+    match buf.first() {
+        Some(b'l') => Ok(false),
+        Some(b'B') => Ok(true),
+        Some(_) => Err(Error::Transport("invalid byte order flag".into())),
+        None => Err(Error::Transport("truncated message".into())),
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    /// Numbers are stored big endian and reversed by [`Reader::array`].
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], Error> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| Error::Transport("truncated message".into()))?;
+        let out = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn align(&mut self, n: usize) -> Result<(), Error> {
+        let pad = (n - self.pos % n) % n;
+        self.take(pad).map(|_| ())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        self.align(N.min(8))?;
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        if self.big_endian {
+            out.reverse();
+        }
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        self.array::<4>().map(u32::from_le_bytes)
+    }
+
+    fn text(&mut self, len: usize) -> Result<String, Error> {
+        let bytes = self.take(len)?;
+        let s = std::str::from_utf8(bytes)
+            .map_err(|_| Error::Transport("string is not valid UTF-8".into()))?
+            .to_string();
+        if self.u8()? != 0 {
+            return Err(Error::Transport("string is not NUL terminated".into()));
+        }
+        Ok(s)
+    }
+
+    fn value(&mut self, sig: &str, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::Transport("message nesting too deep".into()));
+        }
+        let Some(&c) = sig.as_bytes().first() else {
+            return Err(Error::Transport("empty signature".into()));
+        };
+        let v = match c {
+            b'y' => Value::Byte(self.u8()?),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' => Value::Int16(self.array::<2>().map(i16::from_le_bytes)?),
+            b'q' => Value::Uint16(self.array::<2>().map(u16::from_le_bytes)?),
+            b'i' => Value::Int32(self.array::<4>().map(i32::from_le_bytes)?),
+            b'u' | b'h' => Value::Uint32(self.u32()?),
+            b'x' => Value::Int64(self.array::<8>().map(i64::from_le_bytes)?),
+            b't' => Value::Uint64(self.array::<8>().map(u64::from_le_bytes)?),
+            b'd' => Value::Double(self.array::<8>().map(f64::from_le_bytes)?),
+            b's' => {
+                let len = self.u32()? as usize;
+                Value::Str(self.text(len)?)
+            }
+            b'o' => {
+                let len = self.u32()? as usize;
+                Value::ObjectPath(self.text(len)?)
+            }
+            b'g' => {
+                let len = usize::from(self.u8()?);
+                Value::Signature(self.text(len)?)
+            }
+            b'v' => {
+                let len = usize::from(self.u8()?);
+                let inner_sig = self.text(len)?;
+                let (ty, rest) = split_type(&inner_sig)?;
+                if !rest.is_empty() {
+                    return Err(Error::Transport("variant with multiple types".into()));
+                }
+                Value::Variant(Box::new(self.value(ty, depth + 1)?))
+            }
+            b'a' => {
+                let len = self.u32()? as usize;
+                let elem = &sig[1..];
+                self.align(alignment(elem))?;
+                let end = self
+                    .pos
+                    .checked_add(len)
+                    .filter(|end| *end <= self.buf.len())
+                    .ok_or_else(|| Error::Transport("truncated array".into()))?;
+                let mut items = Vec::new();
+                while self.pos < end {
+                    items.push(self.value(elem, depth + 1)?);
+                }
+                Value::Array(elem.to_string(), items)
+            }
+            b'(' => {
+                self.align(8)?;
+                let mut rest = &sig[1..sig.len() - 1];
+                let mut fields = Vec::new();
+                while !rest.is_empty() {
+                    let (ty, tail) = split_type(rest)?;
+                    fields.push(self.value(ty, depth + 1)?);
+                    rest = tail;
+                }
+                Value::Struct(fields)
+            }
+            b'{' => {
+                self.align(8)?;
+                let inner = &sig[1..sig.len() - 1];
+                let (kt, vt) = split_type(inner)?;
+                let k = self.value(kt, depth + 1)?;
+                let v = self.value(vt, depth + 1)?;
+                Value::DictEntry(Box::new(k), Box::new(v))
+            }
+            other => {
+                return Err(Error::Transport(format!(
+                    "unsupported type code `{}`",
+                    char::from(other)
+                )))
+            }
+        };
+        Ok(v)
+    }
+}
+
+/// Parses a D-Bus server address list and returns the socket targets.
+fn parse_address(address: &str) -> Vec<SocketTarget> {
+    let mut out = Vec::new();
+    for entry in address.split(';').filter(|e| !e.is_empty()) {
+        let Some(params) = entry.strip_prefix("unix:") else {
+            continue;
+        };
+        for kv in params.split(',') {
+            let Some((k, v)) = kv.split_once('=') else {
+                continue;
+            };
+            match k {
+                "path" => out.push(SocketTarget::Path(unescape(v))),
+                "abstract" => out.push(SocketTarget::Abstract(unescape(v))),
+                _ => {}
+            }
+        }
+    }
+    out
+}
+
+fn unescape(v: &str) -> String {
+    let bytes = v.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+enum SocketTarget {
+    Path(String),
+    Abstract(String),
+}
+
+impl SocketTarget {
+    fn connect(&self) -> std::io::Result<UnixStream> {
+        match self {
+            Self::Path(p) => UnixStream::connect(p),
+            #[cfg(target_os = "linux")]
+            Self::Abstract(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+                UnixStream::connect_addr(&addr)
+            }
+            #[cfg(not(target_os = "linux"))]
+            Self::Abstract(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "abstract sockets are only supported on Linux",
+            )),
+        }
+    }
+}
+
+/// An authenticated connection to a message bus.
+pub struct Connection {
+    stream: UnixStream,
+    next_serial: u32,
+    unique_name: String,
+}
+
+impl Connection {
+    /// Connects to the system bus.
+    ///
+    /// The address is taken from `DBUS_SYSTEM_BUS_ADDRESS` and falls back to
+    /// [`DEFAULT_SYSTEM_BUS_ADDRESS`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Transport`] if the bus cannot be reached or
+    /// authentication fails.
+    pub fn system() -> Result<Self, Error> {
+        let address = std::env::var("DBUS_SYSTEM_BUS_ADDRESS")
+            .unwrap_or_else(|_| DEFAULT_SYSTEM_BUS_ADDRESS.to_string());
+        Self::open(&address)
+    }
+
+    /// Connects to the bus at `address`, authenticates and registers with
+    /// the bus daemon.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Transport`] if none of the listed addresses can be
+    /// reached, or an error if authentication or the `Hello` call fails.
+    pub fn open(address: &str) -> Result<Self, Error> {
+        let targets = parse_address(address);
+        if targets.is_empty() {
+            return Err(Error::Transport(format!(
+                "no supported transport in address `{address}`"
+            )));
+        }
+
+        let mut last_err = None;
+        let mut stream = None;
+        for t in &targets {
+            match t.connect() {
+                Ok(s) => {
+                    stream = Some(s);
+                    break;
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+        let Some(stream) = stream else {
+            let e = last_err.map_or_else(|| "unknown error".to_string(), |e| e.to_string());
+            return Err(Error::Transport(format!(
+                "failed to connect to `{address}`: {e}"
+            )));
+        };
+        stream
+            .set_read_timeout(Some(DEFAULT_CALL_TIMEOUT))
+            .map_err(|e| transport("failed to set socket timeout", e))?;
+
+        let mut conn = Self {
+            stream,
+            next_serial: 1,
+            unique_name: String::new(),
+        };
+        conn.authenticate()?;
+
+        let reply = conn.call(BUS_NAME, BUS_PATH, BUS_NAME, "Hello", vec![])?;
+        conn.unique_name = reply
+            .first()
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        Ok(conn)
+    }
+
+    /// Unique bus name assigned by the daemon (e.g. `:1.42`).
+    #[must_use]
+    pub fn unique_name(&self) -> &str {
+        &self.unique_name
+    }
+
+    /// Sets the timeout for reading replies and incoming messages.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Transport`] if the socket option cannot be set.
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.stream
+            .set_read_timeout(Some(timeout))
+            .map_err(|e| transport("failed to set socket timeout", e))
+    }
+
+    fn authenticate(&mut self) -> Result<(), Error> {
+        use std::fmt::Write as _;
+
+        let uid = std::fs::metadata("/proc/self")
+            .map(|m| m.uid())
+            .map_err(|e| transport("failed to determine own uid", e))?;
+        let mut hex_uid = String::new();
+        for b in uid.to_string().bytes() {
+            let _ = write!(hex_uid, "{b:02x}");
+        }
+
+        self.stream
+            .write_all(format!("\0AUTH EXTERNAL {hex_uid}\r\n").as_bytes())
+            .map_err(|e| transport("failed to send AUTH", e))?;
+        let line = self.read_auth_line()?;
+        if !line.starts_with("OK ") {
+            return Err(Error::Transport(format!("authentication rejected: {line}")));
+        }
+        self.stream
+            .write_all(b"BEGIN\r\n")
+            .map_err(|e| transport("failed to send BEGIN", e))
+    }
+
+    fn read_auth_line(&mut self) -> Result<String, Error> {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while !line.ends_with(b"\r\n") {
+            if line.len() > 512 {
+                return Err(Error::Transport("authentication line too long".into()));
+            }
+            self.stream
+                .read_exact(&mut byte)
+                .map_err(|e| transport("failed to read auth reply", e))?;
+            line.push(byte[0]);
+        }
+        line.truncate(line.len() - 2);
+        Ok(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    /// Sends `msg` and returns the serial assigned to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Transport`] if encoding or writing fails.
+    pub fn send(&mut self, mut msg: Message) -> Result<u32, Error> {
+        let serial = self.next_serial;
+        self.next_serial = self.next_serial.wrapping_add(1).max(1);
+        msg.serial = serial;
+        let bytes = msg.encode()?;
+        self.stream
+            .write_all(&bytes)
+            .map_err(|e| transport("failed to send message", e))?;
+        Ok(serial)
+    }
+
+    /// Blocks until the next message arrives and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Transport`] on timeouts, broken connections or
+    /// malformed messages.
+    pub fn read_message(&mut self) -> Result<Message, Error> {
+        let mut fixed = [0u8; 16];
+        self.stream
+            .read_exact(&mut fixed)
+            .map_err(|e| transport("failed to read message", e))?;
+        let read_u32 = if big_endian(&fixed)? {
+            u32::from_be_bytes
+        } else {
+            u32::from_le_bytes
+        };
+        let body_len = read_u32([fixed[4], fixed[5], fixed[6], fixed[7]]) as usize;
+        let fields_len = read_u32([fixed[12], fixed[13], fixed[14], fixed[15]]) as usize;
+        let header_len = (16 + fields_len).div_ceil(8) * 8;
+        let total = header_len
+            .checked_add(body_len)
+            .filter(|t| *t <= MAX_MESSAGE_LEN)
+            .ok_or_else(|| Error::Transport("message too large".into()))?;
+
+        let mut buf = vec![0u8; total];
+        buf[..16].copy_from_slice(&fixed);
+        self.stream
+            .read_exact(&mut buf[16..])
+            .map_err(|e| transport("failed to read message", e))?;
+        Message::decode(&buf)
+    }
+
+    /// Calls `member` on `interface` at `destination`/`path` and waits for
+    /// the reply.
+    ///
+    /// Unrelated messages received while waiting (e.g. signals) are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Remote`] if the peer answers with an error and
+    /// [`Error::Transport`] for connection problems.
+    pub fn call(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        body: Vec<Value>,
+    ) -> Result<Vec<Value>, Error> {
+        let serial = self.send(Message::method_call(
+            destination,
+            path,
+            interface,
+            member,
+            body,
+        ))?;
+
+        loop {
+            let msg = self.read_message()?;
+            if msg.reply_serial != Some(serial) {
+                continue;
+            }
+            match msg.msg_type {
+                MessageType::MethodReturn => return Ok(msg.body),
+                MessageType::Error => {
+                    return Err(Error::Remote {
+                        name: msg.error_name.unwrap_or_default(),
+                        message: msg
+                            .body
+                            .first()
+                            .and_then(Value::as_str)
+                            .unwrap_or_default()
+                            .to_string(),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads the property `name` of `interface` via
+    /// `org.freedesktop.DBus.Properties.Get`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error produced by [`Connection::call`].
+    pub fn get_property(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        name: &str,
+    ) -> Result<Value, Error> {
+        let reply = self.call(
+            destination,
+            path,
+            PROPERTIES_IFACE,
+            "Get",
+            vec![Value::Str(interface.into()), Value::Str(name.into())],
+        )?;
+        reply
+            .into_iter()
+            .next()
+            .map(|v| v.unwrap_variant().clone())
+            .ok_or_else(|| Error::Transport(format!("empty reply for property `{name}`")))
+    }
+
+    /// Requests ownership of the well-known bus `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the call fails or the name could not be acquired
+    /// as primary owner.
+    pub fn request_name(&mut self, name: &str) -> Result<(), Error> {
+        // Flag 4 = DBUS_NAME_FLAG_DO_NOT_QUEUE; reply 1 = primary owner.
+        let reply = self.call(
+            BUS_NAME,
+            BUS_PATH,
+            BUS_NAME,
+            "RequestName",
+            vec![Value::Str(name.into()), Value::Uint32(4)],
+        )?;
+        match reply.first().and_then(Value::as_u64) {
+            Some(1) => Ok(()),
+            other => Err(Error::Transport(format!(
+                "failed to acquire bus name `{name}` (reply {other:?})"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_type_handles_containers() {
+        assert_eq!(split_type("a(sa{sv})s").unwrap(), ("a(sa{sv})", "s"));
+        assert_eq!(split_type("us").unwrap(), ("u", "s"));
+        assert!(split_type("a(s").is_err());
+    }
+
+    #[test]
+    fn message_roundtrip_keeps_header_and_body() {
+        let mut msg = Message::method_call(
+            "de.pengutronix.rauc",
+            "/",
+            "de.pengutronix.rauc.Installer",
+            "Mark",
+            vec![
+                Value::Str("good".into()),
+                Value::Array(
+                    "{sv}".into(),
+                    vec![Value::DictEntry(
+                        Box::new(Value::Str("count".into())),
+                        Box::new(Value::Variant(Box::new(Value::Uint64(7)))),
+                    )],
+                ),
+            ],
+        );
+        msg.serial = 3;
+
+        let decoded = Message::decode(&msg.encode().unwrap()).unwrap();
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn decode_accepts_big_endian_messages() {
+        let mut bytes = vec![b'B', 2, 0, 1, 0, 0, 0, 7, 0, 0, 0, 9, 0, 0, 0, 15];
+        // reply serial (u) = 3, signature (g) = "s"
+        bytes.extend_from_slice(&[5, 1, b'u', 0, 0, 0, 0, 3]);
+        bytes.extend_from_slice(&[8, 1, b'g', 0, 1, b's', 0, 0]);
+        bytes.extend_from_slice(&[0, 0, 0, 2, b'o', b'k', 0]);
+
+        let msg = Message::decode(&bytes).unwrap();
+        assert_eq!(msg.msg_type, MessageType::MethodReturn);
+        assert_eq!(msg.serial, 9);
+        assert_eq!(msg.reply_serial, Some(3));
+        assert_eq!(msg.body, vec![Value::Str("ok".into())]);
+
+        bytes[0] = b'x';
+        assert!(Message::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_truncated_input() {
+        let msg = Message::method_call("a.b", "/", "a.b", "C", vec![Value::Str("x".into())]);
+        let bytes = msg.encode().unwrap();
+        assert!(Message::decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn parse_address_supports_path_and_abstract() {
+        let t = parse_address("unix:abstract=/tmp/x,guid=1;unix:path=/run/a%2cb");
+        assert_eq!(t.len(), 2);
+        assert!(matches!(&t[0], SocketTarget::Abstract(n) if n == "/tmp/x"));
+        assert!(matches!(&t[1], SocketTarget::Path(p) if p == "/run/a,b"));
+    }
+
+    #[test]
+    fn unescape_keeps_multibyte_characters_after_percent() {
+        assert_eq!(unescape("/run/%äb"), "/run/%äb");
+        assert_eq!(unescape("/run/%aä"), "/run/%aä");
+        assert_eq!(unescape("/run/ä%2cö"), "/run/ä,ö");
+    }
+}
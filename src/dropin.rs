@@ -0,0 +1,662 @@
+// Datei: src/dropin.rs
+//! Layered configuration: base file plus drop-ins.
+//!
+//! The `*.toml` files in the drop-in directory next to the base file (for
+//! `/etc/rauc-health.toml` that is `/etc/rauc-health.d`) are merged onto it
+//! in lexical order of their file names:
+//!
+//! - tables are merged key by key
+//! - scalars are overridden
+//! - lists are extended; entries already present are skipped
+//!
+//! Instead of a list, a drop-in may give a table with `replace`, `remove`
+//! and/or `extend` (applied in this order):
+//!
+//! ```toml
+//! required_services = { remove = ["bluetooth"], extend = ["net.wlan0"] }
+//! ```
+//!
+//! Matching `[[variant]]` sections (see [`crate::variant`]) are merged the
+//! same way after the drop-ins, and environment overrides (`RAUC_HEALTH_*`)
+//! are applied last. Every value remembers the file or variable it came
+//! from for `print-config`.
+
+use crate::cli::PrintConfigArgs;
+use crate::config::{HealthConfig, Locate, Position, Severity};
+use crate::variant::SystemFacts;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Drop-in directory used without `--config`.
+pub const DEFAULT_DROPIN_DIR: &str = "/etc/rauc-health.d";
+
+/// Origin label of built-in defaults.
+const DEFAULT_ORIGIN: &str = "default";
+
+/// Where a value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Origin {
+    /// A scalar, set by one file.
+    Value(String),
+    /// A list; one origin per entry.
+    List(Vec<String>),
+}
+
+/// Result of merging the base file and its drop-ins.
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    /// The effective configuration.
+    pub config: HealthConfig,
+    /// Files that were merged, in merge order (base file first).
+    pub files: Vec<PathBuf>,
+    /// Facts the variant sections were matched against.
+    pub facts: SystemFacts,
+    /// Names of the variant sections that were merged, in merge order.
+    pub applied: Vec<String>,
+    value: toml::Table,
+    origins: BTreeMap<String, Origin>,
+}
+
+/// Returns the drop-in directory belonging to the base file `base`.
+#[must_use]
+pub fn dropin_dir(base: Option<&Path>) -> PathBuf {
+    base.map_or_else(
+        || PathBuf::from(DEFAULT_DROPIN_DIR),
+        |b| b.with_extension("d"),
+    )
+}
+
+/// Lists the `*.toml` files in `dir`, sorted by file name. A missing
+/// directory has no drop-ins.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the directory exists but cannot be read.
+pub fn dropin_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("failed to read {}: {e}", dir.display())),
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.extension().is_some_and(|x| x == "toml"))
+        .collect();
+    files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    Ok(files)
+}
+
+/// Loads the effective configuration: the base file, the drop-ins from its
+/// [`dropin_dir`], the variant sections matching the system and finally the
+/// `RAUC_HEALTH_*` environment overrides (see
+/// [`crate::config::apply_env_table`]).
+///
+/// `env` returns the value of a variable; `facts` describes the system for
+/// the configuration merged so far (see [`SystemFacts::detect`]).
+///
+/// Without `config` the base file is the first one found in
+/// [`crate::config::SEARCH_PATH`]; if there is none, the built-in defaults
+/// are used and drop-ins are read from [`DEFAULT_DROPIN_DIR`].
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the base file cannot be loaded (see
+/// [`crate::config::from_file`]), a drop-in cannot be read or parsed, sets an
+/// unknown key or a value of the wrong type (this includes the `config` of
+/// every variant section, matching or not), `facts` fails, an environment
+/// override is invalid, or the merged configuration fails
+/// [`crate::config::validate`].
+pub fn load_layered(
+    config: Option<&Path>,
+    env: &dyn Fn(&str) -> Option<String>,
+    facts: &dyn Fn(&HealthConfig) -> Result<SystemFacts, String>,
+) -> Result<LayeredConfig, String> {
+    if let Some(path) = config {
+        return load_from(Some(path), &dropin_dir(Some(path)), env, facts, true);
+    }
+    let found = crate::config::find_config(&|p| p.is_file());
+    match &found {
+        Some(path) => log::info!("using config file {}", path.display()),
+        None => log::warn!(
+            "no config file found in {}; using built-in defaults",
+            crate::config::SEARCH_PATH.join(", ")
+        ),
+    }
+    load_from(
+        found.as_deref(),
+        Path::new(DEFAULT_DROPIN_DIR),
+        env,
+        facts,
+        true,
+    )
+}
+
+/// Loads the configuration of the root file system `root` (e.g. an image
+/// being built) without looking at the running system.
+///
+/// Without `config` the base file is searched in
+/// [`crate::config::SEARCH_PATH`] below `root` and drop-ins are read from
+/// [`DEFAULT_DROPIN_DIR`] below `root`. Neither `RAUC_HEALTH_*` variables
+/// nor system facts are used, so no variant section applies (their `config`
+/// is still checked).
+///
+/// Unlike [`load_layered`], the merged configuration is not run through
+/// [`crate::config::validate`]; callers report those issues themselves,
+/// located with the returned [`LayeredConfig`].
+///
+/// # Errors
+///
+/// See [`load_layered`], except for the validation of the merged
+/// configuration.
+pub fn load_image(config: Option<&Path>, root: &Path) -> Result<LayeredConfig, String> {
+    let base = config
+        .map(Path::to_path_buf)
+        .or_else(|| crate::config::find_config_in(root));
+    let dir = match config {
+        Some(path) => dropin_dir(Some(path)),
+        None => root.join(DEFAULT_DROPIN_DIR.trim_start_matches('/')),
+    };
+    load_from(
+        base.as_deref(),
+        &dir,
+        &|_| None,
+        &|_| Ok(SystemFacts::default()),
+        false,
+    )
+}
+
+/// Loads `base` (or the defaults), the drop-ins in `dir`, the matching
+/// variant sections and the environment overrides. With `check` a merged
+/// configuration must pass [`crate::config::validate`].
+fn load_from(
+    base: Option<&Path>,
+    dir: &Path,
+    env: &dyn Fn(&str) -> Option<String>,
+    facts: &dyn Fn(&HealthConfig) -> Result<SystemFacts, String>,
+    check: bool,
+) -> Result<LayeredConfig, String> {
+    let (cfg, base_table, base_origin) = match base {
+        Some(path) => {
+            let cfg = crate::config::from_file(path)?;
+            let table = std::fs::read_to_string(path)
+                .ok()
+                .and_then(|s| s.parse::<toml::Table>().ok())
+                .unwrap_or_default();
+            (cfg, table, path.display().to_string())
+        }
+        None => (HealthConfig::default(), toml::Table::new(), String::new()),
+    };
+
+    let mut value = match toml::Value::try_from(&cfg) {
+        Ok(toml::Value::Table(t)) => t,
+        Ok(_) => return Err("config did not serialize to a table".into()),
+        Err(e) => return Err(format!("failed to serialize config: {e}")),
+    };
+    let mut origins = BTreeMap::new();
+    record_origins(&value, Some(&base_table), "", &base_origin, &mut origins);
+
+    let mut files: Vec<PathBuf> = base.map(Path::to_path_buf).into_iter().collect();
+    let dropins = dropin_files(dir)?;
+    let merged = !dropins.is_empty();
+    for path in dropins {
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read config file {}: {e}", path.display()))?;
+        let layer = source
+            .parse::<toml::Table>()
+            .map_err(|e| format!("failed to parse config file {}: {e}", path.display()))?;
+        let mut merge = Merge {
+            origin: path.display().to_string(),
+            source: &source,
+            origins: &mut origins,
+        };
+        merge.table(&mut value, &layer, "")?;
+        log::debug!("merged config drop-in {}", path.display());
+        files.push(path);
+    }
+
+    let cfg = if merged {
+        HealthConfig::deserialize(toml::Value::Table(value.clone()))
+            .map_err(|e| format!("invalid merged config: {e}"))?
+    } else {
+        cfg
+    };
+    let facts = facts(&cfg)?;
+    let applied = merge_variants(&cfg, &facts, &mut value, &mut origins)?;
+
+    let overridden = crate::config::apply_env_table(&mut value, env)?;
+    for (path, var) in &overridden {
+        origins.insert(path.clone(), Origin::Value(format!("env {var}")));
+    }
+    if !merged && applied.is_empty() && overridden.is_empty() {
+        return Ok(LayeredConfig {
+            config: cfg,
+            files,
+            facts,
+            applied,
+            value,
+            origins,
+        });
+    }
+
+    let config = HealthConfig::deserialize(toml::Value::Table(value.clone()))
+        .map_err(|e| format!("invalid merged config: {e}"))?;
+    let layered = LayeredConfig {
+        config,
+        files,
+        facts,
+        applied,
+        value,
+        origins,
+    };
+    if check && (merged || !layered.applied.is_empty()) {
+        let mut errors = Vec::new();
+        for issue in crate::config::validate(&layered.config, &layered) {
+            match issue.severity {
+                Severity::Error => errors.push(format!("merged config: {issue}")),
+                Severity::Warning => log::warn!("merged config: {issue}"),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+    }
+    Ok(layered)
+}
+
+impl LayeredConfig {
+    /// Position of the `nth` entry of the merged list `key` accepted by
+    /// `matches`, looked up with `find` in the file the entry came from.
+    fn find_entry(
+        &self,
+        key: &str,
+        nth: usize,
+        matches: impl Fn(&toml::Value) -> bool,
+        find: impl Fn(&str, usize) -> Option<std::ops::Range<usize>>,
+    ) -> Position {
+        let (Some(toml::Value::Array(items)), Some(Origin::List(labels))) =
+            (self.value.get(key), self.origins.get(key))
+        else {
+            return Position::default();
+        };
+        let Some(i) = (0..items.len()).filter(|&i| matches(&items[i])).nth(nth) else {
+            return Position::default();
+        };
+        let Some(label) = labels.get(i) else {
+            return Position::default();
+        };
+        // Entries of the same file before this one, i.e. its index there.
+        let in_file = items[..i]
+            .iter()
+            .zip(labels)
+            .filter(|(v, l)| matches(v) && *l == label)
+            .count();
+        // Variants, environment overrides and defaults have no file.
+        let Some(file) = self
+            .files
+            .iter()
+            .find(|f| f.display().to_string() == *label)
+        else {
+            return Position::default();
+        };
+        let source = std::fs::read_to_string(file).unwrap_or_default();
+        Position::in_source(Some(label.clone()), &source, find(&source, in_file))
+    }
+}
+
+impl Locate for LayeredConfig {
+    fn entry(&self, key: &str, value: &str, nth: usize) -> Position {
+        self.find_entry(
+            key,
+            nth,
+            |v| v.as_str() == Some(value),
+            |source, n| crate::config::locate(source, key, value, n),
+        )
+    }
+
+    fn variant(&self, name: &str, nth: usize) -> Position {
+        self.find_entry(
+            "variant",
+            nth,
+            |v| v.get("name").and_then(toml::Value::as_str) == Some(name),
+            |source, n| crate::config::locate_variant(source, name, n),
+        )
+    }
+}
+
+/// Merges the `[[variant]]` sections of `cfg` matching `facts` into `value`
+/// and returns their names in merge order.
+fn merge_variants(
+    cfg: &HealthConfig,
+    facts: &SystemFacts,
+    value: &mut toml::Table,
+    origins: &mut BTreeMap<String, Origin>,
+) -> Result<Vec<String>, String> {
+    let mut applied = Vec::new();
+    for variant in &cfg.variants {
+        let origin = format!("variant {}", variant.name);
+        if variant.config.contains_key("variant") {
+            return Err(format!("{origin}: variant sections cannot be nested"));
+        }
+        // Non-matching sections are merged too (and dropped), so mistakes
+        // show up on every board.
+        let mut merged_value = value.clone();
+        let mut merged_origins = origins.clone();
+        Merge {
+            origin,
+            source: "",
+            origins: &mut merged_origins,
+        }
+        .table(&mut merged_value, &variant.config, "")?;
+        if let Some(condition) = facts.mismatch(&variant.when) {
+            log::debug!(
+                "config variant {} skipped: {condition} differs",
+                variant.name
+            );
+            continue;
+        }
+        *value = merged_value;
+        *origins = merged_origins;
+        log::info!("applied config variant {}", variant.name);
+        applied.push(variant.name.clone());
+    }
+    Ok(applied)
+}
+
+/// Records `origin` for every value in `table`; values missing from
+/// `set_by` (the keys actually present in the file) come from the defaults.
+fn record_origins(
+    table: &toml::Table,
+    set_by: Option<&toml::Table>,
+    prefix: &str,
+    origin: &str,
+    origins: &mut BTreeMap<String, Origin>,
+) {
+    for (key, value) in table {
+        let path = join(prefix, key);
+        let present = set_by.and_then(|t| t.get(key));
+        let label = if present.is_some() {
+            origin
+        } else {
+            DEFAULT_ORIGIN
+        };
+        match value {
+            toml::Value::Table(t) => {
+                record_origins(
+                    t,
+                    present.and_then(toml::Value::as_table),
+                    &path,
+                    origin,
+                    origins,
+                );
+            }
+            toml::Value::Array(items) => {
+                origins.insert(path, Origin::List(vec![label.to_string(); items.len()]));
+            }
+            _ => {
+                origins.insert(path, Origin::Value(label.to_string()));
+            }
+        }
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+/// Merges one drop-in or variant section.
+struct Merge<'a> {
+    /// Drop-in file or variant section, recorded as origin of merged values.
+    origin: String,
+    /// TOML text of the drop-in, used to locate errors; empty if unknown.
+    source: &'a str,
+    origins: &'a mut BTreeMap<String, Origin>,
+}
+
+impl Merge<'_> {
+    fn label(&self) -> String {
+        self.origin.clone()
+    }
+
+    /// Error at the key `path` of this drop-in.
+    fn error(&self, path: &str, message: &str) -> String {
+        let location = key_span(self.source, path).map_or(String::new(), |span| {
+            let (line, column) = crate::config::line_col(self.source, span.start);
+            format!(" line {line}, column {column}:")
+        });
+        format!("{}:{location} {message}", self.origin)
+    }
+
+    fn table(
+        &mut self,
+        target: &mut toml::Table,
+        layer: &toml::Table,
+        prefix: &str,
+    ) -> Result<(), String> {
+        for (key, new) in layer {
+            let path = join(prefix, key);
+            let Some(current) = target.get_mut(key) else {
+                return Err(self.error(&path, &format!("unknown field `{path}`")));
+            };
+            match (current, new) {
+                (toml::Value::Table(t), toml::Value::Table(l)) => self.table(t, l, &path)?,
+                (toml::Value::Table(_), _) => {
+                    return Err(self.error(&path, &format!("`{path}` must be a table")));
+                }
+                (toml::Value::Array(items), toml::Value::Array(extend)) => {
+                    self.extend(items, extend, &path);
+                }
+                (toml::Value::Array(items), toml::Value::Table(ops)) => {
+                    self.list_ops(items, ops, &path)?;
+                }
+                (toml::Value::Array(_), _) => {
+                    return Err(self.error(
+                        &path,
+                        &format!("`{path}` must be a list or a table with replace/remove/extend"),
+                    ));
+                }
+                (current, new) => {
+                    if std::mem::discriminant(current) != std::mem::discriminant(new) {
+                        return Err(self.error(
+                            &path,
+                            &format!(
+                                "`{path}` expects {}, got {}",
+                                current.type_str(),
+                                new.type_str()
+                            ),
+                        ));
+                    }
+                    *current = new.clone();
+                    let label = self.label();
+                    self.origins.insert(path, Origin::Value(label));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list_origins(&mut self, path: &str) -> &mut Vec<String> {
+        let entry = self
+            .origins
+            .entry(path.to_string())
+            .or_insert_with(|| Origin::List(Vec::new()));
+        if !matches!(entry, Origin::List(_)) {
+            *entry = Origin::List(Vec::new());
+        }
+        match entry {
+            Origin::List(list) => list,
+            Origin::Value(_) => unreachable!("replaced above"),
+        }
+    }
+
+    fn extend(&mut self, items: &mut Vec<toml::Value>, extend: &[toml::Value], path: &str) {
+        let label = self.label();
+        for item in extend {
+            if !items.contains(item) {
+                items.push(item.clone());
+                self.list_origins(path).push(label.clone());
+            }
+        }
+    }
+
+    fn list_ops(
+        &mut self,
+        items: &mut Vec<toml::Value>,
+        ops: &toml::Table,
+        path: &str,
+    ) -> Result<(), String> {
+        let list = |op: &str| -> Result<Option<&Vec<toml::Value>>, String> {
+            match ops.get(op) {
+                None => Ok(None),
+                Some(toml::Value::Array(a)) => Ok(Some(a)),
+                Some(_) => Err(self.error(path, &format!("`{path}.{op}` must be a list"))),
+            }
+        };
+        let (replace, remove, extend) = (list("replace")?, list("remove")?, list("extend")?);
+        if let Some(op) = ops
+            .keys()
+            .find(|k| !matches!(k.as_str(), "replace" | "remove" | "extend"))
+        {
+            return Err(self.error(
+                path,
+                &format!("unknown list operation `{op}` (expected replace, remove or extend)"),
+            ));
+        }
+
+        if let Some(replace) = replace {
+            items.clear();
+            self.list_origins(path).clear();
+            self.extend(items, replace, path);
+        }
+        if let Some(remove) = remove {
+            let origins = self.list_origins(path);
+            let mut i = 0;
+            while i < items.len() {
+                if remove.contains(&items[i]) {
+                    items.remove(i);
+                    if i < origins.len() {
+                        origins.remove(i);
+                    }
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        if let Some(extend) = extend {
+            self.extend(items, extend, path);
+        }
+        Ok(())
+    }
+}
+
+/// Byte position of the key `path` (dotted) in `source`.
+fn key_span(source: &str, path: &str) -> Option<std::ops::Range<usize>> {
+    let doc = toml_edit::ImDocument::parse(source).ok()?;
+    let mut table: &dyn toml_edit::TableLike = doc.as_table();
+    let mut span = None;
+    for segment in path.split('.') {
+        let (key, item) = table.get_key_value(segment)?;
+        span = key.span();
+        match item.as_table_like() {
+            Some(t) => table = t,
+            None => break,
+        }
+    }
+    span
+}
+
+impl LayeredConfig {
+    /// Origin of every scalar value by dotted key path: a file, `variant
+    /// <name>`, `env <VAR>` or `default`.
+    #[must_use]
+    pub fn sources(&self) -> BTreeMap<String, String> {
+        self.origins
+            .iter()
+            .filter_map(|(path, origin)| match origin {
+                Origin::Value(o) => Some((path.clone(), o.clone())),
+                Origin::List(_) => None,
+            })
+            .collect()
+    }
+
+    /// Renders the effective configuration as TOML, each value annotated with
+    /// the file it came from.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::from("# Effective configuration, merged from:\n");
+        if self.files.is_empty() && self.applied.is_empty() {
+            out.push_str("#   (built-in defaults only)\n");
+        }
+        for f in &self.files {
+            let _ = writeln!(out, "#   {}", f.display());
+        }
+        for v in &self.applied {
+            let _ = writeln!(out, "#   variant {v}");
+        }
+        self.render_table(&mut out, &self.value, "");
+        out
+    }
+
+    fn render_table(&self, out: &mut String, table: &toml::Table, prefix: &str) {
+        let origin = |path: &str| match self.origins.get(path) {
+            Some(Origin::Value(o)) => o.clone(),
+            _ => DEFAULT_ORIGIN.to_string(),
+        };
+        for (key, value) in table {
+            let path = join(prefix, key);
+            match value {
+                toml::Value::Table(_) => {}
+                toml::Value::Array(items) => {
+                    let origins = match self.origins.get(&path) {
+                        Some(Origin::List(l)) => l.as_slice(),
+                        _ => &[],
+                    };
+                    if items.is_empty() {
+                        let _ = writeln!(out, "{key} = []");
+                        continue;
+                    }
+                    let _ = writeln!(out, "{key} = [");
+                    for (i, item) in items.iter().enumerate() {
+                        let o = origins.get(i).map_or(DEFAULT_ORIGIN, String::as_str);
+                        let _ = writeln!(out, "    {item}, # {o}");
+                    }
+                    out.push_str("]\n");
+                }
+                scalar => {
+                    let _ = writeln!(out, "{key} = {scalar} # {}", origin(&path));
+                }
+            }
+        }
+        for (key, value) in table {
+            if let toml::Value::Table(t) = value {
+                let path = join(prefix, key);
+                let _ = writeln!(out, "\n[{path}]");
+                self.render_table(out, t, &path);
+            }
+        }
+    }
+}
+
+/// Implementation of the `print-config` subcommand.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the configuration cannot be loaded.
+pub fn print_config(args: &PrintConfigArgs) -> Result<(), String> {
+    print!(
+        "{}",
+        load_layered(
+            args.config.as_deref(),
+            &|k| std::env::var(k).ok(),
+            &SystemFacts::detect
+        )?
+        .render()
+    );
+    Ok(())
+}
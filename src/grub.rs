@@ -0,0 +1,209 @@
+// Datei: src/grub.rs
+//! GRUB environment block backend.
+//!
+//! Reads and writes RAUC's `ORDER`, `<x>_OK` and `<x>_TRY` variables in
+//! `grubenv` directly. The block keeps its size (normally 1024 bytes) and
+//! `#` padding, and is replaced atomically.
+
+use crate::config::HealthConfig;
+use crate::marker::{SlotBootState, SlotMarker, SlotNames};
+use crate::rauc::{MarkState, SlotIdentifier};
+use std::path::{Path, PathBuf};
+
+/// First line of every GRUB environment block.
+pub const HEADER: &str = "# GRUB Environment Block\n";
+/// Size of a block created by `grub-editenv`.
+pub const BLOCK_SIZE: usize = 1024;
+/// Variable holding the space-separated boot order.
+pub const ORDER: &str = "ORDER";
+
+/// Parsed GRUB environment block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrubEnv {
+    vars: Vec<(String, String)>,
+    size: usize,
+}
+
+impl Default for GrubEnv {
+    fn default() -> Self {
+        Self {
+            vars: Vec::new(),
+            size: BLOCK_SIZE,
+        }
+    }
+}
+
+impl GrubEnv {
+    /// Parses a block; its size is kept for [`GrubEnv::to_block`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the header is missing, the block is not
+    /// UTF-8 or a line is not `key=value`.
+    pub fn parse(block: &[u8]) -> Result<Self, String> {
+        let s = std::str::from_utf8(block).map_err(|_| "grubenv is not valid UTF-8".to_string())?;
+        let body = s
+            .strip_prefix(HEADER)
+            .ok_or_else(|| "grubenv header missing".to_string())?;
+
+        let mut vars = Vec::new();
+        for line in body.lines() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (k, v) = line
+                .split_once('=')
+                .ok_or_else(|| format!("malformed grubenv line `{line}`"))?;
+            vars.push((k.to_string(), v.to_string()));
+        }
+        Ok(Self {
+            vars,
+            size: block.len(),
+        })
+    }
+
+    /// Returns the value of `key`.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.vars
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sets `key` to `value`, appending it if it does not exist yet.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.vars.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.vars.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Serializes the block, padded with `#` to its original size.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the variables do not fit into the block.
+    pub fn to_block(&self) -> Result<Vec<u8>, String> {
+        let mut out = String::from(HEADER);
+        for (k, v) in &self.vars {
+            out.push_str(k);
+            out.push('=');
+            out.push_str(v);
+            out.push('\n');
+        }
+        if out.len() > self.size {
+            return Err(format!(
+                "grubenv too large ({} > {} bytes)",
+                out.len(),
+                self.size
+            ));
+        }
+        let mut block = out.into_bytes();
+        block.resize(self.size, b'#');
+        Ok(block)
+    }
+}
+
+/// Reads the environment block at `path`.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the file cannot be read or parsed.
+pub fn read_grubenv(path: &Path) -> Result<GrubEnv, String> {
+    let block =
+        std::fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    GrubEnv::parse(&block).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Replaces the environment block at `path` atomically.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the block cannot be serialized or written.
+pub fn write_grubenv(path: &Path, env: &GrubEnv) -> Result<(), String> {
+    crate::state::write_atomic(path, &env.to_block()?)
+}
+
+/// Slot marker writing RAUC's GRUB boot variables.
+///
+/// - good: `<x>_OK=1`, `<x>_TRY=0`
+/// - bad: `<x>_OK=0`, `<x>_TRY=0`
+/// - active: `<x>` moved to the front of `ORDER`, `<x>_OK=1`, `<x>_TRY=0`
+#[derive(Debug, Clone)]
+pub struct GrubMarker {
+    grubenv: PathBuf,
+    names: SlotNames,
+}
+
+impl GrubMarker {
+    /// Creates a marker for the block at `grubenv`.
+    #[must_use]
+    pub fn new(grubenv: PathBuf, names: SlotNames) -> Self {
+        Self { grubenv, names }
+    }
+
+    /// Creates the marker from the `[grub]` section of `cfg`.
+    #[must_use]
+    pub fn from_config(cfg: &HealthConfig) -> Self {
+        Self::new(
+            cfg.grub.grubenv.clone(),
+            SlotNames::detect(&cfg.rauc_system_conf),
+        )
+    }
+}
+
+fn boot_order(env: &GrubEnv) -> Result<Vec<String>, String> {
+    env.get(ORDER)
+        .map(|o| o.split_whitespace().map(str::to_string).collect())
+        .ok_or_else(|| format!("{ORDER} not set in grubenv"))
+}
+
+impl SlotMarker for GrubMarker {
+    fn mark(&self, state: MarkState, slot: &SlotIdentifier) -> Result<(), String> {
+        let old = read_grubenv(&self.grubenv)?;
+        let mut env = old.clone();
+        let mut order = boot_order(&env)?;
+        let bootname = self.names.resolve(slot, &order)?;
+        let ok = format!("{bootname}_OK");
+        let try_ = format!("{bootname}_TRY");
+
+        match state {
+            MarkState::Good => env.set(&ok, "1"),
+            MarkState::Bad => env.set(&ok, "0"),
+            MarkState::Active => {
+                order.retain(|b| *b != bootname);
+                order.insert(0, bootname.clone());
+                env.set(ORDER, &order.join(" "));
+                env.set(&ok, "1");
+            }
+        }
+        env.set(&try_, "0");
+
+        if env == old {
+            log::info!("GRUB: slot {bootname} already {state}");
+            return Ok(());
+        }
+        write_grubenv(&self.grubenv, &env)?;
+        log::info!("GRUB: marked slot {bootname} as {state}");
+        Ok(())
+    }
+
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String> {
+        let env = read_grubenv(&self.grubenv)?;
+        let order = boot_order(&env)?;
+        Ok(order
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let ok = env.get(&format!("{b}_OK")) == Some("1");
+                let tried = env.get(&format!("{b}_TRY")) == Some("1");
+                SlotBootState {
+                    bootname: b.clone(),
+                    order: Some(i),
+                    remaining_attempts: u32::from(ok && !tried),
+                }
+            })
+            .collect())
+    }
+}
@@ -1,7 +1,20 @@
 #![forbid(unsafe_code)]
 
+pub mod barebox;
+pub mod boot;
 pub mod cli;
 pub mod cmd;
 pub mod config;
+pub mod dbus;
+pub mod dropin;
+pub mod grub;
+pub mod marker;
 pub mod openrc;
+pub mod openrc_state;
 pub mod rauc;
+pub mod rauc_dbus;
+pub mod state;
+pub mod status;
+pub mod uboot;
+pub mod validate;
+pub mod variant;
@@ -1,22 +1,48 @@
 use clap::Parser;
+use rauc_health::boot;
 use rauc_health::cli::Cli;
 use rauc_health::cli::Commands;
+use rauc_health::cli::MarkArgs;
+use rauc_health::config;
+use rauc_health::dropin;
+use rauc_health::marker;
 use rauc_health::openrc;
-use rauc_health::rauc;
+use rauc_health::rauc::MarkState;
+use rauc_health::status;
+use rauc_health::validate;
+use rauc_health::variant;
+use std::process::ExitCode;
 // llvm-cov: ignore-file
-fn main() -> Result<(), String> {
+fn main() -> ExitCode {
     env_logger::init();
     let cli = Cli::parse();
 
     let res = match cli.command {
-        Commands::MarkGood => rauc::mark_good(),
-        Commands::MarkBad => rauc::mark_bad(),
-        Commands::CheckOpenrc(args) => openrc::check_openrc_and_mark(&args),
+        Commands::MarkGood(args) => mark(&args, MarkState::Good, cli.dry_run).map(|()| 0),
+        Commands::MarkBad(args) => mark(&args, MarkState::Bad, cli.dry_run).map(|()| 0),
+        Commands::MarkActive(args) => mark(&args, MarkState::Active, cli.dry_run).map(|()| 0),
+        Commands::CheckOpenrc(args) => {
+            openrc::check_openrc_and_mark(&args, cli.dry_run).map(|outcome| outcome.exit_code())
+        }
+        Commands::Status(args) => status::show_status(&args).map(|()| 0),
+        Commands::RecordUpdate(args) => boot::record_update(&args, cli.dry_run).map(|()| 0),
+        Commands::ValidateConfig(args) => validate::validate_config(&args),
+        Commands::PrintConfig(args) => dropin::print_config(&args).map(|()| 0),
+        Commands::Explain(args) => variant::explain(&args).map(|()| 0),
     };
 
-    if let Err(ref e) = res {
-        log::error!("{e}");
+    match res {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            log::error!("{e}");
+            eprintln!("Error: {e:?}");
+            ExitCode::FAILURE
+        }
     }
+}
 
-    res
+fn mark(args: &MarkArgs, state: MarkState, dry_run: bool) -> Result<(), String> {
+    let mut cfg = config::load(args.config.as_deref())?;
+    cfg.dry_run |= dry_run;
+    marker::from_config(&cfg)?.mark(state, &args.slot)
 }
@@ -0,0 +1,311 @@
+// Datei: src/marker.rs
+//! Backend-independent slot marking.
+//!
+//! [`SlotMarker`] is implemented by every backend that can mark slots good,
+//! bad or active. Bootloader backends work on bootnames; [`SlotNames`]
+//! translates RAUC slot identifiers into bootnames for them.
+
+use crate::config::{HealthConfig, MarkerBackend};
+use crate::rauc::{MarkState, SlotIdentifier};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Boot state of a single slot as seen by the bootloader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotBootState {
+    /// Bootname of the slot, e.g. `A`.
+    pub bootname: String,
+    /// Position in the boot order (0 = primary), `None` if not bootable.
+    pub order: Option<usize>,
+    /// Remaining boot attempts; 0 means the slot is marked bad. Not a real
+    /// counter for every backend, see [`SlotMarker::slot_states`].
+    pub remaining_attempts: u32,
+}
+
+/// A backend that can mark slots and report their boot state.
+///
+/// Implement this trait to support a bootloader that is not built in and
+/// pass it to [`crate::openrc::OpenRcDeps`].
+pub trait SlotMarker {
+    /// Marks `slot` with `state`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the slot cannot be resolved or the backend
+    /// fails to store the new state.
+    fn mark(&self, state: MarkState, slot: &SlotIdentifier) -> Result<(), String>;
+
+    /// Marks the booted slot as good.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error of [`SlotMarker::mark`].
+    fn mark_good(&self) -> Result<(), String> {
+        self.mark(MarkState::Good, &SlotIdentifier::Booted)
+    }
+
+    /// Marks the booted slot as bad.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error of [`SlotMarker::mark`].
+    fn mark_bad(&self) -> Result<(), String> {
+        self.mark(MarkState::Bad, &SlotIdentifier::Booted)
+    }
+
+    /// Makes `slot` the primary boot target.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error of [`SlotMarker::mark`].
+    fn mark_active(&self, slot: &SlotIdentifier) -> Result<(), String> {
+        self.mark(MarkState::Active, slot)
+    }
+
+    /// Returns the boot state of all slots known to the backend.
+    ///
+    /// Only the bootloader backends (U-Boot, GRUB, barebox) report real
+    /// attempt counters. Backends that only know good or bad (the RAUC CLI
+    /// and D-Bus backends, the file marker) report one remaining attempt for
+    /// a good slot and zero for a bad one.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the backend storage cannot be read.
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String>;
+}
+
+/// Mapping from RAUC slot identifiers to bootloader bootnames.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SlotNames {
+    /// Bootname of the booted slot (`rauc.slot=` kernel parameter).
+    pub booted: Option<String>,
+    /// RAUC slot name → bootname, from `system.conf`.
+    pub bootnames: BTreeMap<String, String>,
+}
+
+impl SlotNames {
+    /// Reads the booted slot from `/proc/cmdline` and the slot bootnames
+    /// from the RAUC `system.conf` at `system_conf`.
+    ///
+    /// Missing files are logged and leave the corresponding field empty.
+    #[must_use]
+    pub fn detect(system_conf: &Path) -> Self {
+        let booted = std::fs::read_to_string("/proc/cmdline")
+            .map_err(|e| log::warn!("failed to read /proc/cmdline: {e}"))
+            .ok()
+            .and_then(|c| crate::boot::bootname_from_cmdline(&c));
+        let bootnames = std::fs::read_to_string(system_conf)
+            .map_err(|e| log::warn!("failed to read {}: {e}", system_conf.display()))
+            .map(|s| parse_system_conf_bootnames(&s))
+            .unwrap_or_default();
+        Self { booted, bootnames }
+    }
+
+    /// Resolves `slot` to a bootname.
+    ///
+    /// `Named` accepts a RAUC slot name (`rootfs.1`) or a bootname (`B`);
+    /// `Other` is the only entry of `order` that is not booted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the slot cannot be determined unambiguously.
+    pub fn resolve(&self, slot: &SlotIdentifier, order: &[String]) -> Result<String, String> {
+        let booted = || {
+            self.booted
+                .clone()
+                .ok_or_else(|| "booted slot unknown (no rauc.slot= on kernel command line)".into())
+        };
+        match slot {
+            SlotIdentifier::Booted => booted(),
+            SlotIdentifier::Other => {
+                let booted = booted()?;
+                let mut others = order.iter().filter(|b| **b != booted);
+                match (others.next(), others.next()) {
+                    (Some(other), None) => Ok(other.clone()),
+                    _ => Err(format!(
+                        "cannot determine other slot from boot order `{}`",
+                        order.join(" ")
+                    )),
+                }
+            }
+            SlotIdentifier::Named(name) => {
+                if let Some(bootname) = self.bootnames.get(name) {
+                    Ok(bootname.clone())
+                } else if order.contains(name) || self.bootnames.values().any(|b| b == name) {
+                    Ok(name.clone())
+                } else {
+                    Err(format!("unknown slot `{name}`"))
+                }
+            }
+        }
+    }
+}
+
+/// Extracts `slot name → bootname` from a RAUC `system.conf`.
+///
+/// Only `[slot.<class>.<n>]` sections with a `bootname=` key are returned.
+#[must_use]
+pub fn parse_system_conf_bootnames(s: &str) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    let mut section: Option<&str> = None;
+    for line in s.lines().map(str::trim) {
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.strip_prefix("slot.");
+            continue;
+        }
+        if let (Some(slot), Some((key, value))) = (section, line.split_once('=')) {
+            if key.trim() == "bootname" {
+                out.insert(slot.to_string(), value.trim().to_string());
+            }
+        }
+    }
+    out
+}
+
+/// Content of the file used by [`FileMarker`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMarkerState {
+    /// Bootname of the booted slot, used if the kernel command line has none.
+    #[serde(default)]
+    pub booted: Option<String>,
+    /// Boot order, primary first.
+    #[serde(default)]
+    pub order: Vec<String>,
+    /// Bootname → `good`/`bad`.
+    #[serde(default)]
+    pub slots: BTreeMap<String, String>,
+}
+
+/// Fake backend keeping the slot states in a TOML file.
+///
+/// Meant for development hosts and integration tests without a bootloader.
+#[derive(Debug, Clone)]
+pub struct FileMarker {
+    path: PathBuf,
+    names: SlotNames,
+}
+
+impl FileMarker {
+    /// Creates a marker storing its state in `path`.
+    #[must_use]
+    pub fn new(path: PathBuf, names: SlotNames) -> Self {
+        Self { path, names }
+    }
+
+    /// Reads the state file; a missing file yields an empty state.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the file exists but cannot be read or parsed.
+    pub fn read_state(&self) -> Result<FileMarkerState, String> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(s) => toml::from_str(&s)
+                .map_err(|e| format!("failed to parse {}: {e}", self.path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(FileMarkerState::default()),
+            Err(e) => Err(format!("failed to read {}: {e}", self.path.display())),
+        }
+    }
+}
+
+impl SlotMarker for FileMarker {
+    fn mark(&self, state: MarkState, slot: &SlotIdentifier) -> Result<(), String> {
+        let mut st = self.read_state()?;
+        let names = SlotNames {
+            booted: self.names.booted.clone().or_else(|| st.booted.clone()),
+            bootnames: self.names.bootnames.clone(),
+        };
+        let bootname = names.resolve(slot, &st.order)?;
+
+        let value = match state {
+            MarkState::Good => "good",
+            MarkState::Bad => "bad",
+            MarkState::Active => {
+                st.order.retain(|b| *b != bootname);
+                st.order.insert(0, bootname.clone());
+                "good"
+            }
+        };
+        st.slots.insert(bootname.clone(), value.to_string());
+
+        let s = toml::to_string(&st)
+            .map_err(|e| format!("failed to serialize {}: {e}", self.path.display()))?;
+        crate::state::write_atomic(&self.path, s.as_bytes())?;
+        log::info!("file marker: marked slot {bootname} as {state}");
+        Ok(())
+    }
+
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String> {
+        let st = self.read_state()?;
+        Ok(st
+            .slots
+            .iter()
+            .map(|(b, v)| SlotBootState {
+                bootname: b.clone(),
+                order: st.order.iter().position(|o| o == b),
+                remaining_attempts: u32::from(v == "good"),
+            })
+            .collect())
+    }
+}
+
+/// Backend that only logs what it would mark.
+///
+/// Queries are answered by `inner`, if given.
+pub struct DryRunMarker {
+    inner: Option<Box<dyn SlotMarker>>,
+}
+
+impl DryRunMarker {
+    /// Creates a dry-run marker, optionally wrapping a real backend.
+    #[must_use]
+    pub fn new(inner: Option<Box<dyn SlotMarker>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl SlotMarker for DryRunMarker {
+    fn mark(&self, state: MarkState, slot: &SlotIdentifier) -> Result<(), String> {
+        log::info!("dry run: would mark slot {slot} as {state}");
+        Ok(())
+    }
+
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String> {
+        self.inner
+            .as_ref()
+            .map_or(Ok(Vec::new()), |m| m.slot_states())
+    }
+}
+
+/// Creates the backend selected by `marker_backend` in `cfg`.
+///
+/// With `dry_run` set, the backend is wrapped in a [`DryRunMarker`], so it
+/// only answers queries.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the backend cannot be set up (e.g. missing
+/// `fw_env.config`).
+pub fn from_config(cfg: &HealthConfig) -> Result<Box<dyn SlotMarker>, String> {
+    let marker: Box<dyn SlotMarker> = match cfg.marker_backend {
+        MarkerBackend::Cli => Box::new(crate::rauc::CliMarker::from_config(cfg)?),
+        MarkerBackend::Dbus => Box::new(crate::rauc_dbus::DbusMarker::from_config(cfg)?),
+        MarkerBackend::Uboot => Box::new(crate::uboot::UbootMarker::from_config(cfg)?),
+        MarkerBackend::Grub => Box::new(crate::grub::GrubMarker::from_config(cfg)),
+        MarkerBackend::Barebox => Box::new(crate::barebox::BareboxMarker::from_config(cfg)?),
+        MarkerBackend::File => Box::new(FileMarker::new(
+            cfg.marker_file.clone(),
+            SlotNames::detect(&cfg.rauc_system_conf),
+        )),
+        MarkerBackend::DryRun => return Ok(Box::new(DryRunMarker::new(None))),
+    };
+    Ok(if cfg.dry_run {
+        Box::new(DryRunMarker::new(Some(marker)))
+    } else {
+        marker
+    })
+}
@@ -1,29 +1,225 @@
+use crate::boot::{BootAssessment, BootKind, Rollback};
 use crate::cli::CheckOpenrcArgs;
-use crate::config::HealthConfig;
+use crate::cmd::{run_stdout_timeout, RealCmdRunner};
+use crate::config::{
+    CheckMode, DegradedPolicy, HealthConfig, NormalBootPolicy, ServiceSource, StatePolicy,
+    TrialBootDetection,
+};
+use crate::marker::SlotMarker;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::process::Command;
 use std::time::{Duration, Instant};
 
-const STARTED: &str = "started";
+/// Runlevel whose services are checked unless `runlevels` is configured.
+pub const RUNLEVEL: &str = "default";
 
-/// Represents a required service that is not in the expected state.
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Time granted to the final `rc-status` call once the deadline has passed.
+pub const MIN_RC_STATUS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Represents a required or optional service that is not in the expected
+/// state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FailedService {
     /// Name of the service.
     pub name: String,
-    /// Reported service status (e.g. "stopped", "crashed", "missing").
-    pub status: String,
+    /// Reported service status (e.g. `stopped`, `crashed`, `missing`).
+    pub status: ServiceState,
+}
+
+/// State of a service as reported by `rc-status`.
+///
+/// Parsing is case-sensitive; anything else is kept as
+/// [`ServiceState::Unknown`] with the raw text. Serialized as the raw text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceState {
+    /// Service is running.
+    Started,
+    /// Service is being started.
+    Starting,
+    /// Service is being stopped.
+    Stopping,
+    /// Service is not running.
+    Stopped,
+    /// Service was started, but one of its daemons has died.
+    Crashed,
+    /// Service failed to start.
+    Failed,
+    /// Service was started, but is waiting for something (e.g. a network
+    /// interface) before it is usable.
+    Inactive,
+    /// Service is scheduled to start once another service has started.
+    Scheduled,
+    /// Service was started by hotplug.
+    Hotplugged,
+    /// Service is not listed by `rc-status` at all; never parsed, only
+    /// reported for a checked service.
+    Missing,
+    /// Any other status text, kept verbatim.
+    Unknown(String),
+}
+
+impl ServiceState {
+    /// Parses the status column of `rc-status`.
+    #[must_use]
+    pub fn parse(status: &str) -> Self {
+        match status {
+            "started" => Self::Started,
+            "starting" => Self::Starting,
+            "stopping" => Self::Stopping,
+            "stopped" => Self::Stopped,
+            "crashed" => Self::Crashed,
+            "failed" => Self::Failed,
+            "inactive" => Self::Inactive,
+            "scheduled" => Self::Scheduled,
+            "hotplugged" => Self::Hotplugged,
+            other => Self::Unknown(other.to_string()),
+        }
+    }
+
+    /// Raw status text as printed by `rc-status`.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Started => "started",
+            Self::Starting => "starting",
+            Self::Stopping => "stopping",
+            Self::Stopped => "stopped",
+            Self::Crashed => "crashed",
+            Self::Failed => "failed",
+            Self::Inactive => "inactive",
+            Self::Scheduled => "scheduled",
+            Self::Hotplugged => "hotplugged",
+            Self::Missing => "missing",
+            Self::Unknown(raw) => raw,
+        }
+    }
+
+    /// Policy for this state according to `cfg.service_states`; a missing
+    /// service may still show up, so it is waited for.
+    #[must_use]
+    pub fn policy(&self, cfg: &HealthConfig) -> StatePolicy {
+        let states = &cfg.service_states;
+        match self {
+            Self::Started => states.started,
+            Self::Starting => states.starting,
+            Self::Stopping => states.stopping,
+            Self::Stopped => states.stopped,
+            Self::Crashed => states.crashed,
+            Self::Failed => states.failed,
+            Self::Inactive => states.inactive,
+            Self::Scheduled => states.scheduled,
+            Self::Hotplugged => states.hotplugged,
+            Self::Missing => StatePolicy::Wait,
+            Self::Unknown(_) => states.unknown,
+        }
+    }
+}
+
+impl std::fmt::Display for ServiceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ServiceState {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ServiceState {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        Ok(match raw.as_str() {
+            "missing" => Self::Missing,
+            other => Self::parse(other),
+        })
+    }
+}
+
+/// Ignore rule that excludes a service from the check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IgnoreRule {
+    /// Entry of `ignore_exact`.
+    Exact(String),
+    /// Entry of `ignore_prefixes`.
+    Prefix(String),
+    /// The service running the check itself (`RC_SVCNAME`).
+    Caller,
+}
+
+impl std::fmt::Display for IgnoreRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact(name) => write!(f, "ignore_exact `{name}`"),
+            Self::Prefix(prefix) => write!(f, "ignore_prefixes `{prefix}`"),
+            Self::Caller => f.write_str("calling service"),
+        }
+    }
+}
+
+/// A service listed by `OpenRC` that was not checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoredService {
+    /// Name of the service.
+    pub name: String,
+    /// Reported service status.
+    pub status: ServiceState,
+    /// Rule that excluded the service.
+    pub rule: IgnoreRule,
 }
 
 /// Result of evaluating the `OpenRC` service health.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HealthDecision {
-    /// All required services are running as expected.
+    /// All required and optional services are running as expected.
     Good,
+    /// All required services are running, but one or more optional services
+    /// are missing or not started.
+    Degraded(Vec<FailedService>),
     /// One or more required services are missing or not started.
     Bad(Vec<FailedService>),
 }
 
+/// Exit code reported when a rollback to another slot was detected.
+pub const EXIT_ROLLBACK: u8 = 3;
+
+/// Exit code reported when optional services failed (see
+/// [`CheckOutcome::Degraded`]).
+///
+/// `service/rauc-health.initd` must not reboot on this code nor on
+/// [`EXIT_ROLLBACK`]; every other non-zero code triggers a reboot.
+pub const EXIT_DEGRADED: u8 = 4;
+
+/// Outcome of a health check run that did not fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    /// All required services are healthy and the slot was marked good.
+    MarkedGood,
+    /// The slot was deliberately left unmarked (normal boot policy).
+    NotMarked,
+    /// All required services are healthy, but the listed optional services
+    /// are not. Whether the slot was marked good depends on
+    /// `degraded_policy`.
+    Degraded(Vec<FailedService>),
+    /// The bootloader fell back from the freshly installed slot, so the
+    /// rollout failed. Reported whatever the health decision; the booted
+    /// slot is only marked good if it passed, and never marked bad.
+    RollbackOccurred(Rollback),
+}
+
+impl CheckOutcome {
+    /// Process exit code for this outcome.
+    #[must_use]
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::MarkedGood | Self::NotMarked => 0,
+            Self::Degraded(_) => EXIT_DEGRADED,
+            Self::RollbackOccurred(_) => EXIT_ROLLBACK,
+        }
+    }
+}
+
 /// Wrapper that allows injecting a preloaded [`HealthConfig`].
 ///
 /// This is mainly useful for tests or higher-level orchestration code.
@@ -35,23 +231,32 @@ pub fn check_openrc_and_mark_with_cfg(
     args: &CheckOpenrcArgs,
     cfg: &HealthConfig,
     deps: &OpenRcDeps<'_>,
-) -> Result<(), String> {
+) -> Result<CheckOutcome, String> {
     check_openrc_and_mark_with(args, cfg, deps)
 }
 
 /// Decides overall system health based on `rc-status` output.
 ///
+/// With `check_mode = "all"` every listed service counts as required unless
+/// it is optional or matches an ignore rule.
+///
 /// # Returns
 ///
-/// - [`HealthDecision::Good`] if all required services are started
-/// - [`HealthDecision::Bad`] with a list of failed services otherwise
+/// - [`HealthDecision::Bad`] with the failed required services if any
+/// - [`HealthDecision::Degraded`] with the failed optional services if any
+/// - [`HealthDecision::Good`] otherwise
 #[must_use]
 pub fn decide_health(stdout: &str, cfg: &HealthConfig) -> HealthDecision {
-    let failed = collect_failed_services(stdout, cfg);
+    let services = ServiceView::new(stdout, cfg);
+    let failed = failed_services(&services, &checked_required(&services, cfg), cfg);
+    if !failed.is_empty() {
+        return HealthDecision::Bad(failed);
+    }
+    let failed = failed_services(&services, &cfg.optional_services, cfg);
     if failed.is_empty() {
         HealthDecision::Good
     } else {
-        HealthDecision::Bad(failed)
+        HealthDecision::Degraded(failed)
     }
 }
 
@@ -59,31 +264,82 @@ pub fn decide_health(stdout: &str, cfg: &HealthConfig) -> HealthDecision {
 ///
 /// # Behavior
 ///
+/// - Only the runlevels in `cfg.runlevels` are checked (see [`ServiceView`])
+/// - With `check_mode = "all"`, every service of these runlevels that is not
+///   optional is required as well
 /// - Services listed in `cfg.ignore_exact` or `cfg.ignore_prefixes` are skipped
-/// - A service is considered healthy only if its status equals `"started"`
+/// - A service is considered healthy if the policy for its
+///   [`ServiceState`] in `cfg.service_states` is `ok` (by default only
+///   `started`)
 ///
 /// # Returns
 ///
 /// A list of [`FailedService`] entries. The list is empty if all services are healthy.
 #[must_use]
 pub fn collect_failed_services(stdout: &str, cfg: &HealthConfig) -> Vec<FailedService> {
-    let services = parse_services_map(stdout);
+    let services = ServiceView::new(stdout, cfg);
+    failed_services(&services, &checked_required(&services, cfg), cfg)
+}
+
+/// Collects the services of the checked runlevels excluded by an ignore
+/// rule, sorted by name, together with the rule.
+#[must_use]
+pub fn collect_ignored_services(stdout: &str, cfg: &HealthConfig) -> Vec<IgnoredService> {
+    let mut ignored: Vec<IgnoredService> = ServiceView::new(stdout, cfg)
+        .checked
+        .into_iter()
+        .filter_map(|(name, status)| {
+            ignore_rule(&name, cfg).map(|rule| IgnoredService { name, status, rule })
+        })
+        .collect();
+    ignored.sort_by(|a, b| a.name.cmp(&b.name));
+    ignored
+}
+
+/// Services treated as required: `required_services`, plus every
+/// non-optional service of the checked runlevels in `check_mode = "all"`.
+fn checked_required(services: &ServiceView, cfg: &HealthConfig) -> Vec<String> {
+    let mut names = cfg.required_services.clone();
+    if cfg.check_mode == CheckMode::All {
+        let mut listed: Vec<&String> = services
+            .checked
+            .keys()
+            .filter(|n| !names.contains(n) && !cfg.optional_services.contains(n))
+            .collect();
+        listed.sort();
+        names.extend(listed.into_iter().cloned());
+    }
+    names
+}
+
+/// Collects all optional services that are missing or not started; see
+/// [`collect_failed_services`].
+#[must_use]
+pub fn collect_failed_optional_services(stdout: &str, cfg: &HealthConfig) -> Vec<FailedService> {
+    failed_services(&ServiceView::new(stdout, cfg), &cfg.optional_services, cfg)
+}
+
+fn failed_services(
+    services: &ServiceView,
+    names: &[String],
+    cfg: &HealthConfig,
+) -> Vec<FailedService> {
     let mut failed = Vec::new();
 
-    for req in &cfg.required_services {
+    for req in names {
         if is_ignored_service(req, cfg) {
             continue;
         }
 
-        match services.get(req) {
-            Some(status) if status == STARTED => {}
+        match services.status(req) {
+            Some(status) if status.policy(cfg) == StatePolicy::Healthy => {}
             Some(status) => failed.push(FailedService {
                 name: req.clone(),
                 status: status.clone(),
             }),
             None => failed.push(FailedService {
                 name: req.clone(),
-                status: "missing".to_string(),
+                status: ServiceState::Missing,
             }),
         }
     }
@@ -92,13 +348,140 @@ pub fn collect_failed_services(stdout: &str, cfg: &HealthConfig) -> Vec<FailedSe
 }
 
 /// Checks whether a service should be ignored according to the configuration.
-fn is_ignored_service(name: &str, cfg: &HealthConfig) -> bool {
-    cfg.ignore_exact.iter().any(|s| s == name)
-        || cfg.ignore_prefixes.iter().any(|p| name.starts_with(p))
+pub(crate) fn is_ignored_service(name: &str, cfg: &HealthConfig) -> bool {
+    ignore_rule(name, cfg).is_some()
+}
+
+/// Returns the first ignore rule matching `name`; the calling service is
+/// always ignored, exact names win over prefixes.
+#[must_use]
+pub fn ignore_rule(name: &str, cfg: &HealthConfig) -> Option<IgnoreRule> {
+    if cfg.caller.as_deref() == Some(name) {
+        return Some(IgnoreRule::Caller);
+    }
+    if let Some(exact) = cfg.ignore_exact.iter().find(|s| *s == name) {
+        return Some(IgnoreRule::Exact(exact.clone()));
+    }
+    cfg.ignore_prefixes
+        .iter()
+        .find(|p| name.starts_with(p.as_str()))
+        .map(|p| IgnoreRule::Prefix(p.clone()))
+}
+
+/// Services of one runlevel as listed by `rc-status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunlevelServices {
+    /// Runlevel name, e.g. `default` or `needed/wanted`; empty for services
+    /// listed before any runlevel header.
+    pub name: String,
+    /// `true` for the dynamic runlevels (`hotplugged`, `needed/wanted`,
+    /// `manual`), which hold services started outside of any runlevel.
+    pub dynamic: bool,
+    /// Service → status; a later line overwrites an earlier one.
+    pub services: HashMap<String, ServiceState>,
+}
+
+/// Parses `rc-status` output into one section per runlevel header, in
+/// output order.
+///
+/// Service lines are parsed as in [`parse_services_map`]; lines before the
+/// first header form a section with an empty name.
+#[must_use]
+pub fn parse_runlevels(stdout: &str) -> Vec<RunlevelServices> {
+    let mut sections: Vec<RunlevelServices> = Vec::new();
+
+    for raw_line in stdout.lines() {
+        let line = raw_line.trim();
+        let header = line
+            .strip_prefix("Runlevel:")
+            .map(|n| (n, false))
+            .or_else(|| line.strip_prefix("Dynamic Runlevel:").map(|n| (n, true)));
+        if let Some((name, dynamic)) = header {
+            sections.push(RunlevelServices {
+                name: name.trim().to_string(),
+                dynamic,
+                services: HashMap::new(),
+            });
+            continue;
+        }
+        let Some((name, status)) = parse_service_line(line) else {
+            continue;
+        };
+        if sections.is_empty() {
+            sections.push(RunlevelServices {
+                name: String::new(),
+                dynamic: false,
+                services: HashMap::new(),
+            });
+        }
+        if let Some(section) = sections.last_mut() {
+            section.services.insert(name, ServiceState::parse(&status));
+        }
+    }
+
+    sections
+}
+
+/// Services of an `rc-status` output as seen by the check.
+///
+/// # Rules
+///
+/// - `checked` holds the services of the runlevels in `cfg.runlevels` and
+///   those listed before any runlevel header; only these are checked with
+///   `check_mode = "all"`
+/// - `dynamic` holds the services of the dynamic runlevels (`hotplugged`,
+///   `needed/wanted`, `manual`); they are only looked at for services named
+///   in `required_services` or `optional_services`, so a required service
+///   started as a dependency or by hotplug still counts
+/// - Other runlevels are ignored
+/// - If a service is listed more than once, its first listing wins, so a
+///   dynamic runlevel cannot hide a service's state in a checked runlevel
+pub struct ServiceView {
+    /// Services of the checked runlevels.
+    pub checked: HashMap<String, ServiceState>,
+    /// Services of the dynamic runlevels not listed in a checked one.
+    pub dynamic: HashMap<String, ServiceState>,
+}
+
+impl ServiceView {
+    /// Builds the view of `stdout` for the runlevels configured in `cfg`.
+    #[must_use]
+    pub fn new(stdout: &str, cfg: &HealthConfig) -> Self {
+        let mut view = Self {
+            checked: HashMap::new(),
+            dynamic: HashMap::new(),
+        };
+        let sections = parse_runlevels(stdout);
+        let (checked, rest): (Vec<_>, Vec<_>) = sections
+            .into_iter()
+            .partition(|s| s.name.is_empty() || cfg.runlevels.contains(&s.name));
+        for section in checked {
+            for (name, status) in section.services {
+                view.checked.entry(name).or_insert(status);
+            }
+        }
+        for section in rest.into_iter().filter(|s| s.dynamic) {
+            for (name, status) in section.services {
+                if !view.checked.contains_key(&name) {
+                    view.dynamic.entry(name).or_insert(status);
+                }
+            }
+        }
+        view
+    }
+
+    /// Status of `name`: from a checked runlevel, else from a dynamic one.
+    #[must_use]
+    pub fn status(&self, name: &str) -> Option<&ServiceState> {
+        self.checked.get(name).or_else(|| self.dynamic.get(name))
+    }
 }
 
 /// Parses `rc-status` output into a service → status map.
 ///
+/// All runlevels are flattened into one map; see [`parse_runlevels`] for
+/// the per-runlevel sections.
+///
 /// # Behavior
 ///
 /// - Ignores empty lines and runlevel headers
@@ -119,18 +502,23 @@ pub fn parse_services_map(stdout: &str) -> HashMap<String, String> {
         if line.starts_with("Runlevel:") || line.starts_with("Dynamic Runlevel:") {
             continue;
         }
-
-        let mut it = line.split_whitespace();
-        let Some(svc_name) = it.next() else { continue };
-        let _ = it.next(); // usually "["
-        let Some(status) = it.next() else { continue };
-
-        map.insert(svc_name.to_string(), status.to_string());
+        if let Some((name, status)) = parse_service_line(line) {
+            map.insert(name, status);
+        }
     }
 
     map
 }
 
+/// Parses a line like `cron [ started ]` into name and status.
+fn parse_service_line(line: &str) -> Option<(String, String)> {
+    let mut it = line.split_whitespace();
+    let svc_name = it.next()?;
+    let _ = it.next(); // usually "["
+    let status = it.next()?;
+    Some((svc_name.to_string(), status.to_string()))
+}
+
 //
 // ============================
 // Dependency Injection Layer
@@ -142,12 +530,19 @@ pub fn parse_services_map(stdout: &str) -> HashMap<String, String> {
 /// This allows deterministic testing by replacing system calls,
 /// sleeping, and time measurement.
 pub struct OpenRcDeps<'a> {
-    /// Runs `rc-status` and returns its stdout.
-    pub run_rc_status: &'a dyn Fn() -> Result<String, String>,
-    /// Marks the system as healthy.
-    pub mark_good: &'a dyn Fn() -> Result<(), String>,
-    /// Marks the system as unhealthy.
-    pub mark_bad: &'a dyn Fn() -> Result<(), String>,
+    /// Runs `rc-status` with the given timeout and returns its stdout (or
+    /// output in the same format, see [`crate::openrc_state`]).
+    pub run_rc_status: &'a dyn Fn(Duration) -> Result<String, String>,
+    /// Backend used to mark the booted slot good or bad.
+    pub marker: &'a dyn SlotMarker,
+    /// Persists the health decision (e.g. for `rauc-health status`).
+    pub record_decision: &'a dyn Fn(&HealthDecision) -> Result<(), String>,
+    /// Classifies the current boot as trial or normal boot.
+    pub assess_boot: &'a dyn Fn() -> BootAssessment,
+    /// Records that a trial boot passed the strict gate.
+    pub confirm_boot: &'a dyn Fn(&BootAssessment) -> Result<(), String>,
+    /// Reacts to a detected rollback (e.g. marks the abandoned slot bad).
+    pub handle_rollback: &'a dyn Fn(&Rollback) -> Result<(), String>,
     /// Sleeps for the given duration.
     pub sleep: &'a dyn Fn(Duration),
     /// Returns the current time.
@@ -162,28 +557,52 @@ pub struct OpenRcDeps<'a> {
 /// - the configuration cannot be loaded
 /// - `rc-status` fails or exits unsuccessfully
 /// - marking the system good or bad fails
-pub fn check_openrc_and_mark(args: &CheckOpenrcArgs) -> Result<(), String> {
-    let cfg = match args.config.as_deref() {
-        Some(path) => crate::config::from_file(path)?,
-        None => HealthConfig::default(),
+///
+/// With `dry_run` (or `dry_run` in the config) the complete check runs, but
+/// marking and state changes are only logged.
+pub fn check_openrc_and_mark(
+    args: &CheckOpenrcArgs,
+    dry_run: bool,
+) -> Result<CheckOutcome, String> {
+    let layered = crate::config::load_layered(args.config.as_deref())?;
+    let sources = layered.sources();
+    let mut cfg = layered.config;
+    cfg.sources = sources;
+    cfg.dry_run |= dry_run;
+    cfg.caller = std::env::var("RC_SVCNAME").ok();
+    let runner = RealCmdRunner::new(&cfg.commands)?;
+
+    let marker = crate::marker::from_config(&cfg)?;
+    let state_tree = crate::openrc_state::StateTree::new(&cfg.openrc_root);
+
+    let rc_status = |timeout| {
+        run_stdout_timeout(
+            &runner,
+            "rc-status",
+            &["--nocolor", "--all"],
+            "rc-status",
+            timeout,
+        )
     };
+    // Reading the state tree spawns no process that could hang
+    let read_state_tree = |_timeout| state_tree.read();
 
     let deps = OpenRcDeps {
-        run_rc_status: &|| {
-            let output = Command::new("rc-status")
-                .args(["--nocolor", "default"])
-                .output()
-                .map_err(|e| format!("failed to execute rc-status: {e}"))?;
-
-            if !output.status.success() {
-                return Err(format!("rc-status exited with {}", output.status));
+        run_rc_status: match cfg.service_source {
+            ServiceSource::RcStatus => &rc_status,
+            ServiceSource::StateTree => &read_state_tree,
+        },
+        marker: marker.as_ref(),
+        record_decision: &|decision| {
+            if cfg.dry_run {
+                log::info!("dry run: would record decision {decision:?}");
+                return Ok(());
             }
-
-            String::from_utf8(output.stdout)
-                .map_err(|_| "rc-status output was not valid UTF-8".to_string())
+            crate::state::record_decision(&cfg.state_dir, decision)
         },
-        mark_good: &crate::rauc::mark_good,
-        mark_bad: &crate::rauc::mark_bad,
+        assess_boot: &|| crate::boot::assess_current_boot(&cfg),
+        confirm_boot: &|assessment| crate::boot::confirm_boot(&cfg, assessment),
+        handle_rollback: &|rollback| crate::boot::handle_rollback(&cfg, rollback, marker.as_ref()),
         sleep: &std::thread::sleep,
         now: &Instant::now,
     };
@@ -195,44 +614,273 @@ pub fn check_openrc_and_mark(args: &CheckOpenrcArgs) -> Result<(), String> {
 ///
 /// # Behavior
 ///
-/// - Polls `rc-status` until all services are healthy or a timeout is reached
+/// - With `trial_boot_detection = "auto"` the boot is classified first; on
+///   normal boots `normal_boot_policy` decides whether the check is skipped,
+///   lenient (never marks bad) or strict
+/// - If the booted slot is not the target of the pending update, the
+///   rollback is handled and the strict gate runs on the fallback slot; a
+///   healthy fallback slot yields [`CheckOutcome::RollbackOccurred`];
+///   failures to handle the rollback are logged but do not abort the check
+/// - Polls `rc-status` until all services are healthy or a timeout is reached;
+///   each `rc-status` call only gets the time left until the deadline (at
+///   least [`MIN_RC_STATUS_TIMEOUT`]), so a hanging call cannot outlive it
 /// - Retries at `poll_interval_ms` intervals
+/// - Logs which ignore rule excluded each listed service from the final
+///   check
+/// - Timeout and poll interval from `args` take precedence over `cfg`
 /// - Marks the system good immediately on success
-/// - Marks the system bad once the timeout expires
+/// - Marks the system bad once the timeout expires, or right away if a
+///   service is in a state whose policy is `fail` (e.g. `crashed`)
+/// - If only optional services still fail at the deadline, the boot is
+///   degraded and `degraded_policy` decides whether the system is marked
+///   good, left unmarked or marked bad
+/// - Records the final decision before marking; failures to record are
+///   logged but do not abort the check
+/// - Confirms trial boots after marking good
 ///
 /// # Errors
 ///
 /// Returns `Err(String)` if:
-/// - querying `rc-status` fails
+/// - querying `rc-status` fails or times out
 /// - the timeout expires before services become healthy
 /// - marking the system good or bad fails
 pub fn check_openrc_and_mark_with(
     args: &CheckOpenrcArgs,
     cfg: &HealthConfig,
     deps: &OpenRcDeps<'_>,
-) -> Result<(), String> {
-    let deadline = (deps.now)() + Duration::from_secs(args.timeout_secunds);
+) -> Result<CheckOutcome, String> {
+    let mut boot = (deps.assess_boot)();
+    if cfg.trial_boot_detection == TrialBootDetection::Off {
+        boot.kind = BootKind::Trial;
+        boot.reason = BootAssessment::detection_disabled().reason;
+    }
+    log::info!("Boot classified as {}: {}", boot.kind, boot.reason);
+
+    if let Some(rollback) = &boot.rollback {
+        log::error!("Rollback occurred: {rollback}");
+        // Failing here would reboot into the same rollback again
+        if let Err(e) = (deps.handle_rollback)(rollback) {
+            log::warn!("failed to handle rollback: {e}");
+        }
+        boot.kind = BootKind::Trial;
+    }
+
+    let strict = match (boot.kind, cfg.normal_boot_policy) {
+        (BootKind::Trial, _) | (BootKind::Normal, NormalBootPolicy::Strict) => true,
+        (BootKind::Normal, NormalBootPolicy::Lenient) => {
+            log::info!("Normal boot policy lenient: slot will not be marked bad");
+            false
+        }
+        (BootKind::Normal, NormalBootPolicy::Skip) => {
+            log::info!("Normal boot policy skip: not checking or marking slot");
+            return Ok(CheckOutcome::NotMarked);
+        }
+    };
+
+    let source = |key: &str| cfg.sources.get(key).map_or("config", String::as_str);
+    let (timeout_seconds, timeout_source) = match args.timeout_seconds {
+        Some(t) => (t, "command line"),
+        None => (cfg.timeout_seconds, source("timeout_seconds")),
+    };
+    let (poll_interval_ms, poll_source) = match args.poll_interval_ms {
+        Some(p) => (p, "command line"),
+        None => (cfg.poll_interval_ms, source("poll_interval_ms")),
+    };
+    log::info!(
+        "Waiting up to {timeout_seconds}s ({timeout_source}), polling every {poll_interval_ms}ms ({poll_source})"
+    );
+
+    let deadline = (deps.now)() + Duration::from_secs(timeout_seconds);
 
     loop {
-        let stdout = (deps.run_rc_status)()?;
+        let remaining = deadline.saturating_duration_since((deps.now)());
+        let stdout = (deps.run_rc_status)(remaining.max(MIN_RC_STATUS_TIMEOUT))?;
 
         match decide_health(&stdout, cfg) {
             HealthDecision::Good => {
-                (deps.mark_good)()?;
-                return Ok(());
+                report(&stdout, cfg);
+                record(deps, &HealthDecision::Good);
+                return accept(deps, boot, CheckOutcome::MarkedGood);
+            }
+            HealthDecision::Degraded(failed) => {
+                if (deps.now)() < deadline && !fails_fast(&failed, cfg) {
+                    (deps.sleep)(Duration::from_millis(poll_interval_ms));
+                    continue;
+                }
+
+                report(&stdout, cfg);
+                record(deps, &HealthDecision::Degraded(failed.clone()));
+                return finish_degraded(deps, cfg, boot, strict, failed);
             }
             HealthDecision::Bad(failed) => {
-                if (deps.now)() < deadline {
-                    (deps.sleep)(Duration::from_millis(args.poll_interval_ms));
+                if (deps.now)() < deadline && !fails_fast(&failed, cfg) {
+                    (deps.sleep)(Duration::from_millis(poll_interval_ms));
                     continue;
                 }
 
-                (deps.mark_bad)()?;
-                return Err(format!(
-                    "OpenRC health check failed ({} failing services)",
-                    failed.len()
-                ));
+                report(&stdout, cfg);
+                record(deps, &HealthDecision::Bad(failed.clone()));
+                return finish_bad(deps, boot, strict, &failed);
             }
         }
     }
 }
+
+/// Returns `true` (and logs why) if a service in `failed` is in a state
+/// whose policy is `fail`, so waiting for the timeout is pointless.
+fn fails_fast(failed: &[FailedService], cfg: &HealthConfig) -> bool {
+    let Some(f) = failed
+        .iter()
+        .find(|f| f.status.policy(cfg) == StatePolicy::Fail)
+    else {
+        return false;
+    };
+    log::warn!("{} is {}, not waiting for the timeout", f.name, f.status);
+    true
+}
+
+/// Logs configured runlevels missing from `stdout`, services in unknown
+/// states, and which rule excluded each listed service from the final check.
+fn report(stdout: &str, cfg: &HealthConfig) {
+    let view = ServiceView::new(stdout, cfg);
+    let mut unknown: Vec<(&String, &str)> = view
+        .checked
+        .iter()
+        .chain(&view.dynamic)
+        .filter_map(|(name, status)| match status {
+            ServiceState::Unknown(raw) => Some((name, raw.as_str())),
+            _ => None,
+        })
+        .collect();
+    unknown.sort();
+    for (name, status) in unknown {
+        log::warn!("Service {name} has unknown state `{status}`");
+    }
+
+    let sections = parse_runlevels(stdout);
+    if !sections.iter().all(|s| s.name.is_empty()) {
+        for runlevel in &cfg.runlevels {
+            if !sections.iter().any(|s| s.name == *runlevel) {
+                log::warn!("Runlevel {runlevel} is not listed by rc-status");
+            }
+        }
+    }
+    for s in collect_ignored_services(stdout, cfg) {
+        log::info!(
+            "Not checked: {} [{}], excluded by {}",
+            s.name,
+            s.status,
+            s.rule
+        );
+    }
+}
+
+/// Handles a degraded decision according to `cfg.degraded_policy`; with a
+/// lenient normal boot or after a rollback the slot is never marked bad.
+fn finish_degraded(
+    deps: &OpenRcDeps<'_>,
+    cfg: &HealthConfig,
+    boot: BootAssessment,
+    strict: bool,
+    failed: Vec<FailedService>,
+) -> Result<CheckOutcome, String> {
+    let names: Vec<&str> = failed.iter().map(|f| f.name.as_str()).collect();
+    log::warn!(
+        "Boot degraded: optional services not started: {}",
+        names.join(", ")
+    );
+    match cfg.degraded_policy {
+        DegradedPolicy::MarkGood => accept(deps, boot, CheckOutcome::Degraded(failed)),
+        DegradedPolicy::Unmarked => {
+            log::info!("Degraded policy unmarked: not marking slot");
+            Ok(unmarked(boot, CheckOutcome::Degraded(failed)))
+        }
+        DegradedPolicy::MarkBad if !strict => {
+            log::warn!("Degraded normal boot, not marking slot bad (lenient policy)");
+            Ok(unmarked(boot, CheckOutcome::Degraded(failed)))
+        }
+        DegradedPolicy::MarkBad => reject(
+            deps,
+            boot,
+            format!(
+                "OpenRC health check degraded ({} failing optional services)",
+                failed.len()
+            ),
+        ),
+    }
+}
+
+/// Handles a bad decision: marks the slot bad, unless the boot is lenient
+/// or a rollback was detected.
+fn finish_bad(
+    deps: &OpenRcDeps<'_>,
+    boot: BootAssessment,
+    strict: bool,
+    failed: &[FailedService],
+) -> Result<CheckOutcome, String> {
+    if !strict {
+        log::warn!(
+            "{} failing services on normal boot, not marking slot bad (lenient policy)",
+            failed.len()
+        );
+        return Ok(unmarked(boot, CheckOutcome::NotMarked));
+    }
+    reject(
+        deps,
+        boot,
+        format!(
+            "OpenRC health check failed ({} failing services)",
+            failed.len()
+        ),
+    )
+}
+
+/// Outcome of leaving the slot unmarked. A detected rollback takes
+/// precedence over `outcome`.
+fn unmarked(boot: BootAssessment, outcome: CheckOutcome) -> CheckOutcome {
+    boot.rollback
+        .map_or(outcome, CheckOutcome::RollbackOccurred)
+}
+
+/// Marks the booted slot bad and fails with `error`.
+///
+/// After a rollback the booted slot is the one the bootloader fell back to,
+/// possibly the only bootable one: it is left unmarked and the rollback is
+/// reported instead, so the init script does not reboot into it again.
+fn reject(
+    deps: &OpenRcDeps<'_>,
+    boot: BootAssessment,
+    error: String,
+) -> Result<CheckOutcome, String> {
+    if let Some(rollback) = boot.rollback {
+        log::error!("{error}; not marking fallback slot {} bad", rollback.booted);
+        return Ok(CheckOutcome::RollbackOccurred(rollback));
+    }
+    deps.marker.mark_bad()?;
+    Err(error)
+}
+
+/// Marks the system good and confirms a trial boot. A detected rollback
+/// takes precedence over `outcome`.
+fn accept(
+    deps: &OpenRcDeps<'_>,
+    boot: BootAssessment,
+    outcome: CheckOutcome,
+) -> Result<CheckOutcome, String> {
+    deps.marker.mark_good()?;
+    if let Some(rollback) = boot.rollback {
+        return Ok(CheckOutcome::RollbackOccurred(rollback));
+    }
+    if boot.kind == BootKind::Trial {
+        if let Err(e) = (deps.confirm_boot)(&boot) {
+            log::warn!("failed to confirm trial boot: {e}");
+        }
+    }
+    Ok(outcome)
+}
+
+fn record(deps: &OpenRcDeps<'_>, decision: &HealthDecision) {
+    if let Err(e) = (deps.record_decision)(decision) {
+        log::warn!("failed to record health decision: {e}");
+    }
+}
@@ -0,0 +1,201 @@
+// Datei: src/openrc_state.rs
+//! Service states read directly from `OpenRC`'s state tree.
+//!
+//! Instead of spawning `rc-status` on every poll, [`StateTree`] reads
+//! `/run/openrc` (one directory per state, holding a link per service, and
+//! `softlevel` with the current runlevel) and `/etc/runlevels`, and renders
+//! the result in the format of `rc-status --nocolor --all`. This way it runs
+//! behind the same `run_rc_status` dependency and the same parser as the
+//! `rc-status` source.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// `OpenRC` state directory, relative to the root.
+pub const STATE_DIR: &str = "run/openrc";
+
+/// Runlevel directory, relative to the root.
+pub const RUNLEVELS_DIR: &str = "etc/runlevels";
+
+/// State directories, in the order in which they take precedence if a
+/// service is listed in more than one; a service in none is `stopped`.
+const STATES: [&str; 5] = ["stopping", "starting", "failed", "inactive", "started"];
+
+/// Reader for the `OpenRC` state tree below a root file system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateTree {
+    root: PathBuf,
+}
+
+impl StateTree {
+    /// Reads `run/openrc` and `etc/runlevels` below `root` (`/` on the
+    /// running system).
+    #[must_use]
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Current runlevel from `run/openrc/softlevel`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the file cannot be read, e.g. because
+    /// `OpenRC` has not started yet.
+    pub fn softlevel(&self) -> Result<String, String> {
+        let path = self.root.join(STATE_DIR).join("softlevel");
+        std::fs::read_to_string(&path)
+            .map(|s| s.trim().to_string())
+            .map_err(|e| format!("OpenRC state not available: {}: {e}", path.display()))
+    }
+
+    /// Renders the state tree like `rc-status --nocolor --all`.
+    ///
+    /// # Behavior
+    ///
+    /// - Lists every runlevel of `etc/runlevels` with the state of each of
+    ///   its services, sorted by name
+    /// - Services in `run/openrc/hotplugged` that are in no runlevel are
+    ///   listed under `Dynamic Runlevel: hotplugged`, other started services
+    ///   in no runlevel under `Dynamic Runlevel: manual` (`needed/wanted` is
+    ///   not told apart; the check treats all dynamic runlevels alike)
+    /// - Services in a directory below `run/openrc/scheduled` without any
+    ///   other state are `scheduled`
+    /// - `OpenRC` keeps no directory for crashed services: a started service
+    ///   is `crashed` if one of its daemons recorded in `run/openrc/daemons`
+    ///   is gone, like `rc-status` reports it (see [`StateTree::crashed`])
+    /// - Stacked runlevels inside a runlevel directory are skipped
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if `softlevel` is missing or a directory exists
+    /// but cannot be read.
+    pub fn read(&self) -> Result<String, String> {
+        let softlevel = self.softlevel()?;
+        log::debug!("OpenRC softlevel: {softlevel}");
+
+        let state_dir = self.root.join(STATE_DIR);
+        let mut states: BTreeMap<String, &str> = BTreeMap::new();
+        for state in STATES.iter().rev() {
+            for name in list_dir(&state_dir.join(state))? {
+                states.insert(name, state);
+            }
+        }
+        for trigger in list_dir(&state_dir.join("scheduled"))? {
+            for name in list_dir(&state_dir.join("scheduled").join(trigger))? {
+                states.entry(name).or_insert("scheduled");
+            }
+        }
+        for (name, state) in &mut states {
+            if *state == "started" && self.crashed(name)? {
+                *state = "crashed";
+            }
+        }
+        let state_of = |name: &str| states.get(name).copied().unwrap_or("stopped");
+
+        let runlevels = self.root.join(RUNLEVELS_DIR);
+        let mut out = String::new();
+        let mut in_runlevel = BTreeSet::new();
+        let names = list_dir(&runlevels)?;
+        for runlevel in &names {
+            let _ = writeln!(out, "Runlevel: {runlevel}");
+            let dir = runlevels.join(runlevel);
+            for name in list_dir(&dir)? {
+                let stacked = names.contains(&name)
+                    || std::fs::symlink_metadata(dir.join(&name)).is_ok_and(|m| m.is_dir());
+                if stacked {
+                    continue;
+                }
+                let _ = writeln!(out, " {name} [ {} ]", state_of(&name));
+                in_runlevel.insert(name);
+            }
+        }
+
+        let hotplugged: BTreeSet<String> = list_dir(&state_dir.join("hotplugged"))?
+            .into_iter()
+            .filter(|n| !in_runlevel.contains(n))
+            .collect();
+        let manual = states
+            .keys()
+            .filter(|n| !in_runlevel.contains(*n) && !hotplugged.contains(*n));
+        out.push_str("Dynamic Runlevel: hotplugged\n");
+        for name in &hotplugged {
+            let _ = writeln!(out, " {name} [ {} ]", state_of(name));
+        }
+        out.push_str("Dynamic Runlevel: manual\n");
+        for name in manual {
+            let _ = writeln!(out, " {name} [ {} ]", state_of(name));
+        }
+        Ok(out)
+    }
+
+    /// Returns `true` if a daemon started by service `name` is no longer
+    /// running.
+    ///
+    /// Each file in `run/openrc/daemons/<name>` describes one daemon. With a
+    /// `pidfile`, the daemon runs if the pid in it has an entry in `proc`;
+    /// without one, if a process in `proc` was started from its `exec` (or
+    /// `argv_0`). A missing or unreadable pid file counts as crashed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the daemon directory cannot be read.
+    pub fn crashed(&self, name: &str) -> Result<bool, String> {
+        let dir = self.root.join(STATE_DIR).join("daemons").join(name);
+        for daemon in list_dir(&dir)? {
+            let path = dir.join(daemon);
+            let info = std::fs::read_to_string(&path)
+                .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+            let value = |key: &str| {
+                info.lines()
+                    .filter_map(|l| l.split_once('='))
+                    .find(|(k, v)| *k == key && !v.is_empty())
+                    .map(|(_, v)| v)
+            };
+            let running = match value("pidfile") {
+                Some(pidfile) => self.pid_running(pidfile),
+                None => value("exec")
+                    .or_else(|| value("argv_0"))
+                    .is_some_and(|exec| self.exec_running(exec)),
+            };
+            if !running {
+                log::debug!("{name}: daemon {} is not running", path.display());
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn pid_running(&self, pidfile: &str) -> bool {
+        std::fs::read_to_string(self.root.join(pidfile.trim_start_matches('/')))
+            .ok()
+            .and_then(|s| s.trim().parse::<u32>().ok())
+            .is_some_and(|pid| self.root.join("proc").join(pid.to_string()).exists())
+    }
+
+    fn exec_running(&self, exec: &str) -> bool {
+        let Ok(entries) = std::fs::read_dir(self.root.join("proc")) else {
+            return false;
+        };
+        entries.flatten().any(|e| {
+            std::fs::read(e.path().join("cmdline"))
+                .is_ok_and(|c| c.split(|b| *b == 0).next() == Some(exec.as_bytes()))
+        })
+    }
+}
+
+/// Sorted entry names of `dir`; a missing directory is empty.
+fn list_dir(dir: &Path) -> Result<Vec<String>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("cannot read {}: {e}", dir.display())),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("cannot read {}: {e}", dir.display()))?;
+        names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    Ok(names)
+}
@@ -1,79 +1,633 @@
-use crate::cmd::{run_checked, CmdRunner, RealCmdRunner};
+use crate::cmd::{check_output, run_stdout, CmdError, CmdRunner, RealCmdRunner, DEFAULT_TIMEOUT};
+use crate::config::{HealthConfig, RetryConfig};
+use crate::marker::{SlotBootState, SlotMarker};
+use std::time::{Duration, Instant};
 
-/// Marks the currently active RAUC slot as **good**.
+/// Marks the currently active RAUC slot as **good** via `runner`.
 ///
-/// This is the production entry point using the real command runner.
+/// # Behavior
+///
+/// Executes:
+///
+/// ```text
+/// rauc status mark-good
+/// ```
 ///
 /// # Errors
 ///
-/// Returns `Err(String)` if invoking the `rauc` command fails or if the command
-/// exits with a non-zero status.
-pub fn mark_good() -> Result<(), String> {
-    mark_good_with(&RealCmdRunner)
+/// Returns `Err(String)` if:
+/// - the command cannot be executed
+/// - the command exits with a non-zero exit status
+pub fn mark_good_with(runner: &dyn CmdRunner) -> Result<(), String> {
+    mark_slot_with(runner, MarkState::Good, &SlotIdentifier::Booted)
 }
 
-/// Marks the currently active RAUC slot as **bad**.
+/// Marks the currently active RAUC slot as **bad** via `runner`.
+///
+/// # Behavior
+///
+/// Executes:
 ///
-/// This is the production entry point using the real command runner.
+/// ```text
+/// rauc status mark-bad
+/// ```
 ///
 /// # Errors
 ///
-/// Returns `Err(String)` if invoking the `rauc` command fails or if the command
-/// exits with a non-zero status.
-pub fn mark_bad() -> Result<(), String> {
-    mark_bad_with(&RealCmdRunner)
+/// Returns `Err(String)` if:
+/// - the command cannot be executed
+/// - the command exits with a non-zero exit status
+pub fn mark_bad_with(runner: &dyn CmdRunner) -> Result<(), String> {
+    mark_slot_with(runner, MarkState::Bad, &SlotIdentifier::Booted)
+}
+
+/// Slot a mark operation applies to, as understood by
+/// `rauc status mark-good|mark-bad|mark-active [booted|other|<SLOT_NAME>]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlotIdentifier {
+    /// The currently booted slot (RAUC's default).
+    Booted,
+    /// The other slot of the booted slot's class (A/B setups).
+    Other,
+    /// A slot given by its RAUC slot name, e.g. `rootfs.1`.
+    Named(String),
+}
+
+impl SlotIdentifier {
+    /// Returns the identifier as passed to RAUC.
+    #[must_use]
+    pub fn as_arg(&self) -> &str {
+        match self {
+            Self::Booted => "booted",
+            Self::Other => "other",
+            Self::Named(name) => name,
+        }
+    }
+}
+
+impl std::str::FromStr for SlotIdentifier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" => Err("slot identifier must not be empty".to_string()),
+            "booted" => Ok(Self::Booted),
+            "other" => Ok(Self::Other),
+            name => Ok(Self::Named(name.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for SlotIdentifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_arg())
+    }
+}
+
+/// State a slot can be marked with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkState {
+    Good,
+    Bad,
+    /// Make the slot the primary boot target.
+    Active,
+}
+
+impl MarkState {
+    /// Returns the state as used by RAUC (`good`, `bad`, `active`).
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Good => "good",
+            Self::Bad => "bad",
+            Self::Active => "active",
+        }
+    }
+}
+
+impl std::fmt::Display for MarkState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-/// Testable variant of [`mark_good`] using an injected [`CmdRunner`].
+/// Marks `slot` with `state` via `runner`.
 ///
 /// # Behavior
 ///
-/// Executes:
+/// Named slots are first validated against the slot list reported by
+/// [`status_with`]. Then executes:
 ///
 /// ```text
-/// rauc status mark-good
+/// rauc status mark-<state> [other|<SLOT_NAME>]
 /// ```
 ///
+/// The slot argument is omitted for [`SlotIdentifier::Booted`], which is
+/// RAUC's default.
+///
 /// # Errors
 ///
 /// Returns `Err(String)` if:
+/// - a named slot does not exist (or the slot list cannot be queried)
 /// - the command cannot be executed
 /// - the command exits with a non-zero exit status
-pub fn mark_good_with(runner: &dyn CmdRunner) -> Result<(), String> {
-    log::info!("Marking current RAUC slot as GOOD…");
-    run_checked(
-        runner,
-        "rauc",
-        &["status", "mark-good"],
-        "rauc status mark-good",
-    )?;
-    log::info!("Successfully marked slot as GOOD.");
+pub fn mark_slot_with(
+    runner: &dyn CmdRunner,
+    state: MarkState,
+    slot: &SlotIdentifier,
+) -> Result<(), String> {
+    try_mark_slot_with(runner, state, slot).map_err(|e| e.to_string())
+}
+
+/// Like [`mark_slot_with`], but classifies failures as [`MarkError`].
+///
+/// # Errors
+///
+/// Returns [`MarkError::NotReachable`] if the command output indicates that
+/// the RAUC service is not up yet, and [`MarkError::Rejected`] for any other
+/// failure.
+pub fn try_mark_slot_with(
+    runner: &dyn CmdRunner,
+    state: MarkState,
+    slot: &SlotIdentifier,
+) -> Result<(), MarkError> {
+    if let SlotIdentifier::Named(name) = slot {
+        let status = status_with(runner).map_err(|e| MarkError::classify(&e, e.clone()))?;
+        validate_slot_name(&status, name).map_err(MarkError::Rejected)?;
+    }
+
+    let target = match slot {
+        SlotIdentifier::Booted => "current RAUC slot".to_string(),
+        other => format!("RAUC slot {other}"),
+    };
+    let upper = state.as_str().to_uppercase();
+    if state == MarkState::Bad {
+        log::warn!("Marking {target} as {upper}…");
+    } else {
+        log::info!("Marking {target} as {upper}…");
+    }
+
+    let verb = format!("mark-{state}");
+    let mut args = vec!["status", verb.as_str()];
+    if *slot != SlotIdentifier::Booted {
+        args.push(slot.as_arg());
+    }
+    let ctx = format!("rauc {}", args.join(" "));
+    check_output(runner, "rauc", &args, &ctx, DEFAULT_TIMEOUT).map_err(|e| match e {
+        CmdError::Spawn(e) => MarkError::Rejected(e),
+        timed_out @ CmdError::TimedOut { .. } => MarkError::NotReachable(timed_out.to_string()),
+        failed => MarkError::classify(failed.stderr_tail(), failed.to_string()),
+    })?;
+
+    if state == MarkState::Bad {
+        log::warn!("Successfully marked slot as {upper}.");
+    } else {
+        log::info!("Successfully marked slot as {upper}.");
+    }
     Ok(())
 }
 
-/// Testable variant of [`mark_bad`] using an injected [`CmdRunner`].
+/// Output fragments of `rauc` meaning the RAUC D-Bus service is not up yet.
+const NOT_REACHABLE_PATTERNS: &[&str] = &[
+    "ServiceUnknown",
+    "NameHasNoOwner",
+    "was not provided by any .service files",
+    "Could not connect",
+    "Failed to connect",
+    "Error creating proxy",
+    "Timeout was reached",
+    "NoReply",
+];
+
+/// Failure of a mark request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkError {
+    /// RAUC could not be reached (service not started yet); worth retrying.
+    NotReachable(String),
+    /// RAUC rejected the request, or `rauc` could not be run at all.
+    Rejected(String),
+}
+
+impl MarkError {
+    /// Builds a [`MarkError::NotReachable`] if `output` indicates that the
+    /// RAUC service is not reachable, and [`MarkError::Rejected`] otherwise.
+    #[must_use]
+    pub fn classify(output: &str, message: String) -> Self {
+        if NOT_REACHABLE_PATTERNS.iter().any(|p| output.contains(p)) {
+            Self::NotReachable(message)
+        } else {
+            Self::Rejected(message)
+        }
+    }
+}
+
+impl std::fmt::Display for MarkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotReachable(e) | Self::Rejected(e) => f.write_str(e),
+        }
+    }
+}
+
+/// How often and how long a mark request is retried while RAUC is not
+/// reachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one.
+    pub attempts: u32,
+    /// Delay before the first retry; doubled after each retry.
+    pub backoff: Duration,
+    /// Upper bound for the total time spent, including the delays.
+    pub max_total: Duration,
+}
+
+impl RetryPolicy {
+    /// Policy that tries exactly once.
+    #[must_use]
+    pub fn none() -> Self {
+        Self {
+            attempts: 1,
+            backoff: Duration::ZERO,
+            max_total: Duration::ZERO,
+        }
+    }
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    fn from(cfg: &RetryConfig) -> Self {
+        Self {
+            attempts: cfg.attempts.max(1),
+            backoff: Duration::from_millis(cfg.backoff_ms),
+            max_total: Duration::from_secs(cfg.max_total_seconds),
+        }
+    }
+}
+
+/// Runs `op` until it succeeds, fails with [`MarkError::Rejected`], or
+/// `policy` is exhausted.
+///
+/// A retry is only started if it still fits into `policy.max_total`.
+///
+/// # Errors
+///
+/// Returns the rejection, or the last [`MarkError::NotReachable`] once the
+/// policy is exhausted.
+pub fn retry_with<T>(
+    policy: &RetryPolicy,
+    sleep: &dyn Fn(Duration),
+    now: &dyn Fn() -> Instant,
+    op: &mut dyn FnMut() -> Result<T, MarkError>,
+) -> Result<T, MarkError> {
+    let start = now();
+    let mut delay = policy.backoff;
+    let mut attempt = 1;
+    loop {
+        let e = match op() {
+            Err(MarkError::NotReachable(e)) => e,
+            res => return res,
+        };
+        let elapsed = now().saturating_duration_since(start);
+        if attempt >= policy.attempts || elapsed + delay > policy.max_total {
+            log::error!("RAUC still not reachable after {attempt} attempt(s), giving up");
+            return Err(MarkError::NotReachable(e));
+        }
+        log::warn!(
+            "RAUC not reachable ({e}), retrying in {} ms (attempt {attempt}/{})",
+            delay.as_millis(),
+            policy.attempts
+        );
+        sleep(delay);
+        delay = delay.saturating_mul(2);
+        attempt += 1;
+    }
+}
+
+/// Checks that `name` is one of the slots in `status`.
+///
+/// # Errors
+///
+/// Returns `Err(String)` listing the available slots if `name` is unknown.
+pub fn validate_slot_name(status: &RaucStatus, name: &str) -> Result<(), String> {
+    if status.slot(name).is_some() {
+        return Ok(());
+    }
+    let known: Vec<&str> = status.slots.iter().map(|s| s.name.as_str()).collect();
+    Err(format!(
+        "unknown RAUC slot `{name}` (available: {})",
+        known.join(", ")
+    ))
+}
+
+//
+// ============================
+// Status (`rauc status --output-format=json`)
+// ============================
+//
+
+/// Boot status of a slot as reported by the bootloader backend of RAUC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BootStatus {
+    Good,
+    Bad,
+    /// Any other value reported by RAUC (kept verbatim).
+    Other(String),
+}
+
+impl BootStatus {
+    fn parse(s: &str) -> Self {
+        match s {
+            "good" => Self::Good,
+            "bad" => Self::Bad,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for BootStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Good => f.write_str("good"),
+            Self::Bad => f.write_str("bad"),
+            Self::Other(s) => f.write_str(s),
+        }
+    }
+}
+
+/// A single slot from `rauc status --detailed`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotInfo {
+    /// Slot name, e.g. `rootfs.0`.
+    pub name: String,
+    /// Slot class, e.g. `rootfs`.
+    pub class: String,
+    pub device: String,
+    pub slot_type: Option<String>,
+    /// Name the bootloader uses for this slot (e.g. `A`).
+    pub bootname: Option<String>,
+    /// RAUC slot state: `booted`, `active` or `inactive`.
+    pub state: String,
+    pub parent: Option<String>,
+    pub mountpoint: Option<String>,
+    pub boot_status: Option<BootStatus>,
+    /// Version of the bundle installed into this slot.
+    pub bundle_version: Option<String>,
+    /// Time of the last installation into this slot.
+    pub installed: Option<jiff::Timestamp>,
+    /// Number of installations into this slot.
+    pub installed_count: Option<u64>,
+    /// Time this slot was last activated.
+    pub activated: Option<jiff::Timestamp>,
+}
+
+/// Typed view of `rauc status --detailed --output-format=json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RaucStatus {
+    pub compatible: String,
+    pub variant: String,
+    /// Bootname of the booted slot.
+    pub booted: Option<String>,
+    /// Slot name of the primary boot slot.
+    pub boot_primary: Option<String>,
+    pub slots: Vec<SlotInfo>,
+}
+
+impl RaucStatus {
+    /// Returns the slot with the given name.
+    #[must_use]
+    pub fn slot(&self, name: &str) -> Option<&SlotInfo> {
+        self.slots.iter().find(|s| s.name == name)
+    }
+
+    /// Returns the currently booted slot.
+    #[must_use]
+    pub fn booted_slot(&self) -> Option<&SlotInfo> {
+        self.slots.iter().find(|s| s.state == "booted").or_else(|| {
+            let booted = self.booted.as_deref()?;
+            self.slots
+                .iter()
+                .find(|s| s.bootname.as_deref() == Some(booted))
+        })
+    }
+}
+
+mod raw {
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Deserialize)]
+    pub struct Status {
+        #[serde(default)]
+        pub compatible: Option<String>,
+        #[serde(default)]
+        pub variant: Option<String>,
+        #[serde(default)]
+        pub booted: Option<String>,
+        #[serde(default)]
+        pub boot_primary: Option<String>,
+        #[serde(default)]
+        pub slots: Vec<BTreeMap<String, Slot>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Slot {
+        #[serde(default)]
+        pub class: Option<String>,
+        #[serde(default)]
+        pub device: Option<String>,
+        #[serde(default, rename = "type")]
+        pub kind: Option<String>,
+        #[serde(default)]
+        pub bootname: Option<String>,
+        #[serde(default)]
+        pub state: Option<String>,
+        #[serde(default)]
+        pub parent: Option<String>,
+        #[serde(default)]
+        pub mountpoint: Option<String>,
+        #[serde(default)]
+        pub boot_status: Option<String>,
+        #[serde(default, rename = "slot_status")]
+        pub status: Option<SlotStatus>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SlotStatus {
+        #[serde(default)]
+        pub bundle: Option<Bundle>,
+        #[serde(default)]
+        pub installed: Option<Event>,
+        #[serde(default)]
+        pub activated: Option<Event>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Bundle {
+        #[serde(default)]
+        pub version: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Event {
+        #[serde(default)]
+        pub timestamp: Option<String>,
+        #[serde(default)]
+        pub count: Option<u64>,
+    }
+}
+
+fn parse_timestamp(
+    slot: &str,
+    what: &str,
+    ts: Option<String>,
+) -> Result<Option<jiff::Timestamp>, String> {
+    ts.map(|t| {
+        t.parse::<jiff::Timestamp>()
+            .map_err(|e| format!("invalid {what} timestamp `{t}` for slot {slot}: {e}"))
+    })
+    .transpose()
+}
+
+/// Parses the JSON output of `rauc status --detailed --output-format=json`.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the input is not valid JSON, does not match the
+/// expected structure, or contains invalid timestamps.
+pub fn parse_status_json(json: &str) -> Result<RaucStatus, String> {
+    let raw: raw::Status =
+        serde_json::from_str(json).map_err(|e| format!("invalid rauc status JSON: {e}"))?;
+
+    let mut slots = Vec::new();
+    for entry in raw.slots {
+        for (name, s) in entry {
+            let status = s.status;
+            let (bundle, installed, activated) = match status {
+                Some(st) => (st.bundle, st.installed, st.activated),
+                None => (None, None, None),
+            };
+            let (installed_ts, installed_count) =
+                installed.map_or((None, None), |e| (e.timestamp, e.count));
+
+            slots.push(SlotInfo {
+                class: s.class.unwrap_or_default(),
+                device: s.device.unwrap_or_default(),
+                slot_type: s.kind,
+                bootname: s.bootname,
+                state: s.state.unwrap_or_default(),
+                parent: s.parent,
+                mountpoint: s.mountpoint,
+                boot_status: s.boot_status.as_deref().map(BootStatus::parse),
+                bundle_version: bundle.and_then(|b| b.version),
+                installed: parse_timestamp(&name, "installed", installed_ts)?,
+                installed_count,
+                activated: parse_timestamp(
+                    &name,
+                    "activated",
+                    activated.and_then(|e| e.timestamp),
+                )?,
+                name,
+            });
+        }
+    }
+
+    Ok(RaucStatus {
+        compatible: raw.compatible.unwrap_or_default(),
+        variant: raw.variant.unwrap_or_default(),
+        booted: raw.booted,
+        boot_primary: raw.boot_primary,
+        slots,
+    })
+}
+
+/// Queries RAUC for the detailed slot status via `runner`.
 ///
 /// # Behavior
 ///
 /// Executes:
 ///
 /// ```text
-/// rauc status mark-bad
+/// rauc status --detailed --output-format=json
 /// ```
 ///
 /// # Errors
 ///
-/// Returns `Err(String)` if:
-/// - the command cannot be executed
-/// - the command exits with a non-zero exit status
-pub fn mark_bad_with(runner: &dyn CmdRunner) -> Result<(), String> {
-    log::warn!("Marking current RAUC slot as BAD…");
-    run_checked(
+/// Returns `Err(String)` if the command fails or its output cannot be parsed.
+pub fn status_with(runner: &dyn CmdRunner) -> Result<RaucStatus, String> {
+    let json = run_stdout(
         runner,
         "rauc",
-        &["status", "mark-bad"],
-        "rauc status mark-bad",
+        &["status", "--detailed", "--output-format=json"],
+        "rauc status --detailed --output-format=json",
     )?;
-    log::warn!("Successfully marked slot as BAD.");
-    Ok(())
+    parse_status_json(&json)
+}
+
+/// [`SlotMarker`] backed by the `rauc` binary.
+///
+/// Mark requests are retried according to its [`RetryPolicy`] while RAUC is
+/// not reachable.
+pub struct CliMarker<'a> {
+    runner: Box<dyn CmdRunner + 'a>,
+    retry: RetryPolicy,
+}
+
+impl<'a> CliMarker<'a> {
+    /// Creates a marker running `rauc` via `runner`, without retries.
+    #[must_use]
+    pub fn new(runner: impl CmdRunner + 'a) -> Self {
+        Self {
+            runner: Box::new(runner),
+            retry: RetryPolicy::none(),
+        }
+    }
+
+    /// Sets the retry policy for mark requests.
+    #[must_use]
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+impl CliMarker<'static> {
+    /// Creates the marker from `[commands]` and `[retry]` of `cfg`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if a program path in `[commands]` is invalid.
+    pub fn from_config(cfg: &HealthConfig) -> Result<Self, String> {
+        Ok(Self::new(RealCmdRunner::new(&cfg.commands)?).with_retry((&cfg.retry).into()))
+    }
+}
+
+impl SlotMarker for CliMarker<'_> {
+    fn mark(&self, state: MarkState, slot: &SlotIdentifier) -> Result<(), String> {
+        retry_with(&self.retry, &std::thread::sleep, &Instant::now, &mut || {
+            try_mark_slot_with(&*self.runner, state, slot)
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String> {
+        Ok(slot_boot_states(&status_with(&*self.runner)?))
+    }
+}
+
+/// Derives [`SlotBootState`]s from RAUC's status.
+///
+/// RAUC only reports good or bad, so a good slot counts as one remaining
+/// attempt. Only the primary slot has a known position in the boot order.
+#[must_use]
+pub fn slot_boot_states(status: &RaucStatus) -> Vec<SlotBootState> {
+    status
+        .slots
+        .iter()
+        .filter_map(|s| {
+            Some(SlotBootState {
+                bootname: s.bootname.clone()?,
+                order: (status.boot_primary.as_deref() == Some(s.name.as_str())).then_some(0),
+                remaining_attempts: u32::from(s.boot_status != Some(BootStatus::Bad)),
+            })
+        })
+        .collect()
 }
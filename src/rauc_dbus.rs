@@ -0,0 +1,303 @@
+// Datei: src/rauc_dbus.rs
+//! Native RAUC backend talking to `de.pengutronix.rauc` over D-Bus.
+//!
+//! This avoids spawning the `rauc` binary and gives structured replies.
+//! When the bus itself cannot be reached, the production entry points fall
+//! back to the CLI backend in [`crate::rauc`].
+
+use crate::cmd::RealCmdRunner;
+use crate::config::HealthConfig;
+use crate::dbus::{self, Connection, Value};
+use crate::marker::{SlotBootState, SlotMarker};
+use crate::rauc::{retry_with, CliMarker, MarkError, MarkState, RetryPolicy, SlotIdentifier};
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// Well-known bus name of the RAUC service.
+pub const SERVICE: &str = "de.pengutronix.rauc";
+/// Object path of the RAUC installer object.
+pub const OBJECT_PATH: &str = "/";
+/// Interface implementing `Mark`, `GetSlotStatus` and friends.
+pub const INSTALLER_IFACE: &str = "de.pengutronix.rauc.Installer";
+
+/// Status of a single slot as returned by `GetSlotStatus`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotStatus {
+    /// Slot name, e.g. `rootfs.0`.
+    pub name: String,
+    /// Raw slot properties (`class`, `bootname`, `state`, `boot-status`, ...).
+    pub properties: BTreeMap<String, Value>,
+}
+
+impl SlotStatus {
+    /// Returns the string property `key`, if present.
+    #[must_use]
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.properties
+            .get(key)
+            .and_then(|v| v.unwrap_variant().as_str())
+    }
+}
+
+/// Calls `Installer.Mark(state, slot_identifier)`.
+///
+/// # Returns
+///
+/// The name of the slot RAUC actually marked and RAUC's status message.
+///
+/// # Errors
+///
+/// Returns [`dbus::Error::Remote`] if RAUC rejects the request and
+/// [`dbus::Error::Transport`] on connection problems.
+pub fn mark_with(
+    conn: &mut Connection,
+    state: &str,
+    slot_identifier: &str,
+) -> Result<(String, String), dbus::Error> {
+    let reply = conn.call(
+        SERVICE,
+        OBJECT_PATH,
+        INSTALLER_IFACE,
+        "Mark",
+        vec![
+            Value::Str(state.to_string()),
+            Value::Str(slot_identifier.to_string()),
+        ],
+    )?;
+
+    let mut it = reply
+        .iter()
+        .map(|v| v.as_str().unwrap_or_default().to_string());
+    let slot = it.next().unwrap_or_default();
+    let message = it.next().unwrap_or_default();
+    Ok((slot, message))
+}
+
+/// Calls `Installer.GetSlotStatus()` and decodes the `a(sa{sv})` reply.
+///
+/// # Errors
+///
+/// Returns any D-Bus error, or [`dbus::Error::Transport`] if the reply has
+/// an unexpected shape.
+pub fn get_slot_status(conn: &mut Connection) -> Result<Vec<SlotStatus>, dbus::Error> {
+    let reply = conn.call(
+        SERVICE,
+        OBJECT_PATH,
+        INSTALLER_IFACE,
+        "GetSlotStatus",
+        vec![],
+    )?;
+    let Some(Value::Array(_, slots)) = reply.into_iter().next() else {
+        return Err(dbus::Error::Transport(
+            "unexpected GetSlotStatus reply".into(),
+        ));
+    };
+
+    let mut out = Vec::with_capacity(slots.len());
+    for slot in slots {
+        let Value::Struct(mut fields) = slot else {
+            continue;
+        };
+        if fields.len() != 2 {
+            continue;
+        }
+        let props = fields.pop();
+        let name = fields.pop();
+        let (Some(Value::Str(name)), Some(Value::Array(_, entries))) = (name, props) else {
+            continue;
+        };
+
+        let mut properties = BTreeMap::new();
+        for e in entries {
+            if let Value::DictEntry(k, v) = e {
+                if let Value::Str(k) = *k {
+                    properties.insert(k, *v);
+                }
+            }
+        }
+        out.push(SlotStatus { name, properties });
+    }
+    Ok(out)
+}
+
+fn string_property(conn: &mut Connection, name: &str) -> Result<String, dbus::Error> {
+    let v = conn.get_property(SERVICE, OBJECT_PATH, INSTALLER_IFACE, name)?;
+    v.as_str()
+        .map(str::to_string)
+        .ok_or_else(|| dbus::Error::Transport(format!("property `{name}` is not a string")))
+}
+
+/// Reads the `BootSlot` property (bootname of the booted slot).
+///
+/// # Errors
+///
+/// Returns any D-Bus error produced while reading the property.
+pub fn boot_slot(conn: &mut Connection) -> Result<String, dbus::Error> {
+    string_property(conn, "BootSlot")
+}
+
+/// Reads the `Operation` property (`idle` or `installing`).
+///
+/// # Errors
+///
+/// Returns any D-Bus error produced while reading the property.
+pub fn operation(conn: &mut Connection) -> Result<String, dbus::Error> {
+    string_property(conn, "Operation")
+}
+
+/// Marks `slot_identifier` with `state` over D-Bus, using `fallback` if the
+/// bus cannot be reached.
+///
+/// `connect` is called for every attempt. Transport errors trigger the
+/// fallback; while RAUC has not claimed its bus name (see
+/// [`NOT_REACHABLE_ERRORS`]) the request is retried according to `retry`.
+/// Any other error is a request rejected by RAUC. While RAUC reports an
+/// [`operation`] other than `idle`, e.g. an installation, nothing is marked.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if RAUC is busy, rejects the request, stays
+/// unreachable until `retry` is exhausted, or the fallback fails.
+pub fn mark_or_fallback(
+    connect: &dyn Fn() -> Result<Connection, dbus::Error>,
+    state: &str,
+    slot_identifier: &str,
+    retry: &RetryPolicy,
+    fallback: &dyn Fn() -> Result<(), String>,
+) -> Result<(), String> {
+    // Ok(false): the bus itself is not reachable, use the fallback instead.
+    let res = retry_with(
+        retry,
+        &std::thread::sleep,
+        &Instant::now,
+        &mut || match connect().and_then(|mut c| {
+            let op = operation(&mut c)?;
+            if op != "idle" {
+                return Ok(Err(op));
+            }
+            mark_with(&mut c, state, slot_identifier).map(Ok)
+        }) {
+            Ok(Ok((slot, message))) => {
+                log::info!("RAUC marked slot {slot} as {state}: {message}");
+                Ok(true)
+            }
+            Ok(Err(op)) => Err(MarkError::Rejected(format!(
+                "RAUC is busy ({op}), not running mark-{state}"
+            ))),
+            Err(dbus::Error::Transport(e)) => {
+                log::warn!("RAUC D-Bus service not reachable ({e}), falling back to rauc CLI");
+                Ok(false)
+            }
+            Err(e @ dbus::Error::Remote { .. }) => match &e {
+                dbus::Error::Remote { name, .. }
+                    if NOT_REACHABLE_ERRORS.contains(&name.as_str()) =>
+                {
+                    Err(MarkError::NotReachable(format!(
+                        "RAUC not reachable for mark-{state}: {e}"
+                    )))
+                }
+                _ => Err(MarkError::Rejected(format!(
+                    "RAUC rejected mark-{state}: {e}"
+                ))),
+            },
+        },
+    );
+
+    match res {
+        Ok(true) => Ok(()),
+        Ok(false) => fallback(),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// [`SlotMarker`] talking to RAUC on the system bus.
+///
+/// Like [`mark_or_fallback`], it falls back to the CLI backend if the bus is
+/// not reachable. While the bus is up but RAUC has not claimed its name yet,
+/// requests are retried according to the [`RetryPolicy`].
+#[derive(Debug, Clone)]
+pub struct DbusMarker {
+    runner: RealCmdRunner,
+    retry: RetryPolicy,
+}
+
+impl DbusMarker {
+    /// Creates a marker retrying according to `retry` and falling back to
+    /// `rauc` run via `runner`.
+    #[must_use]
+    pub fn new(runner: RealCmdRunner, retry: RetryPolicy) -> Self {
+        Self { runner, retry }
+    }
+
+    /// Creates the marker from `[commands]` and `[retry]` of `cfg`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if a program path in `[commands]` is invalid.
+    pub fn from_config(cfg: &HealthConfig) -> Result<Self, String> {
+        Ok(Self::new(
+            RealCmdRunner::new(&cfg.commands)?,
+            (&cfg.retry).into(),
+        ))
+    }
+
+    /// CLI backend used if the bus is not reachable.
+    fn cli(&self) -> CliMarker<'_> {
+        CliMarker::new(&self.runner).with_retry(self.retry)
+    }
+}
+
+impl Default for DbusMarker {
+    fn default() -> Self {
+        Self::new(RealCmdRunner::default(), RetryPolicy::none())
+    }
+}
+
+/// D-Bus errors meaning RAUC has not (yet) claimed its bus name.
+pub const NOT_REACHABLE_ERRORS: &[&str] = &[
+    "org.freedesktop.DBus.Error.ServiceUnknown",
+    "org.freedesktop.DBus.Error.NameHasNoOwner",
+    "org.freedesktop.DBus.Error.NoReply",
+];
+
+impl SlotMarker for DbusMarker {
+    fn mark(&self, state: MarkState, slot: &SlotIdentifier) -> Result<(), String> {
+        mark_or_fallback(
+            &Connection::system,
+            state.as_str(),
+            slot.as_arg(),
+            &self.retry,
+            &|| self.cli().mark(state, slot),
+        )
+    }
+
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String> {
+        let slots = match Connection::system().and_then(|mut c| get_slot_status(&mut c)) {
+            Ok(slots) => slots,
+            Err(dbus::Error::Transport(e)) => {
+                log::warn!("RAUC D-Bus service not reachable ({e}), falling back to rauc CLI");
+                return self.cli().slot_states();
+            }
+            Err(e) => return Err(format!("RAUC GetSlotStatus failed: {e}")),
+        };
+        Ok(slot_boot_states(&slots))
+    }
+}
+
+/// Derives [`SlotBootState`]s from a `GetSlotStatus` reply.
+///
+/// The reply carries no boot order; a slot that is not `bad` counts as one
+/// remaining attempt.
+#[must_use]
+pub fn slot_boot_states(slots: &[SlotStatus]) -> Vec<SlotBootState> {
+    slots
+        .iter()
+        .filter_map(|s| {
+            Some(SlotBootState {
+                bootname: s.get_str("bootname")?.to_string(),
+                order: None,
+                remaining_attempts: u32::from(s.get_str("boot-status") != Some("bad")),
+            })
+        })
+        .collect()
+}
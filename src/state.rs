@@ -0,0 +1,206 @@
+// Datei: src/state.rs
+//! Persistent state of rauc-health below the configured state directory.
+
+use crate::openrc::{FailedService, HealthDecision};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+/// File name of the last recorded health decision.
+pub const LAST_DECISION_FILE: &str = "last-decision.toml";
+/// File name of the record written after an installation.
+pub const PENDING_UPDATE_FILE: &str = "pending-update.toml";
+/// File name of the record written once a slot has been confirmed good.
+pub const CONFIRMED_SLOT_FILE: &str = "confirmed-slot.toml";
+
+/// Health decision as persisted after a check run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionRecord {
+    /// RFC 3339 time of the decision.
+    pub timestamp: String,
+    /// `good`, `degraded` or `bad`.
+    pub decision: String,
+    /// Services that caused a `degraded` or `bad` decision.
+    #[serde(default)]
+    pub failed_services: Vec<FailedService>,
+}
+
+impl DecisionRecord {
+    /// Builds a record for `decision` taken at `now`.
+    #[must_use]
+    pub fn new(decision: &HealthDecision, now: jiff::Timestamp) -> Self {
+        let (name, failed) = match decision {
+            HealthDecision::Good => ("good", Vec::new()),
+            HealthDecision::Degraded(failed) => ("degraded", failed.clone()),
+            HealthDecision::Bad(failed) => ("bad", failed.clone()),
+        };
+        Self {
+            timestamp: now.to_string(),
+            decision: name.to_string(),
+            failed_services: failed,
+        }
+    }
+}
+
+/// Installation that has not been confirmed by a health check yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingUpdate {
+    /// RAUC slot name the bundle was installed to, e.g. `rootfs.1`.
+    pub slot: String,
+    /// Bootname of that slot, if known.
+    #[serde(default)]
+    pub bootname: Option<String>,
+    /// Version of the installed bundle, if known.
+    #[serde(default)]
+    pub bundle_version: Option<String>,
+    /// RFC 3339 time the record was written.
+    pub timestamp: String,
+}
+
+/// Slot installation that passed the strict health gate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmedSlot {
+    /// RAUC slot name.
+    pub slot: String,
+    /// Installation timestamp RAUC reported for the slot when it was confirmed.
+    #[serde(default)]
+    pub installed: Option<String>,
+    /// RFC 3339 time of the confirmation.
+    pub timestamp: String,
+}
+
+/// Writes `contents` to `path` atomically (temporary file + rename).
+///
+/// Missing parent directories are created.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the directory cannot be created or the file
+/// cannot be written, synced or renamed.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    }
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = std::path::PathBuf::from(tmp);
+
+    let mut f = std::fs::File::create(&tmp)
+        .map_err(|e| format!("failed to create {}: {e}", tmp.display()))?;
+    f.write_all(contents)
+        .and_then(|()| f.sync_all())
+        .map_err(|e| format!("failed to write {}: {e}", tmp.display()))?;
+    std::fs::rename(&tmp, path).map_err(|e| {
+        format!(
+            "failed to rename {} to {}: {e}",
+            tmp.display(),
+            path.display()
+        )
+    })
+}
+
+/// Persists `record` as last decision in `state_dir`.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the record cannot be serialized or written.
+pub fn write_last_decision(state_dir: &Path, record: &DecisionRecord) -> Result<(), String> {
+    write_record(&state_dir.join(LAST_DECISION_FILE), record)
+}
+
+/// Reads the last decision from `state_dir`.
+///
+/// # Returns
+///
+/// `Ok(None)` if no decision has been recorded yet.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the file exists but cannot be read or parsed.
+pub fn read_last_decision(state_dir: &Path) -> Result<Option<DecisionRecord>, String> {
+    read_record(&state_dir.join(LAST_DECISION_FILE))
+}
+
+fn read_record<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let s = match std::fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("failed to read {}: {e}", path.display())),
+    };
+    toml::from_str(&s)
+        .map(Some)
+        .map_err(|e| format!("failed to parse {}: {e}", path.display()))
+}
+
+fn write_record<T: Serialize>(path: &Path, record: &T) -> Result<(), String> {
+    let s = toml::to_string(record)
+        .map_err(|e| format!("failed to serialize {}: {e}", path.display()))?;
+    write_atomic(path, s.as_bytes())
+}
+
+fn remove_record(path: &Path) -> Result<(), String> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("failed to remove {}: {e}", path.display())),
+    }
+}
+
+/// Stores the pending-update record in `state_dir`.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the record cannot be written.
+pub fn write_pending_update(state_dir: &Path, record: &PendingUpdate) -> Result<(), String> {
+    write_record(&state_dir.join(PENDING_UPDATE_FILE), record)
+}
+
+/// Reads the pending-update record, if any.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the file exists but cannot be read or parsed.
+pub fn read_pending_update(state_dir: &Path) -> Result<Option<PendingUpdate>, String> {
+    read_record(&state_dir.join(PENDING_UPDATE_FILE))
+}
+
+/// Removes the pending-update record; a missing record is not an error.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the file exists but cannot be removed.
+pub fn clear_pending_update(state_dir: &Path) -> Result<(), String> {
+    remove_record(&state_dir.join(PENDING_UPDATE_FILE))
+}
+
+/// Stores the confirmed-slot record in `state_dir`.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the record cannot be written.
+pub fn write_confirmed_slot(state_dir: &Path, record: &ConfirmedSlot) -> Result<(), String> {
+    write_record(&state_dir.join(CONFIRMED_SLOT_FILE), record)
+}
+
+/// Reads the confirmed-slot record, if any.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the file exists but cannot be read or parsed.
+pub fn read_confirmed_slot(state_dir: &Path) -> Result<Option<ConfirmedSlot>, String> {
+    read_record(&state_dir.join(CONFIRMED_SLOT_FILE))
+}
+
+/// Records `decision` with the current time in `state_dir`.
+///
+/// # Errors
+///
+/// Returns any error produced by [`write_last_decision`].
+pub fn record_decision(state_dir: &Path, decision: &HealthDecision) -> Result<(), String> {
+    write_last_decision(
+        state_dir,
+        &DecisionRecord::new(decision, jiff::Timestamp::now()),
+    )
+}
@@ -0,0 +1,77 @@
+// Datei: src/status.rs
+//! Implementation of the `status` subcommand.
+
+use crate::cli::StatusArgs;
+use crate::rauc::RaucStatus;
+use crate::state::DecisionRecord;
+use std::fmt::Write;
+
+/// Production entry point: queries RAUC, reads the last decision and prints
+/// both to stdout.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the configuration cannot be loaded, RAUC cannot
+/// be queried, or the recorded decision cannot be read.
+pub fn show_status(args: &StatusArgs) -> Result<(), String> {
+    let cfg = crate::config::load(args.config.as_deref())?;
+    let runner = crate::cmd::RealCmdRunner::new(&cfg.commands)?;
+    let status = crate::rauc::status_with(&runner)?;
+    let last = crate::state::read_last_decision(&cfg.state_dir)?;
+    print!("{}", render_status(&status, last.as_ref()));
+    Ok(())
+}
+
+/// Renders the RAUC status and the last decision as human readable text.
+#[must_use]
+pub fn render_status(status: &RaucStatus, last: Option<&DecisionRecord>) -> String {
+    let mut out = String::new();
+    let or_dash = |s: Option<&str>| s.unwrap_or("-").to_string();
+
+    let _ = writeln!(out, "Compatible: {}", status.compatible);
+    if !status.variant.is_empty() {
+        let _ = writeln!(out, "Variant:    {}", status.variant);
+    }
+    let _ = writeln!(out, "Booted:     {}", or_dash(status.booted.as_deref()));
+    let _ = writeln!(
+        out,
+        "Primary:    {}",
+        or_dash(status.boot_primary.as_deref())
+    );
+    let _ = writeln!(out, "Slots:");
+    for s in &status.slots {
+        let _ = writeln!(
+            out,
+            "  {} [{}] bootname={} state={} boot-status={} bundle={} installed={}",
+            s.name,
+            s.class,
+            or_dash(s.bootname.as_deref()),
+            s.state,
+            s.boot_status
+                .as_ref()
+                .map_or_else(|| "-".to_string(), ToString::to_string),
+            or_dash(s.bundle_version.as_deref()),
+            s.installed
+                .map_or_else(|| "-".to_string(), |t| t.to_string()),
+        );
+    }
+
+    let _ = writeln!(out, "Last health decision:");
+    match last {
+        None => {
+            let _ = writeln!(out, "  none recorded");
+        }
+        Some(rec) => {
+            let _ = writeln!(
+                out,
+                "  {} at {}",
+                rec.decision.to_uppercase(),
+                rec.timestamp
+            );
+            for f in &rec.failed_services {
+                let _ = writeln!(out, "  failed: {} ({})", f.name, f.status);
+            }
+        }
+    }
+    out
+}
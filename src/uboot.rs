@@ -0,0 +1,410 @@
+// Datei: src/uboot.rs
+//! U-Boot environment backend.
+//!
+//! Reads and writes RAUC's `BOOT_ORDER` / `BOOT_<x>_LEFT` variables directly
+//! in the environment storage described by `fw_env.config`, without
+//! `fw_setenv` or the rauc binary. Both single and redundant environments
+//! are supported.
+//!
+//! Storage is accessed with plain reads and writes at the configured
+//! offset, which works for block devices, eMMC boot partitions and image
+//! files. Raw MTD devices that need an erase cycle are not supported.
+
+use crate::config::HealthConfig;
+use crate::marker::{SlotBootState, SlotMarker, SlotNames};
+use crate::rauc::{MarkState, SlotIdentifier};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Variable holding the space-separated boot order.
+pub const BOOT_ORDER: &str = "BOOT_ORDER";
+
+/// One environment copy as configured in `fw_env.config`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvLocation {
+    /// Device or image file holding the environment.
+    pub device: PathBuf,
+    /// Byte offset of the environment within `device`.
+    pub offset: u64,
+    /// Size of the environment in bytes, including the header.
+    pub size: usize,
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    let res = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    res.map_err(|e| format!("invalid number `{s}`: {e}"))
+}
+
+/// Parses `fw_env.config` (`<device> <offset> <size> [<sector size> [<sectors>]]`).
+///
+/// # Errors
+///
+/// Returns `Err(String)` for malformed lines, or if not exactly one or two
+/// environment copies of equal size are configured.
+pub fn parse_fw_env_config(s: &str) -> Result<Vec<EnvLocation>, String> {
+    let mut out = Vec::new();
+    for (n, line) in s.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [device, offset, size, ..] = fields[..] else {
+            return Err(format!(
+                "fw_env.config line {}: expected device, offset and size",
+                n + 1
+            ));
+        };
+        let offset =
+            parse_number(offset).map_err(|e| format!("fw_env.config line {}: {e}", n + 1))?;
+        let size = parse_number(size).map_err(|e| format!("fw_env.config line {}: {e}", n + 1))?;
+        out.push(EnvLocation {
+            device: PathBuf::from(device),
+            offset,
+            size: usize::try_from(size).map_err(|e| e.to_string())?,
+        });
+    }
+
+    match out.as_slice() {
+        [_] => Ok(out),
+        [a, b] if a.size == b.size => Ok(out),
+        [_, _] => Err("redundant environment copies differ in size".into()),
+        _ => Err(format!(
+            "fw_env.config must list one or two environments, found {}",
+            out.len()
+        )),
+    }
+}
+
+/// CRC-32 (IEEE 802.3) as used by U-Boot for the environment.
+#[must_use]
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &b in data {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Variables of a U-Boot environment in storage order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Environment {
+    vars: Vec<(String, String)>,
+}
+
+impl Environment {
+    /// Returns the value of `key`.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.vars
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sets `key` to `value`, appending it if it does not exist yet.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.vars.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.vars.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Parses the data area (`key=value\0...\0\0`) of an environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if an entry is not valid UTF-8 or lacks `=`.
+    pub fn parse_data(data: &[u8]) -> Result<Self, String> {
+        let mut vars = Vec::new();
+        for entry in data.split(|&b| b == 0) {
+            if entry.is_empty() {
+                break;
+            }
+            let entry = std::str::from_utf8(entry)
+                .map_err(|_| "U-Boot environment entry is not valid UTF-8".to_string())?;
+            let (k, v) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("malformed U-Boot environment entry `{entry}`"))?;
+            vars.push((k.to_string(), v.to_string()));
+        }
+        Ok(Self { vars })
+    }
+
+    /// Serializes the variables into a data area of exactly `len` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the variables do not fit.
+    pub fn to_data(&self, len: usize) -> Result<Vec<u8>, String> {
+        let mut data = Vec::with_capacity(len);
+        for (k, v) in &self.vars {
+            data.extend_from_slice(k.as_bytes());
+            data.push(b'=');
+            data.extend_from_slice(v.as_bytes());
+            data.push(0);
+        }
+        data.push(0);
+        if data.len() > len {
+            return Err(format!(
+                "U-Boot environment too large ({} > {len} bytes)",
+                data.len()
+            ));
+        }
+        data.resize(len, 0);
+        Ok(data)
+    }
+}
+
+/// Environment storage with one or two (redundant) copies.
+#[derive(Debug, Clone)]
+pub struct EnvStorage {
+    locations: Vec<EnvLocation>,
+}
+
+impl EnvStorage {
+    /// Creates storage for the given copies.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` unless one or two copies are given.
+    pub fn new(locations: Vec<EnvLocation>) -> Result<Self, String> {
+        if locations.is_empty() || locations.len() > 2 {
+            return Err("U-Boot environment needs one or two locations".into());
+        }
+        Ok(Self { locations })
+    }
+
+    /// Creates storage from a `fw_env.config` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the file cannot be read or parsed.
+    pub fn from_fw_env_config(path: &Path) -> Result<Self, String> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        Self::new(parse_fw_env_config(&s).map_err(|e| format!("{}: {e}", path.display()))?)
+    }
+
+    fn redundant(&self) -> bool {
+        self.locations.len() == 2
+    }
+
+    fn header_len(&self) -> usize {
+        if self.redundant() {
+            5
+        } else {
+            4
+        }
+    }
+
+    /// Reads copy `idx`; `None` if its CRC does not match.
+    fn read_copy(&self, idx: usize) -> Result<Option<(u8, Environment)>, String> {
+        let loc = &self.locations[idx];
+        let mut buf = vec![0; loc.size];
+        let mut f = std::fs::File::open(&loc.device)
+            .map_err(|e| format!("failed to open {}: {e}", loc.device.display()))?;
+        f.seek(SeekFrom::Start(loc.offset))
+            .and_then(|_| f.read_exact(&mut buf))
+            .map_err(|e| format!("failed to read {}: {e}", loc.device.display()))?;
+
+        let header = self.header_len();
+        if buf.len() < header {
+            return Err(format!("U-Boot environment size {} too small", loc.size));
+        }
+        let stored = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let data = &buf[header..];
+        if crc32(data) != stored {
+            log::warn!(
+                "U-Boot environment copy {idx} on {} has a bad CRC",
+                loc.device.display()
+            );
+            return Ok(None);
+        }
+        let flags = if self.redundant() { buf[4] } else { 0 };
+        Environment::parse_data(data).map(|env| Some((flags, env)))
+    }
+
+    /// Returns index, flags and content of the current copy.
+    fn current(&self) -> Result<Option<(usize, u8, Environment)>, String> {
+        let first = self.read_copy(0)?;
+        if !self.redundant() {
+            return Ok(first.map(|(f, env)| (0, f, env)));
+        }
+        let second = self.read_copy(1)?;
+        Ok(match (first, second) {
+            (None, None) => None,
+            (Some((f, env)), None) => Some((0, f, env)),
+            (None, Some((f, env))) => Some((1, f, env)),
+            // Same rules as U-Boot: the higher flag wins, with 255 -> 0
+            // counting as increment.
+            (Some((f1, e1)), Some((f2, e2))) => {
+                let second_newer = (f1 == 255 && f2 == 0) || (f2 > f1 && !(f2 == 255 && f1 == 0));
+                if second_newer {
+                    Some((1, f2, e2))
+                } else {
+                    Some((0, f1, e1))
+                }
+            }
+        })
+    }
+
+    /// Loads the current environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if no copy can be read with a valid CRC.
+    pub fn load(&self) -> Result<Environment, String> {
+        self.current()?
+            .map(|(_, _, env)| env)
+            .ok_or_else(|| "no valid U-Boot environment found (CRC mismatch)".into())
+    }
+
+    /// Stores `env`.
+    ///
+    /// With redundant storage the copy that is not current is overwritten
+    /// and gets the next flag value, so an interrupted write leaves the
+    /// previous environment intact.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the environment does not fit or cannot be
+    /// written.
+    pub fn store(&self, env: &Environment) -> Result<(), String> {
+        let (idx, flags) = if self.redundant() {
+            match self.current()? {
+                Some((cur, f, _)) => (1 - cur, f.wrapping_add(1)),
+                None => (0, 1),
+            }
+        } else {
+            (0, 0)
+        };
+
+        let loc = &self.locations[idx];
+        let header = self.header_len();
+        let data = env.to_data(loc.size - header)?;
+        let mut buf = Vec::with_capacity(loc.size);
+        buf.extend_from_slice(&crc32(&data).to_le_bytes());
+        if self.redundant() {
+            buf.push(flags);
+        }
+        buf.extend_from_slice(&data);
+
+        let mut f = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&loc.device)
+            .map_err(|e| format!("failed to open {}: {e}", loc.device.display()))?;
+        f.seek(SeekFrom::Start(loc.offset))
+            .and_then(|_| f.write_all(&buf))
+            .and_then(|()| f.sync_all())
+            .map_err(|e| format!("failed to write {}: {e}", loc.device.display()))
+    }
+}
+
+/// Slot marker writing RAUC's U-Boot boot variables.
+///
+/// - good: `BOOT_<x>_LEFT` = boot attempts
+/// - bad: `BOOT_<x>_LEFT` = 0
+/// - active: `<x>` moved to the front of `BOOT_ORDER`, attempts reset
+#[derive(Debug, Clone)]
+pub struct UbootMarker {
+    storage: EnvStorage,
+    boot_attempts: u32,
+    names: SlotNames,
+}
+
+impl UbootMarker {
+    /// Creates a marker for `storage`.
+    #[must_use]
+    pub fn new(storage: EnvStorage, boot_attempts: u32, names: SlotNames) -> Self {
+        Self {
+            storage,
+            boot_attempts,
+            names,
+        }
+    }
+
+    /// Creates the marker from the `[uboot]` section of `cfg`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if `fw_env.config` cannot be read or parsed.
+    pub fn from_config(cfg: &HealthConfig) -> Result<Self, String> {
+        Ok(Self::new(
+            EnvStorage::from_fw_env_config(&cfg.uboot.fw_env_config)?,
+            cfg.uboot.boot_attempts,
+            SlotNames::detect(&cfg.rauc_system_conf),
+        ))
+    }
+}
+
+fn boot_order(env: &Environment) -> Result<Vec<String>, String> {
+    env.get(BOOT_ORDER)
+        .map(|o| o.split_whitespace().map(str::to_string).collect())
+        .ok_or_else(|| format!("{BOOT_ORDER} not set in U-Boot environment"))
+}
+
+impl SlotMarker for UbootMarker {
+    fn mark(&self, state: MarkState, slot: &SlotIdentifier) -> Result<(), String> {
+        let old = self.storage.load()?;
+        let mut env = old.clone();
+        let mut order = boot_order(&env)?;
+        let bootname = self.names.resolve(slot, &order)?;
+        let left = format!("BOOT_{bootname}_LEFT");
+        let attempts = self.boot_attempts.to_string();
+
+        match state {
+            MarkState::Good => env.set(&left, &attempts),
+            MarkState::Bad => env.set(&left, "0"),
+            MarkState::Active => {
+                order.retain(|b| *b != bootname);
+                order.insert(0, bootname.clone());
+                env.set(BOOT_ORDER, &order.join(" "));
+                env.set(&left, &attempts);
+            }
+        }
+
+        if env == old {
+            log::info!("U-Boot: slot {bootname} already {state}");
+            return Ok(());
+        }
+        self.storage.store(&env)?;
+        log::info!("U-Boot: marked slot {bootname} as {state}");
+        Ok(())
+    }
+
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String> {
+        let env = self.storage.load()?;
+        let order = boot_order(&env)?;
+        Ok(order
+            .iter()
+            .enumerate()
+            .map(|(i, b)| SlotBootState {
+                bootname: b.clone(),
+                order: Some(i),
+                remaining_attempts: env
+                    .get(&format!("BOOT_{b}_LEFT"))
+                    .and_then(|v| v.trim().parse().ok())
+                    .unwrap_or(0),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_reference_vector() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+}
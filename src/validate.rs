@@ -0,0 +1,165 @@
+// Datei: src/validate.rs
+//! Implementation of the `validate-config` subcommand.
+//!
+//! Lints a configuration against an `OpenRC` root file system: besides the
+//! checks of [`crate::config::validate`], every required and optional
+//! service must have an init script and be enabled in one of the checked
+//! runlevels.
+
+use crate::cli::ValidateConfigArgs;
+use crate::config::{ConfigIssue, HealthConfig, Locate, Severity};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Machine-readable result of `validate-config`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    /// Validated config file; `None` for the built-in defaults.
+    pub config: Option<PathBuf>,
+    /// Root file system the services were looked up in.
+    pub root: PathBuf,
+    /// `true` if there are no issues with [`Severity::Error`].
+    pub valid: bool,
+    /// All problems found, errors and warnings.
+    pub issues: Vec<ConfigIssue>,
+}
+
+/// Production entry point: prints the report as JSON to stdout.
+///
+/// # Returns
+///
+/// Exit code 0 if the configuration is valid, 1 otherwise.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the report cannot be serialized.
+pub fn validate_config(args: &ValidateConfigArgs) -> Result<u8, String> {
+    let report = validate_config_with(args.config.as_deref(), &args.root);
+    let json = serde_json::to_string_pretty(&report)
+        .map_err(|e| format!("failed to serialize report: {e}"))?;
+    println!("{json}");
+    Ok(u8::from(!report.valid))
+}
+
+/// Validates the config at `config` (or the one found in
+/// [`crate::config::SEARCH_PATH`] below `root`, or the defaults) against the
+/// system below `root`.
+///
+/// Only files are read: drop-ins come from below `root` (or next to
+/// `config`), and neither the environment nor the build host's device tree
+/// or RAUC state are looked at (see [`crate::dropin::load_image`]).
+#[must_use]
+pub fn validate_config_with(config: Option<&Path>, root: &Path) -> ValidationReport {
+    let file = config
+        .map(Path::to_path_buf)
+        .or_else(|| crate::config::find_config_in(root));
+    let source = file
+        .as_deref()
+        .and_then(|p| std::fs::read_to_string(p).ok())
+        .unwrap_or_default();
+
+    let mut issues = Vec::new();
+    match crate::dropin::load_image(config, root) {
+        Ok(layered) => {
+            issues.extend(crate::config::validate(&layered.config, &layered));
+            issues.extend(check_services(&layered.config, &layered, root));
+        }
+        Err(e) => issues.extend(load_issues(file.as_deref(), &source, e)),
+    }
+
+    ValidationReport {
+        config: file,
+        root: root.to_path_buf(),
+        valid: issues.iter().all(|i| i.severity != Severity::Error),
+        issues,
+    }
+}
+
+/// Breaks a load error of [`crate::dropin::load_image`] down into located
+/// issues.
+fn load_issues(config: Option<&Path>, source: &str, error: String) -> Vec<ConfigIssue> {
+    if let Some(path) = config.filter(|p| p.exists()) {
+        let file = Some(path.display().to_string());
+        match toml::from_str::<HealthConfig>(source) {
+            Err(e) => {
+                let mut issue = ConfigIssue::from_toml_error(&e, source);
+                issue.file = file;
+                return vec![issue];
+            }
+            Ok(cfg) => {
+                let mut issues = crate::config::validate(&cfg, source);
+                if issues.iter().any(|i| i.severity == Severity::Error) {
+                    for issue in &mut issues {
+                        issue.file.clone_from(&file);
+                    }
+                    return issues;
+                }
+            }
+        }
+    }
+    vec![ConfigIssue::at(Severity::Error, error, source, None)]
+}
+
+/// Checks that every configured service has an init script and is enabled
+/// in one of the runlevels of `cfg.runlevels` below `root`.
+fn check_services(cfg: &HealthConfig, source: &dyn Locate, root: &Path) -> Vec<ConfigIssue> {
+    let runlevels = root.join("etc/runlevels");
+    let mut issues = Vec::new();
+
+    for (key, list, severity) in [
+        ("required_services", &cfg.required_services, Severity::Error),
+        (
+            "optional_services",
+            &cfg.optional_services,
+            Severity::Warning,
+        ),
+    ] {
+        for name in list.iter().filter(|n| !n.trim().is_empty()) {
+            let message = if !root.join("etc/init.d").join(name).exists() {
+                format!("{key}: `{name}` has no init script /etc/init.d/{name}")
+            } else if !cfg
+                .runlevels
+                .iter()
+                .any(|r| is_enabled(&runlevels.join(r), name))
+            {
+                let checked = cfg.runlevels.join(", ");
+                let elsewhere = enabled_in(&runlevels, name);
+                if elsewhere.is_empty() {
+                    format!("{key}: `{name}` is not enabled in runlevel {checked}")
+                } else {
+                    format!(
+                        "{key}: `{name}` is enabled in runlevel {} but not in {checked}",
+                        elsewhere.join(", ")
+                    )
+                }
+            } else {
+                continue;
+            };
+            issues.push(ConfigIssue::new(
+                severity,
+                message,
+                source.entry(key, name, 0),
+            ));
+        }
+    }
+    issues
+}
+
+/// Runlevel entries are symlinks to absolute paths, which may dangle below a
+/// foreign `root`, so only the link itself is checked.
+fn is_enabled(runlevel_dir: &Path, name: &str) -> bool {
+    std::fs::symlink_metadata(runlevel_dir.join(name)).is_ok()
+}
+
+/// Sorted names of the runlevels below `runlevels` that enable `name`.
+fn enabled_in(runlevels: &Path, name: &str) -> Vec<String> {
+    let mut found: Vec<String> = std::fs::read_dir(runlevels)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| is_enabled(&e.path(), name))
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    found.sort();
+    found
+}
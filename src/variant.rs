@@ -0,0 +1,262 @@
+// Datei: src/variant.rs
+//! Hardware-variant and slot-specific config sections.
+//!
+//! A `[[variant]]` section applies to the systems matching all of its
+//! conditions:
+//!
+//! ```toml
+//! [[variant]]
+//! name = "board-b"
+//! match = { device_tree_compatible = "acme,board-b*" }
+//! config = { required_services = { extend = ["can0"] } }
+//! ```
+//!
+//! [`crate::dropin`] merges the `config` of every matching section over the
+//! base file and drop-ins; the conditions are evaluated against the
+//! [`SystemFacts`] of the running system.
+
+use crate::cli::ExplainArgs;
+use crate::config::{HealthConfig, VariantMatch};
+use crate::marker::SlotNames;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Device-tree directory exported by the kernel.
+pub const DEVICE_TREE_DIR: &str = "/proc/device-tree";
+
+/// Properties of the running system that variant sections match on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemFacts {
+    /// Entries of `/proc/device-tree/compatible`, most specific first.
+    pub device_tree_compatible: Vec<String>,
+    /// `/proc/device-tree/model`.
+    pub device_tree_model: Option<String>,
+    /// `compatible` from the `[system]` section of RAUC's `system.conf`.
+    pub rauc_compatible: Option<String>,
+    /// RAUC slot name of the booted slot, e.g. `rootfs.0`.
+    pub slot: Option<String>,
+    /// Bootname of the booted slot, e.g. `A`.
+    pub bootname: Option<String>,
+    /// Version of the bundle in the booted slot.
+    pub bundle_version: Option<String>,
+}
+
+impl SystemFacts {
+    /// Collects the facts the variant sections of `cfg` need.
+    ///
+    /// Nothing is read without variant sections. Unavailable facts are left
+    /// empty, so conditions on them do not match; the bundle version is only
+    /// read if a section matches on `bundle_version` (see
+    /// [`SystemFacts::bundle_version`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if a section matches on `bundle_version` but
+    /// the version cannot be determined: judging the boot without the
+    /// section's services would weaken the check.
+    pub fn detect(cfg: &HealthConfig) -> Result<Self, String> {
+        if cfg.variants.is_empty() {
+            return Ok(Self::default());
+        }
+        let dt = Path::new(DEVICE_TREE_DIR);
+        let names = SlotNames::detect(&cfg.rauc_system_conf);
+        let slot = names.booted.as_ref().and_then(|b| {
+            names
+                .bootnames
+                .iter()
+                .find(|(_, bootname)| *bootname == b)
+                .map(|(slot, _)| slot.clone())
+        });
+
+        let bundle_version = if cfg.variants.iter().any(|v| v.when.bundle_version.is_some()) {
+            Some(Self::bundle_version(cfg, slot.as_deref())?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            device_tree_compatible: std::fs::read(dt.join("compatible"))
+                .map(|b| parse_string_list(&b))
+                .unwrap_or_default(),
+            device_tree_model: std::fs::read(dt.join("model"))
+                .ok()
+                .and_then(|b| parse_string_list(&b).into_iter().next()),
+            rauc_compatible: std::fs::read_to_string(&cfg.rauc_system_conf)
+                .ok()
+                .and_then(|s| parse_system_conf_compatible(&s)),
+            slot,
+            bootname: names.booted,
+            bundle_version,
+        })
+    }
+
+    /// Version of the bundle in the booted slot `slot`: from the
+    /// pending-update record if it was written for that slot, else from
+    /// `rauc status`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if neither source knows the version, e.g.
+    /// because RAUC is not running yet.
+    pub fn bundle_version(cfg: &HealthConfig, slot: Option<&str>) -> Result<String, String> {
+        let pending = crate::state::read_pending_update(&cfg.state_dir)
+            .map_err(|e| log::warn!("{e}"))
+            .ok()
+            .flatten();
+        if let Some(version) = pending
+            .filter(|p| slot.is_some_and(|s| s == p.slot))
+            .and_then(|p| p.bundle_version)
+        {
+            return Ok(version);
+        }
+        crate::cmd::RealCmdRunner::new(&cfg.commands)
+            .and_then(|runner| crate::rauc::status_with(&runner))
+            .and_then(|st| {
+                st.booted_slot()
+                    .and_then(|s| s.bundle_version.clone())
+                    .ok_or_else(|| "RAUC reports no bundle version for the booted slot".into())
+            })
+            .map_err(|e| format!("variant matches on bundle_version, but it is unknown: {e}"))
+    }
+
+    /// Returns the first condition of `when` these facts do not meet, or
+    /// `None` if the section applies.
+    #[must_use]
+    pub fn mismatch(&self, when: &VariantMatch) -> Option<&'static str> {
+        let one = |pattern: &Option<String>, value: Option<&str>| {
+            pattern
+                .as_deref()
+                .is_none_or(|p| value.is_some_and(|v| matches(p, v)))
+        };
+        if !when
+            .device_tree_compatible
+            .as_deref()
+            .is_none_or(|p| self.device_tree_compatible.iter().any(|c| matches(p, c)))
+        {
+            return Some("device_tree_compatible");
+        }
+        if !one(&when.device_tree_model, self.device_tree_model.as_deref()) {
+            return Some("device_tree_model");
+        }
+        if !one(&when.rauc_compatible, self.rauc_compatible.as_deref()) {
+            return Some("rauc_compatible");
+        }
+        if !(one(&when.slot, self.slot.as_deref()) || one(&when.slot, self.bootname.as_deref())) {
+            return Some("slot");
+        }
+        if !one(&when.bundle_version, self.bundle_version.as_deref()) {
+            return Some("bundle_version");
+        }
+        None
+    }
+}
+
+/// Compares `value` with `pattern`; a trailing `*` matches any rest.
+#[must_use]
+pub fn matches(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => value == pattern,
+    }
+}
+
+/// Splits a NUL-separated device-tree string list.
+fn parse_string_list(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .split(|b| *b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+/// Extracts `compatible` from the `[system]` section of a RAUC `system.conf`.
+#[must_use]
+pub fn parse_system_conf_compatible(s: &str) -> Option<String> {
+    let mut in_system = false;
+    for line in s.lines().map(str::trim) {
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_system = name == "system";
+            continue;
+        }
+        if let (true, Some((key, value))) = (in_system, line.split_once('=')) {
+            if key.trim() == "compatible" {
+                return Some(value.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Implementation of the `explain` subcommand: prints the system facts,
+/// which variant sections applied and the effective configuration.
+///
+/// # Errors
+///
+/// Returns `Err(String)` if the configuration cannot be loaded.
+pub fn explain(args: &ExplainArgs) -> Result<(), String> {
+    let layered = crate::dropin::load_layered(
+        args.config.as_deref(),
+        &|k| std::env::var(k).ok(),
+        &SystemFacts::detect,
+    )?;
+    print!("{}", render_explain(&layered));
+    Ok(())
+}
+
+/// Renders the `explain` output for `layered`.
+#[must_use]
+pub fn render_explain(layered: &crate::dropin::LayeredConfig) -> String {
+    let facts = &layered.facts;
+    let or_dash = |s: Option<&str>| s.unwrap_or("-").to_string();
+    let mut out = String::from("# System facts:\n");
+    let _ = writeln!(
+        out,
+        "#   device-tree compatible: {}",
+        or_dash(
+            Some(facts.device_tree_compatible.join(", "))
+                .filter(|s| !s.is_empty())
+                .as_deref()
+        )
+    );
+    let _ = writeln!(
+        out,
+        "#   device-tree model: {}",
+        or_dash(facts.device_tree_model.as_deref())
+    );
+    let _ = writeln!(
+        out,
+        "#   RAUC compatible: {}",
+        or_dash(facts.rauc_compatible.as_deref())
+    );
+    let _ = writeln!(
+        out,
+        "#   booted slot: {} (bootname {})",
+        or_dash(facts.slot.as_deref()),
+        or_dash(facts.bootname.as_deref())
+    );
+    let _ = writeln!(
+        out,
+        "#   bundle version: {}",
+        or_dash(facts.bundle_version.as_deref())
+    );
+
+    out.push_str("# Variant sections:\n");
+    if layered.config.variants.is_empty() {
+        out.push_str("#   (none)\n");
+    }
+    for v in &layered.config.variants {
+        match facts.mismatch(&v.when) {
+            None => {
+                let _ = writeln!(out, "#   {}: applied", v.name);
+            }
+            Some(condition) => {
+                let _ = writeln!(out, "#   {}: not applied ({condition} differs)", v.name);
+            }
+        }
+    }
+    out.push_str(&layered.render());
+    out
+}
@@ -0,0 +1,159 @@
+// tests/barebox_test.rs
+//
+// barebox bootchooser backend with a fake `barebox-state`.
+
+use rauc_health::barebox::BareboxMarker;
+use rauc_health::cmd::CmdRunner;
+use rauc_health::marker::{SlotMarker, SlotNames};
+use rauc_health::rauc::{MarkState, SlotIdentifier};
+use std::cell::RefCell;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+
+/// Answers `-g` with `stdout` and records every invocation.
+#[derive(Default)]
+struct FakeBareboxState {
+    stdout: String,
+    calls: RefCell<Vec<Vec<String>>>,
+}
+
+impl FakeBareboxState {
+    fn with_values(stdout: &str) -> Self {
+        Self {
+            stdout: stdout.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn set_calls(&self) -> Vec<Vec<String>> {
+        self.calls
+            .borrow()
+            .iter()
+            .filter(|c| c[0] == "-s")
+            .cloned()
+            .collect()
+    }
+}
+
+impl CmdRunner for FakeBareboxState {
+    fn status(&self, program: &str, args: &[&str], _ctx: &str) -> Result<ExitStatus, String> {
+        assert_eq!(program, "barebox-state");
+        self.calls
+            .borrow_mut()
+            .push(args.iter().map(|s| s.to_string()).collect());
+        Ok(ExitStatus::from_raw(0))
+    }
+
+    fn output(&self, program: &str, args: &[&str], ctx: &str) -> Result<Output, String> {
+        let status = self.status(program, args, ctx)?;
+        Ok(Output {
+            status,
+            stdout: self.stdout.clone().into_bytes(),
+            stderr: Vec::new(),
+        })
+    }
+}
+
+fn names() -> SlotNames {
+    SlotNames {
+        booted: Some("system0".into()),
+        bootnames: [("rootfs.0", "system0"), ("rootfs.1", "system1")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    }
+}
+
+// system0: priority 20, 1 attempt left; system1: priority 10, 3 attempts
+const VALUES: &str = "20\n1\n10\n3\n";
+
+#[test]
+fn reads_all_targets_in_one_call() {
+    let runner = FakeBareboxState::with_values(VALUES);
+    let marker = BareboxMarker::new(&runner, 3, names());
+
+    let states = marker.slot_states().unwrap();
+
+    assert_eq!(
+        runner.calls.borrow()[0],
+        vec![
+            "-g",
+            "bootstate.system0.priority",
+            "-g",
+            "bootstate.system0.remaining_attempts",
+            "-g",
+            "bootstate.system1.priority",
+            "-g",
+            "bootstate.system1.remaining_attempts",
+        ]
+    );
+    assert_eq!(states[0].order, Some(0));
+    assert_eq!(states[0].remaining_attempts, 1);
+    assert_eq!(states[1].order, Some(1));
+}
+
+#[test]
+fn mark_good_resets_remaining_attempts() {
+    let runner = FakeBareboxState::with_values(VALUES);
+    let marker = BareboxMarker::new(&runner, 3, names());
+
+    marker
+        .mark(MarkState::Good, &SlotIdentifier::Booted)
+        .unwrap();
+
+    assert_eq!(
+        runner.set_calls(),
+        vec![vec!["-s", "bootstate.system0.remaining_attempts=3"]]
+    );
+}
+
+#[test]
+fn mark_bad_disables_target() {
+    let runner = FakeBareboxState::with_values(VALUES);
+    let marker = BareboxMarker::new(&runner, 3, names());
+
+    marker.mark(MarkState::Bad, &SlotIdentifier::Other).unwrap();
+
+    assert_eq!(
+        runner.set_calls(),
+        vec![vec![
+            "-s",
+            "bootstate.system1.priority=0",
+            "-s",
+            "bootstate.system1.remaining_attempts=0",
+        ]]
+    );
+}
+
+#[test]
+fn mark_active_raises_priority() {
+    let runner = FakeBareboxState::with_values(VALUES);
+    let marker = BareboxMarker::new(&runner, 3, names());
+
+    marker
+        .mark(MarkState::Active, &SlotIdentifier::Named("rootfs.1".into()))
+        .unwrap();
+
+    // system1 already has 3 attempts left, so only priorities change
+    assert_eq!(
+        runner.set_calls(),
+        vec![vec![
+            "-s",
+            "bootstate.system0.priority=10",
+            "-s",
+            "bootstate.system1.priority=20",
+        ]]
+    );
+}
+
+#[test]
+fn unexpected_output_is_an_error() {
+    let runner = FakeBareboxState::with_values("20\n");
+    let marker = BareboxMarker::new(&runner, 3, names());
+
+    let err = marker
+        .mark(MarkState::Good, &SlotIdentifier::Booted)
+        .unwrap_err();
+    assert_eq!(err, "barebox-state returned 1 values, expected 4");
+    assert!(runner.set_calls().is_empty());
+}
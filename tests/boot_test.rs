@@ -0,0 +1,224 @@
+// tests/boot_test.rs
+
+use rauc_health::boot::{
+    assess_boot, bootname_from_cmdline, confirm_boot, detect_rollback, handle_rollback,
+    target_slot_from_env, BootKind, Rollback,
+};
+use rauc_health::config::HealthConfig;
+use rauc_health::marker::{FileMarker, SlotNames};
+use rauc_health::rauc::{parse_status_json, RaucStatus};
+use rauc_health::state::{
+    clear_pending_update, read_confirmed_slot, read_pending_update, write_pending_update,
+    ConfirmedSlot, PendingUpdate,
+};
+
+fn status(installed: Option<&str>) -> RaucStatus {
+    let slot_status = installed.map_or_else(String::new, |ts| {
+        format!(r#","slot_status":{{"installed":{{"timestamp":"{ts}","count":1}}}}"#)
+    });
+    parse_status_json(&format!(
+        r#"{{"booted":"B","slots":[
+            {{"rootfs.0":{{"class":"rootfs","bootname":"A","state":"inactive"}}}},
+            {{"rootfs.1":{{"class":"rootfs","bootname":"B","state":"booted"{slot_status}}}}}]}}"#
+    ))
+    .unwrap()
+}
+
+fn pending() -> PendingUpdate {
+    PendingUpdate {
+        slot: "rootfs.1".into(),
+        bootname: Some("B".into()),
+        bundle_version: Some("2.0".into()),
+        timestamp: "2024-05-02T10:00:00Z".into(),
+    }
+}
+
+fn confirmed(installed: &str) -> ConfirmedSlot {
+    ConfirmedSlot {
+        slot: "rootfs.1".into(),
+        installed: Some(installed.into()),
+        timestamp: "2024-05-02T10:05:00Z".into(),
+    }
+}
+
+#[test]
+fn pending_record_means_trial_boot() {
+    let st = status(Some("2024-05-02T09:00:00Z"));
+    let c = confirmed("2024-05-02T09:00:00Z");
+
+    let a = assess_boot(Some(&pending()), Some(&st), Some(&c), None);
+
+    assert_eq!(a.kind, BootKind::Trial);
+    assert!(a.reason.contains("pending update record for slot rootfs.1"));
+    assert_eq!(a.booted_slot.as_deref(), Some("rootfs.1"));
+}
+
+#[test]
+fn unconfirmed_installation_is_trial_boot() {
+    let st = status(Some("2024-05-02T09:00:00Z"));
+    let c = confirmed("2024-04-01T09:00:00Z");
+
+    let a = assess_boot(None, Some(&st), Some(&c), None);
+
+    assert_eq!(a.kind, BootKind::Trial);
+    assert!(a.reason.contains("not confirmed yet"));
+}
+
+#[test]
+fn confirmed_installation_is_normal_boot() {
+    let st = status(Some("2024-05-02T09:00:00Z"));
+    let c = confirmed("2024-05-02T09:00:00Z");
+
+    let a = assess_boot(None, Some(&st), Some(&c), None);
+
+    assert_eq!(a.kind, BootKind::Normal);
+    assert!(a.reason.contains("already confirmed"));
+}
+
+#[test]
+fn slot_without_installation_is_normal_boot() {
+    let a = assess_boot(None, Some(&status(None)), None, None);
+    assert_eq!(a.kind, BootKind::Normal);
+}
+
+#[test]
+fn unknown_rauc_state_is_trial_boot() {
+    let a = assess_boot(None, None, None, None);
+    assert_eq!(a.kind, BootKind::Trial);
+    assert_eq!(a.booted_slot, None);
+}
+
+#[test]
+fn bootname_is_read_from_kernel_cmdline() {
+    assert_eq!(
+        bootname_from_cmdline("console=ttyS0 rauc.slot=B root=/dev/mmcblk0p3\n").as_deref(),
+        Some("B")
+    );
+    assert_eq!(bootname_from_cmdline("console=ttyS0 rauc.slot="), None);
+    assert_eq!(bootname_from_cmdline("console=ttyS0"), None);
+}
+
+#[test]
+fn booting_the_installed_slot_is_no_rollback() {
+    let st = status(None);
+    assert_eq!(
+        detect_rollback(Some(&pending()), Some(&st), Some("B")),
+        None
+    );
+    assert_eq!(detect_rollback(None, Some(&st), Some("A")), None);
+}
+
+#[test]
+fn booting_another_slot_after_update_is_rollback() {
+    let st = status(None);
+
+    let a = assess_boot(Some(&pending()), Some(&st), None, Some("A"));
+
+    assert_eq!(
+        a.rollback,
+        Some(Rollback {
+            booted: "A".into(),
+            expected_slot: "rootfs.1".into(),
+            expected_bootname: Some("B".into()),
+        })
+    );
+    assert_eq!(
+        a.rollback.unwrap().to_string(),
+        "booted slot A but last update was installed to rootfs.1 (B)"
+    );
+}
+
+#[test]
+fn rollback_without_cmdline_uses_rauc_booted_and_slot_bootname() {
+    let st = status(None);
+    let p = PendingUpdate {
+        slot: "rootfs.0".into(),
+        bootname: None,
+        ..pending()
+    };
+
+    let rb = detect_rollback(Some(&p), Some(&st), None).unwrap();
+    assert_eq!(rb.booted, "B");
+    assert_eq!(rb.expected_bootname.as_deref(), Some("A"));
+}
+
+#[test]
+fn handle_rollback_marks_abandoned_slot_and_clears_pending() {
+    let dir = tempfile::tempdir().unwrap();
+    write_pending_update(dir.path(), &pending()).unwrap();
+    let cfg = HealthConfig {
+        state_dir: dir.path().to_path_buf(),
+        mark_abandoned_slot_bad: true,
+        ..Default::default()
+    };
+    let marker = FileMarker::new(
+        dir.path().join("slots.toml"),
+        SlotNames {
+            booted: Some("A".into()),
+            bootnames: [("rootfs.1".to_string(), "B".to_string())].into(),
+        },
+    );
+    let rb = detect_rollback(Some(&pending()), Some(&status(None)), Some("A")).unwrap();
+
+    handle_rollback(&cfg, &rb, &marker).unwrap();
+
+    let slots = marker.read_state().unwrap().slots;
+    assert_eq!(slots.get("B").map(String::as_str), Some("bad"));
+    assert_eq!(read_pending_update(dir.path()).unwrap(), None);
+}
+
+#[test]
+fn dry_run_keeps_pending_update_and_confirmation() {
+    let dir = tempfile::tempdir().unwrap();
+    write_pending_update(dir.path(), &pending()).unwrap();
+    let cfg = HealthConfig {
+        state_dir: dir.path().to_path_buf(),
+        dry_run: true,
+        ..Default::default()
+    };
+    let marker = FileMarker::new(dir.path().join("slots.toml"), SlotNames::default());
+    let rb = detect_rollback(Some(&pending()), Some(&status(None)), Some("A")).unwrap();
+
+    handle_rollback(&cfg, &rb, &marker).unwrap();
+    confirm_boot(
+        &cfg,
+        &assess_boot(Some(&pending()), Some(&status(None)), None, None),
+    )
+    .unwrap();
+
+    assert_eq!(read_pending_update(dir.path()).unwrap(), Some(pending()));
+    assert_eq!(read_confirmed_slot(dir.path()).unwrap(), None);
+}
+
+#[test]
+fn target_slot_is_taken_from_rauc_handler_env() {
+    let env = |k: &str| -> Option<String> {
+        match k {
+            "RAUC_TARGET_SLOTS" => Some("1 2".into()),
+            "RAUC_SLOT_NAME_1" => Some("appfs.1".into()),
+            "RAUC_SLOT_BOOTNAME_1" => Some(String::new()),
+            "RAUC_SLOT_NAME_2" => Some("rootfs.1".into()),
+            "RAUC_SLOT_BOOTNAME_2" => Some("B".into()),
+            _ => None,
+        }
+    };
+
+    assert_eq!(
+        target_slot_from_env(&env),
+        Some(("rootfs.1".to_string(), "B".to_string()))
+    );
+    assert_eq!(target_slot_from_env(&|_| None), None);
+}
+
+#[test]
+fn pending_update_roundtrip_and_clear() {
+    let dir = tempfile::tempdir().unwrap();
+
+    write_pending_update(dir.path(), &pending()).unwrap();
+    assert_eq!(read_pending_update(dir.path()).unwrap(), Some(pending()));
+
+    clear_pending_update(dir.path()).unwrap();
+    assert_eq!(read_pending_update(dir.path()).unwrap(), None);
+    // clearing twice is fine
+    clear_pending_update(dir.path()).unwrap();
+}
@@ -3,9 +3,29 @@
 // Läuft als Integration-Test (cargo test).
 // Enthält DEINE bestehenden Tests + zusätzliche Tests für from_file und optional_services.
 
-use rauc_health::config::{from_file, from_toml_str};
+use rauc_health::config::{
+    env_var_name, find_config, from_file, from_toml_str, CommandsConfig, MarkerBackend, SEARCH_PATH,
+};
+use rauc_health::dropin::{load_layered, LayeredConfig};
+use rauc_health::variant::SystemFacts;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// Lädt `toml` als Konfigurationsdatei mit den Umgebungsvariablen `vars`.
+fn load_with_env(toml: &str, vars: &[(&str, &str)]) -> Result<LayeredConfig, String> {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rauc-health.toml");
+    fs::write(&path, toml).unwrap();
+    load_layered(
+        Some(&path),
+        &|k| {
+            vars.iter()
+                .find(|(name, _)| *name == k)
+                .map(|(_, v)| v.to_string())
+        },
+        &|_| Ok(SystemFacts::default()),
+    )
+}
 
 #[test]
 fn empty_toml_uses_defaults() {
@@ -55,7 +75,65 @@ ignore_prefixes = ["bar."]
 #[test]
 fn invalid_toml_is_error() {
     let err = from_toml_str("required_services = [").unwrap_err();
-    assert_eq!(err, "invalid config TOML");
+    assert!(err.starts_with("invalid config TOML: "));
+    assert!(err.contains("line 1, column 22"));
+}
+
+#[test]
+fn unknown_keys_are_rejected_with_location() {
+    let err = from_toml_str("timeout_seconds = 5\nrequried_services = [\"sshd\"]\n").unwrap_err();
+    assert!(err.contains("line 2, column 1"));
+    assert!(err.contains("unknown field `requried_services`"));
+
+    let err = from_toml_str("[uboot]\nboot_attempt = 3\n").unwrap_err();
+    assert!(err.contains("unknown field `boot_attempt`"));
+}
+
+#[test]
+fn service_lists_are_validated() {
+    let err = from_toml_str(
+        r#"
+required_services = ["sshd", "getty.tty1", "sshd", "cron"]
+optional_services = ["cron", ""]
+"#,
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err.lines().collect::<Vec<_>>(),
+        [
+            "line 2, column 44: required_services: duplicate `sshd`",
+            "line 3, column 30: optional_services: empty name",
+            "line 2, column 30: required service `getty.tty1` is ignored by ignore_prefixes `getty.`",
+            "line 3, column 22: `cron` is listed in both required_services and optional_services",
+        ]
+    );
+
+    let err = from_toml_str(r#"required_services = ["local"]"#).unwrap_err();
+    assert_eq!(
+        err,
+        "line 1, column 22: required service `local` is ignored by ignore_exact"
+    );
+}
+
+#[test]
+fn every_duplicate_points_at_its_own_entry() {
+    let err =
+        from_toml_str(r#"runlevels = ["default", "boot", "default", "default"]"#).unwrap_err();
+    assert_eq!(
+        err.lines().collect::<Vec<_>>(),
+        [
+            "line 1, column 33: runlevels: duplicate `default`",
+            "line 1, column 44: runlevels: duplicate `default`",
+        ]
+    );
+}
+
+#[test]
+fn shipped_example_config_is_valid() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config/rauc-health.toml");
+    let cfg = from_file(&path).unwrap();
+    assert_eq!(cfg.required_services, vec!["net.eth0", "sshd"]);
 }
 
 #[test]
@@ -68,6 +146,86 @@ fn optional_services_can_be_set_independently() {
     assert!(cfg.ignore_prefixes.contains(&"agetty.".to_string()));
 }
 
+#[test]
+fn timing_is_read_from_file_and_overridden_by_env() {
+    let toml = "timeout_seconds = 90\npoll_interval_ms = 200\n";
+    let cfg = from_toml_str(toml).unwrap();
+    assert_eq!((cfg.timeout_seconds, cfg.poll_interval_ms), (90, 200));
+
+    let layered = load_with_env(toml, &[("RAUC_HEALTH_TIMEOUT_SECONDS", "120")]).unwrap();
+    let cfg = &layered.config;
+    assert_eq!((cfg.timeout_seconds, cfg.poll_interval_ms), (120, 200));
+    let sources = layered.sources();
+    assert_eq!(
+        sources["timeout_seconds"],
+        "env RAUC_HEALTH_TIMEOUT_SECONDS"
+    );
+    assert!(sources["poll_interval_ms"].ends_with("rauc-health.toml"));
+    assert_eq!(sources["retry.attempts"], "default");
+
+    let err = load_with_env(toml, &[("RAUC_HEALTH_TIMEOUT_SECONDS", "soon")]).unwrap_err();
+    assert_eq!(
+        err,
+        "RAUC_HEALTH_TIMEOUT_SECONDS: expected a number, got `soon`"
+    );
+
+    // Defaults ohne Datei
+    let cfg = from_toml_str("").unwrap();
+    assert_eq!((cfg.timeout_seconds, cfg.poll_interval_ms), (30, 500));
+}
+
+#[test]
+fn every_scalar_can_be_overridden_by_env() {
+    let cfg = load_with_env(
+        "",
+        &[
+            ("RAUC_HEALTH_MARKER_BACKEND", "grub"),
+            ("RAUC_HEALTH_DRY_RUN", "true"),
+            ("RAUC_HEALTH_RETRY_ATTEMPTS", "7"),
+            ("RAUC_HEALTH_COMMANDS_RAUC", "/opt/bin/rauc"),
+            ("RAUC_HEALTH_COMMANDS_RC_STATUS", "/tmp/rc-status"),
+            ("RAUC_HEALTH_COMMANDS_PATH", "/tmp"),
+            // Listen lassen sich nicht überschreiben
+            ("RAUC_HEALTH_REQUIRED_SERVICES", "sshd"),
+        ],
+    )
+    .unwrap()
+    .config;
+    assert_eq!(cfg.marker_backend, MarkerBackend::Grub);
+    assert!(cfg.dry_run);
+    assert_eq!(cfg.retry.attempts, 7);
+    // [commands] bestimmt, was als root läuft, und bleibt der Datei vorbehalten
+    assert_eq!(cfg.commands, CommandsConfig::default());
+    assert!(cfg.required_services.is_empty());
+
+    let err = load_with_env("", &[("RAUC_HEALTH_MARKER_BACKEND", "floppy")]).unwrap_err();
+    assert!(
+        err.starts_with("RAUC_HEALTH_MARKER_BACKEND: unknown variant `floppy`"),
+        "{err}"
+    );
+    let err = load_with_env("", &[("RAUC_HEALTH_DRY_RUN", "maybe")]).unwrap_err();
+    assert_eq!(
+        err,
+        "RAUC_HEALTH_DRY_RUN: expected true or false, got `maybe`"
+    );
+}
+
+#[test]
+fn config_is_searched_in_standard_locations() {
+    assert_eq!(env_var_name("retry.attempts"), "RAUC_HEALTH_RETRY_ATTEMPTS");
+
+    assert_eq!(
+        find_config(&|p| p.starts_with("/usr/lib")),
+        Some(PathBuf::from("/usr/lib/rauc-health/rauc-health.toml"))
+    );
+    assert_eq!(
+        find_config(&|_| true),
+        Some(PathBuf::from("/etc/rauc-health.toml"))
+    );
+    assert_eq!(find_config(&|_| false), None);
+    assert_eq!(SEARCH_PATH[0], "/etc/rauc-health.toml");
+}
+
 #[test]
 fn from_file_reads_and_parses() {
     let mut p = std::env::temp_dir();
@@ -0,0 +1,149 @@
+// tests/dropin_test.rs
+//
+// Base config plus `<base>.d/*.toml` drop-ins.
+
+use rauc_health::config::MarkerBackend;
+use rauc_health::dropin::{dropin_dir, load_layered};
+use rauc_health::variant::SystemFacts;
+use std::path::{Path, PathBuf};
+
+fn base(dir: &Path, toml: &str) -> PathBuf {
+    let path = dir.join("rauc-health.toml");
+    std::fs::write(&path, toml).unwrap();
+    path
+}
+
+fn dropin(dir: &Path, name: &str, toml: &str) {
+    let d = dir.join("rauc-health.d");
+    std::fs::create_dir_all(&d).unwrap();
+    std::fs::write(d.join(name), toml).unwrap();
+}
+
+#[test]
+fn dropin_dir_sits_next_to_base_file() {
+    assert_eq!(
+        dropin_dir(Some(Path::new("/etc/rauc-health.toml"))),
+        Path::new("/etc/rauc-health.d")
+    );
+    assert_eq!(dropin_dir(None), Path::new("/etc/rauc-health.d"));
+}
+
+#[test]
+fn dropins_merge_in_lexical_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = base(
+        dir.path(),
+        "required_services = [\"sshd\", \"bluetooth\"]\ntimeout_seconds = 60\n",
+    );
+    // 20 runs after 10 although it is written first
+    dropin(
+        dir.path(),
+        "20-product.toml",
+        "required_services = { remove = [\"bluetooth\"], extend = [\"ui\"] }\ntimeout_seconds = 90\n",
+    );
+    dropin(
+        dir.path(),
+        "10-net.toml",
+        "required_services = [\"net.eth0\", \"sshd\"]\nmarker_backend = \"dbus\"\n\n[retry]\nattempts = 9\n",
+    );
+    dropin(dir.path(), "README", "not toml");
+
+    let layered = load_layered(Some(&path), &|_| None, &|_| Ok(SystemFacts::default())).unwrap();
+    let cfg = &layered.config;
+
+    assert_eq!(cfg.required_services, ["sshd", "net.eth0", "ui"]);
+    assert_eq!(cfg.timeout_seconds, 90);
+    assert_eq!(cfg.marker_backend, MarkerBackend::Dbus);
+    assert_eq!(cfg.retry.attempts, 9);
+    assert_eq!(cfg.retry.backoff_ms, 500);
+    assert_eq!(layered.files.len(), 3);
+}
+
+#[test]
+fn replace_drops_earlier_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = base(dir.path(), "optional_services = [\"ntpd\"]\n");
+    dropin(
+        dir.path(),
+        "10.toml",
+        "ignore_prefixes = { replace = [\"tty.\"] }\n",
+    );
+
+    let cfg = load_layered(Some(&path), &|_| None, &|_| Ok(SystemFacts::default()))
+        .unwrap()
+        .config;
+
+    assert_eq!(cfg.ignore_prefixes, ["tty."]);
+    assert_eq!(cfg.optional_services, ["ntpd"]);
+}
+
+#[test]
+fn print_config_shows_origin_of_every_value() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = base(dir.path(), "required_services = [\"sshd\"]\n");
+    dropin(
+        dir.path(),
+        "10-net.toml",
+        "required_services = [\"net.eth0\"]\n[uboot]\nboot_attempts = 5\n",
+    );
+    let dropin_file = dir.path().join("rauc-health.d/10-net.toml");
+
+    let out = load_layered(Some(&path), &|_| None, &|_| Ok(SystemFacts::default()))
+        .unwrap()
+        .render();
+
+    assert!(out.contains(&format!("    \"sshd\", # {}\n", path.display())));
+    assert!(out.contains(&format!("    \"net.eth0\", # {}\n", dropin_file.display())));
+    assert!(out.contains("timeout_seconds = 30 # default\n"));
+    assert!(out.contains(&format!(
+        "[uboot]\nboot_attempts = 5 # {}\n",
+        dropin_file.display()
+    )));
+}
+
+#[test]
+fn invalid_dropins_are_rejected_with_location() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = base(dir.path(), "");
+
+    dropin(
+        dir.path(),
+        "10.toml",
+        "timeout_seconds = 5\nrequried_services = []\n",
+    );
+    let err = load_layered(Some(&path), &|_| None, &|_| Ok(SystemFacts::default())).unwrap_err();
+    assert!(err.ends_with("10.toml: line 2, column 1: unknown field `requried_services`"));
+
+    dropin(dir.path(), "10.toml", "[retry]\nattempts = \"many\"\n");
+    let err = load_layered(Some(&path), &|_| None, &|_| Ok(SystemFacts::default())).unwrap_err();
+    assert!(err.ends_with("line 2, column 1: `retry.attempts` expects integer, got string"));
+
+    dropin(
+        dir.path(),
+        "10.toml",
+        "ignore_exact = { drop = [\"local\"] }\n",
+    );
+    let err = load_layered(Some(&path), &|_| None, &|_| Ok(SystemFacts::default())).unwrap_err();
+    assert!(err.contains("unknown list operation `drop`"));
+
+    dropin(
+        dir.path(),
+        "10.toml",
+        "required_services = [\"local\", \"x\"]\noptional_services = [\"x\"]\n",
+    );
+    let err = load_layered(Some(&path), &|_| None, &|_| Ok(SystemFacts::default())).unwrap_err();
+    let file = dir.path().join("rauc-health.d/10.toml");
+    assert_eq!(
+        err.lines().collect::<Vec<_>>(),
+        [
+            format!(
+                "merged config: {}: line 1, column 22: required service `local` is ignored by ignore_exact",
+                file.display()
+            ),
+            format!(
+                "merged config: {}: line 2, column 22: `x` is listed in both required_services and optional_services",
+                file.display()
+            ),
+        ]
+    );
+}
@@ -0,0 +1,97 @@
+// tests/grub_test.rs
+//
+// GRUB grubenv backend on temp files.
+
+use rauc_health::grub::{read_grubenv, GrubEnv, GrubMarker, BLOCK_SIZE, HEADER};
+use rauc_health::marker::{SlotMarker, SlotNames};
+use rauc_health::rauc::{MarkState, SlotIdentifier};
+use std::path::Path;
+
+fn block(vars: &str) -> Vec<u8> {
+    let mut b = format!("{HEADER}{vars}").into_bytes();
+    b.resize(BLOCK_SIZE, b'#');
+    b
+}
+
+fn grubenv(dir: &Path, vars: &str) -> std::path::PathBuf {
+    let path = dir.join("grubenv");
+    std::fs::write(&path, block(vars)).unwrap();
+    path
+}
+
+fn booted_a() -> SlotNames {
+    SlotNames {
+        booted: Some("A".into()),
+        bootnames: [("rootfs.0", "A"), ("rootfs.1", "B")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    }
+}
+
+#[test]
+fn block_roundtrip_keeps_size_and_padding() {
+    let b = block("ORDER=A B\nA_OK=1\nA_TRY=0\n");
+    let mut env = GrubEnv::parse(&b).unwrap();
+    assert_eq!(env.get("ORDER"), Some("A B"));
+
+    assert_eq!(env.to_block().unwrap(), b);
+
+    env.set("B_OK", "0");
+    let out = env.to_block().unwrap();
+    assert_eq!(out.len(), BLOCK_SIZE);
+    assert!(String::from_utf8(out).unwrap().contains("B_OK=0\n###"));
+}
+
+#[test]
+fn invalid_blocks_are_rejected() {
+    assert!(GrubEnv::parse(b"ORDER=A B\n")
+        .unwrap_err()
+        .contains("header"));
+    assert!(GrubEnv::parse(&block("garbage\n")).is_err());
+
+    let mut env = GrubEnv::parse(&block("")).unwrap();
+    env.set("X", &"y".repeat(BLOCK_SIZE));
+    assert!(env.to_block().unwrap_err().contains("too large"));
+}
+
+#[test]
+fn marker_uses_rauc_grub_variables() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = grubenv(dir.path(), "ORDER=A B\nA_OK=1\nA_TRY=1\nB_OK=1\nB_TRY=0\n");
+    let marker = GrubMarker::new(path.clone(), booted_a());
+
+    marker
+        .mark(MarkState::Good, &SlotIdentifier::Booted)
+        .unwrap();
+    let env = read_grubenv(&path).unwrap();
+    assert_eq!((env.get("A_OK"), env.get("A_TRY")), (Some("1"), Some("0")));
+
+    marker.mark(MarkState::Bad, &SlotIdentifier::Other).unwrap();
+    assert_eq!(read_grubenv(&path).unwrap().get("B_OK"), Some("0"));
+
+    marker
+        .mark(MarkState::Active, &SlotIdentifier::Named("rootfs.1".into()))
+        .unwrap();
+    let env = read_grubenv(&path).unwrap();
+    assert_eq!(env.get("ORDER"), Some("B A"));
+    assert_eq!(env.get("B_OK"), Some("1"));
+
+    assert_eq!(std::fs::read(&path).unwrap().len(), BLOCK_SIZE);
+    assert!(!dir.path().join("grubenv.tmp").exists());
+
+    let states = marker.slot_states().unwrap();
+    assert_eq!(states[0].bootname, "B");
+    assert_eq!(states[1].remaining_attempts, 1);
+}
+
+#[test]
+fn missing_order_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = grubenv(dir.path(), "saved_entry=0\n");
+
+    let err = GrubMarker::new(path, booted_a())
+        .mark(MarkState::Good, &SlotIdentifier::Booted)
+        .unwrap_err();
+    assert_eq!(err, "ORDER not set in grubenv");
+}
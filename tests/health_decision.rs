@@ -1,6 +1,6 @@
 use once_cell::sync::Lazy;
 use rauc_health::config::HealthConfig;
-use rauc_health::openrc::{decide_health, HealthDecision};
+use rauc_health::openrc::{decide_health, HealthDecision, ServiceState};
 
 static CRONCFG: Lazy<HealthConfig> = Lazy::new(|| HealthConfig {
     required_services: vec!["cron".to_string()],
@@ -33,11 +33,13 @@ cron [ stopped ]
     let decision = decide_health(input, &CRONCFG);
 
     match decision {
-        HealthDecision::Good => panic!("expected Bad, got Good"),
+        HealthDecision::Good | HealthDecision::Degraded(_) => {
+            panic!("expected Bad, got {decision:?}")
+        }
         HealthDecision::Bad(failed) => {
             assert!(failed
                 .iter()
-                .any(|s| s.name == "cron" && s.status == "stopped"));
+                .any(|s| s.name == "cron" && s.status == ServiceState::Stopped));
         }
     }
 }
@@ -0,0 +1,116 @@
+// tests/marker_test.rs
+//
+// Backend selection and the file-backed / dry-run markers.
+
+use rauc_health::config::{from_toml_str, HealthConfig, MarkerBackend};
+use rauc_health::marker::{
+    from_config, parse_system_conf_bootnames, DryRunMarker, FileMarker, SlotMarker, SlotNames,
+};
+use rauc_health::rauc::{MarkState, SlotIdentifier};
+
+fn names() -> SlotNames {
+    SlotNames {
+        booted: None,
+        bootnames: parse_system_conf_bootnames(
+            "[slot.rootfs.0]\ndevice=/dev/sda2\nbootname=A\n# [slot.rootfs.9]\n[slot.rootfs.1]\nbootname = B\n[slot.appfs.0]\ndevice=/dev/sda4\n",
+        ),
+    }
+}
+
+#[test]
+fn system_conf_bootnames_are_parsed() {
+    let n = names();
+    assert_eq!(n.bootnames.len(), 2);
+    assert_eq!(n.bootnames["rootfs.1"], "B");
+}
+
+#[test]
+fn file_marker_keeps_state_in_toml_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("slots.toml");
+    std::fs::write(&path, "booted = \"A\"\norder = [\"A\", \"B\"]\n").unwrap();
+    let marker = FileMarker::new(path.clone(), names());
+
+    marker.mark_good().unwrap();
+    marker.mark(MarkState::Bad, &SlotIdentifier::Other).unwrap();
+    marker
+        .mark_active(&SlotIdentifier::Named("rootfs.1".into()))
+        .unwrap();
+
+    let st = marker.read_state().unwrap();
+    assert_eq!(st.order, vec!["B", "A"]);
+    assert_eq!(st.slots["A"], "good");
+    assert_eq!(st.slots["B"], "good");
+
+    let states = marker.slot_states().unwrap();
+    assert_eq!(states[1].bootname, "B");
+    assert_eq!(states[1].order, Some(0));
+}
+
+#[test]
+fn file_marker_without_booted_slot_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let marker = FileMarker::new(dir.path().join("slots.toml"), names());
+
+    let err = marker.mark_good().unwrap_err();
+    assert!(err.contains("booted slot unknown"));
+}
+
+#[test]
+fn dry_run_marker_changes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("slots.toml");
+    std::fs::write(&path, "order = [\"A\"]\n[slots]\nA = \"good\"\n").unwrap();
+    let inner = FileMarker::new(path.clone(), names());
+    let marker = DryRunMarker::new(Some(Box::new(inner)));
+
+    marker.mark_bad().unwrap();
+
+    assert_eq!(marker.slot_states().unwrap()[0].remaining_attempts, 1);
+    assert!(DryRunMarker::new(None).slot_states().unwrap().is_empty());
+}
+
+#[test]
+fn dry_run_config_wraps_any_backend() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("slots.toml");
+    std::fs::write(&path, "order = [\"A\"]\n[slots]\nA = \"good\"\n").unwrap();
+    let cfg = HealthConfig {
+        marker_backend: MarkerBackend::File,
+        marker_file: path.clone(),
+        dry_run: true,
+        ..Default::default()
+    };
+    let before = std::fs::read(&path).unwrap();
+
+    let marker = from_config(&cfg).unwrap();
+    marker
+        .mark(MarkState::Bad, &SlotIdentifier::Named("A".into()))
+        .unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+    assert_eq!(marker.slot_states().unwrap()[0].bootname, "A");
+}
+
+#[test]
+fn backend_is_selected_from_config() {
+    let cfg = from_toml_str("marker_backend = \"dry-run\"").unwrap();
+    assert_eq!(cfg.marker_backend, MarkerBackend::DryRun);
+    from_config(&cfg).unwrap().mark_good().unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let cfg = HealthConfig {
+        marker_backend: MarkerBackend::File,
+        marker_file: dir.path().join("slots.toml"),
+        ..Default::default()
+    };
+    assert!(from_config(&cfg).unwrap().slot_states().unwrap().is_empty());
+
+    let mut cfg = HealthConfig {
+        marker_backend: MarkerBackend::Uboot,
+        ..Default::default()
+    };
+    cfg.uboot.fw_env_config = dir.path().join("fw_env.config");
+    let err = from_config(&cfg).err().unwrap();
+    assert!(err.contains("fw_env.config"));
+}
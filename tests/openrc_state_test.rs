@@ -0,0 +1,138 @@
+// tests/openrc_state_test.rs
+//
+// `OpenRC` state tree read from a temporary root.
+
+use rauc_health::config::HealthConfig;
+use rauc_health::openrc::{decide_health, HealthDecision, ServiceState, ServiceView};
+use rauc_health::openrc_state::StateTree;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+/// Links `name` into `dir` below `root` like `OpenRC` does.
+fn link(root: &Path, dir: &str, name: &str) {
+    let dir = root.join(dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    symlink(format!("/etc/init.d/{name}"), dir.join(name)).unwrap();
+}
+
+fn system(root: &Path) {
+    std::fs::create_dir_all(root.join("run/openrc")).unwrap();
+    std::fs::write(root.join("run/openrc/softlevel"), "default\n").unwrap();
+    for name in ["sshd", "ntpd", "cron"] {
+        link(root, "etc/runlevels/default", name);
+    }
+    link(root, "etc/runlevels/boot", "hostname");
+    for name in ["hostname", "sshd", "dbus"] {
+        link(root, "run/openrc/started", name);
+    }
+    link(root, "run/openrc/failed", "ntpd");
+    link(root, "run/openrc/hotplugged", "net.eth0");
+    link(root, "run/openrc/starting", "net.eth0");
+}
+
+#[test]
+fn renders_the_tree_like_rc_status() {
+    let dir = tempfile::tempdir().unwrap();
+    system(dir.path());
+    let tree = StateTree::new(dir.path());
+
+    assert_eq!(tree.softlevel().unwrap(), "default");
+    assert_eq!(
+        tree.read().unwrap(),
+        "Runlevel: boot\n hostname [ started ]\n\
+         Runlevel: default\n cron [ stopped ]\n ntpd [ failed ]\n sshd [ started ]\n\
+         Dynamic Runlevel: hotplugged\n net.eth0 [ starting ]\n\
+         Dynamic Runlevel: manual\n dbus [ started ]\n"
+    );
+}
+
+#[test]
+fn state_tree_feeds_the_same_check() {
+    let dir = tempfile::tempdir().unwrap();
+    system(dir.path());
+    let stdout = StateTree::new(dir.path()).read().unwrap();
+    let cfg = HealthConfig {
+        required_services: vec!["sshd".into(), "dbus".into()],
+        ..Default::default()
+    };
+
+    let view = ServiceView::new(&stdout, &cfg);
+    assert_eq!(view.status("dbus"), Some(&ServiceState::Started));
+    assert!(!view.checked.contains_key("hostname"));
+    assert_eq!(decide_health(&stdout, &cfg), HealthDecision::Good);
+
+    let cfg = HealthConfig {
+        required_services: vec!["net.eth0".into()],
+        ..Default::default()
+    };
+    assert!(matches!(
+        decide_health(&stdout, &cfg),
+        HealthDecision::Bad(_)
+    ));
+}
+
+#[test]
+fn missing_softlevel_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    link(dir.path(), "etc/runlevels/default", "sshd");
+
+    let err = StateTree::new(dir.path()).read().unwrap_err();
+    assert!(err.starts_with("OpenRC state not available: "), "{err}");
+    assert!(err.ends_with("run/openrc/softlevel: No such file or directory (os error 2)"));
+}
+
+#[test]
+fn started_services_with_dead_daemons_are_crashed() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    system(root);
+    let daemons = root.join("run/openrc/daemons");
+    std::fs::create_dir_all(daemons.join("sshd")).unwrap();
+    std::fs::create_dir_all(daemons.join("dbus")).unwrap();
+    std::fs::write(
+        daemons.join("sshd/001"),
+        "exec=/usr/sbin/sshd\nargv_0=/usr/sbin/sshd\npidfile=/run/sshd.pid\n",
+    )
+    .unwrap();
+    std::fs::write(
+        daemons.join("dbus/001"),
+        "exec=/usr/bin/dbus-daemon\npidfile=\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("run/sshd.pid"), "1234\n").unwrap();
+    std::fs::create_dir_all(root.join("proc/1234")).unwrap();
+    std::fs::create_dir_all(root.join("proc/99")).unwrap();
+    std::fs::write(
+        root.join("proc/99/cmdline"),
+        "/usr/bin/dbus-daemon\0--system\0",
+    )
+    .unwrap();
+    let tree = StateTree::new(root);
+
+    assert!(!tree.crashed("sshd").unwrap());
+    assert!(!tree.crashed("dbus").unwrap());
+    assert!(!tree.crashed("hostname").unwrap());
+
+    std::fs::remove_dir(root.join("proc/1234")).unwrap();
+    std::fs::remove_file(root.join("proc/99/cmdline")).unwrap();
+    assert!(tree.crashed("sshd").unwrap());
+    let stdout = tree.read().unwrap();
+    assert!(stdout.contains(" sshd [ crashed ]\n"), "{stdout}");
+    assert!(stdout.contains(" dbus [ crashed ]\n"), "{stdout}");
+
+    std::fs::remove_file(root.join("run/sshd.pid")).unwrap();
+    assert!(tree.crashed("sshd").unwrap());
+}
+
+#[test]
+fn stacked_runlevels_are_not_services() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    system(root);
+    symlink("../boot", root.join("etc/runlevels/default/boot")).unwrap();
+    std::fs::create_dir_all(root.join("etc/runlevels/default/custom")).unwrap();
+
+    let stdout = StateTree::new(root).read().unwrap();
+    assert!(!stdout.contains(" boot ["), "{stdout}");
+    assert!(!stdout.contains(" custom ["), "{stdout}");
+}
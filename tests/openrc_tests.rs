@@ -2,12 +2,20 @@
 
 use once_cell::sync::Lazy;
 
+use rauc_health::boot::{BootAssessment, BootKind, Rollback};
 use rauc_health::cli::CheckOpenrcArgs;
-use rauc_health::config::HealthConfig;
+use rauc_health::config::{
+    CheckMode, DegradedPolicy, HealthConfig, NormalBootPolicy, ServiceStatesConfig, StatePolicy,
+    TrialBootDetection,
+};
+use rauc_health::marker::{SlotBootState, SlotMarker};
 use rauc_health::openrc::{
     check_openrc_and_mark_with, check_openrc_and_mark_with_cfg, collect_failed_services,
-    decide_health, parse_services_map, HealthDecision, OpenRcDeps,
+    collect_ignored_services, decide_health, parse_runlevels, parse_services_map, CheckOutcome,
+    FailedService, HealthDecision, IgnoreRule, IgnoredService, OpenRcDeps, ServiceState,
+    EXIT_DEGRADED, EXIT_ROLLBACK,
 };
+use rauc_health::rauc::{MarkState, SlotIdentifier};
 
 use std::time::{Duration, Instant};
 
@@ -42,8 +50,8 @@ static MULTICFG: Lazy<HealthConfig> = Lazy::new(|| HealthConfig {
 fn args() -> CheckOpenrcArgs {
     CheckOpenrcArgs {
         config: None,
-        timeout_secunds: 30,
-        poll_interval_ms: 50,
+        timeout_seconds: Some(30),
+        poll_interval_ms: Some(50),
     }
 }
 
@@ -51,8 +59,8 @@ fn args() -> CheckOpenrcArgs {
 fn args_timeout_immediate() -> CheckOpenrcArgs {
     CheckOpenrcArgs {
         config: None,
-        timeout_secunds: 0,
-        poll_interval_ms: 1,
+        timeout_seconds: Some(0),
+        poll_interval_ms: Some(1),
     }
 }
 
@@ -67,15 +75,70 @@ fn cfg_required_cron() -> HealthConfig {
 // Helper fns for deps (fn-items are 'static -> no lifetime issues)
 // ------------------------------------------------------------
 
-fn run_rc_ok() -> Result<String, String> {
+fn run_rc_ok(_timeout: Duration) -> Result<String, String> {
     Ok("Runlevel: default\ncron [ started ]\n".to_string())
 }
 
-fn run_rc_bad() -> Result<String, String> {
+fn run_rc_bad(_timeout: Duration) -> Result<String, String> {
     Ok("Runlevel: default\ncron [ stopped ]\n".to_string())
 }
 
-fn mark_ok() -> Result<(), String> {
+/// Accepts every mark without touching a bootloader.
+struct OkMarker;
+
+impl SlotMarker for OkMarker {
+    fn mark(&self, _state: MarkState, _slot: &SlotIdentifier) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String> {
+        Ok(Vec::new())
+    }
+}
+
+/// Records every mark.
+#[derive(Default)]
+struct RecordingMarker {
+    marks: std::cell::RefCell<Vec<(MarkState, SlotIdentifier)>>,
+}
+
+impl SlotMarker for RecordingMarker {
+    fn mark(&self, state: MarkState, slot: &SlotIdentifier) -> Result<(), String> {
+        self.marks.borrow_mut().push((state, slot.clone()));
+        Ok(())
+    }
+
+    fn slot_states(&self) -> Result<Vec<SlotBootState>, String> {
+        Ok(Vec::new())
+    }
+}
+
+fn record_noop(_d: &HealthDecision) -> Result<(), String> {
+    Ok(())
+}
+
+fn trial_boot() -> BootAssessment {
+    BootAssessment {
+        kind: BootKind::Trial,
+        reason: "test".into(),
+        booted_slot: Some("rootfs.1".into()),
+        installed: None,
+        rollback: None,
+    }
+}
+
+fn normal_boot() -> BootAssessment {
+    BootAssessment {
+        kind: BootKind::Normal,
+        ..trial_boot()
+    }
+}
+
+fn confirm_noop(_b: &BootAssessment) -> Result<(), String> {
+    Ok(())
+}
+
+fn rollback_noop(_r: &Rollback) -> Result<(), String> {
     Ok(())
 }
 
@@ -88,8 +151,11 @@ fn now_real() -> Instant {
 fn deps_ok<'a>() -> OpenRcDeps<'a> {
     OpenRcDeps {
         run_rc_status: &run_rc_ok,
-        mark_good: &mark_ok,
-        mark_bad: &mark_ok,
+        marker: &OkMarker,
+        record_decision: &record_noop,
+        assess_boot: &trial_boot,
+        confirm_boot: &confirm_noop,
+        handle_rollback: &rollback_noop,
         sleep: &sleep_noop,
         now: &now_real,
     }
@@ -98,8 +164,11 @@ fn deps_ok<'a>() -> OpenRcDeps<'a> {
 fn deps_bad<'a>() -> OpenRcDeps<'a> {
     OpenRcDeps {
         run_rc_status: &run_rc_bad,
-        mark_good: &mark_ok,
-        mark_bad: &mark_ok,
+        marker: &OkMarker,
+        record_decision: &record_noop,
+        assess_boot: &trial_boot,
+        confirm_boot: &confirm_noop,
+        handle_rollback: &rollback_noop,
         sleep: &sleep_noop,
         now: &now_real,
     }
@@ -121,7 +190,7 @@ sshd [ started ]
 
     assert_eq!(failed.len(), 1);
     assert_eq!(failed[0].name, "cron");
-    assert_eq!(failed[0].status, "stopped");
+    assert_eq!(failed[0].status, ServiceState::Stopped);
 }
 
 #[test]
@@ -190,7 +259,7 @@ cron [ started ]
 
     assert_eq!(failed.len(), 1);
     assert_eq!(failed[0].name, "sshd");
-    assert_eq!(failed[0].status, "missing");
+    assert_eq!(failed[0].status, ServiceState::Missing);
 }
 
 #[test]
@@ -222,10 +291,10 @@ sshd [ started ]
     assert_eq!(failed.len(), 2);
     assert!(failed
         .iter()
-        .any(|f| f.name == "cron" && f.status == "stopped"));
+        .any(|f| f.name == "cron" && f.status == ServiceState::Stopped));
     assert!(failed
         .iter()
-        .any(|f| f.name == "dbus" && f.status == "missing"));
+        .any(|f| f.name == "dbus" && f.status == ServiceState::Missing));
 }
 
 #[test]
@@ -259,7 +328,7 @@ cron [ Started ]
     let failed = collect_failed_services(input, &cfg);
 
     assert_eq!(failed.len(), 1);
-    assert_eq!(failed[0].status, "Started");
+    assert_eq!(failed[0].status, ServiceState::Unknown("Started".into()));
 }
 
 // ------------------------------------------------------------
@@ -348,7 +417,7 @@ fn decide_health_bad_contains_failed_services() {
 #[test]
 fn check_openrc_marks_good() {
     let res = check_openrc_and_mark_with(&args(), &cfg_required_cron(), &deps_ok());
-    assert!(res.is_ok());
+    assert_eq!(res, Ok(CheckOutcome::MarkedGood));
 }
 
 #[test]
@@ -361,8 +430,675 @@ fn check_openrc_timeout_errors() {
     assert!(err.contains("OpenRC health check failed"));
 }
 
+#[test]
+fn rc_status_timeout_is_bounded_by_the_deadline() {
+    use std::cell::{Cell, RefCell};
+
+    let clock = Cell::new(Instant::now());
+    let timeouts = RefCell::new(Vec::new());
+    let rc_status = |timeout: Duration| {
+        timeouts.borrow_mut().push(timeout);
+        // a slow rc-status eats into the 30 s budget
+        clock.set(clock.get() + Duration::from_secs(20));
+        run_rc_bad(timeout)
+    };
+    let now = || clock.get();
+    let deps = OpenRcDeps {
+        run_rc_status: &rc_status,
+        now: &now,
+        ..deps_bad()
+    };
+
+    let res = check_openrc_and_mark_with(&args(), &CRONCFG, &deps);
+
+    assert!(res.is_err());
+    assert_eq!(
+        timeouts.into_inner(),
+        vec![Duration::from_secs(30), Duration::from_secs(10)]
+    );
+}
+
+#[test]
+fn timing_comes_from_config_unless_given_on_command_line() {
+    use std::cell::{Cell, RefCell};
+
+    let clock = Cell::new(Instant::now());
+    let timeouts = RefCell::new(Vec::new());
+    let sleeps = RefCell::new(Vec::new());
+    let rc_status = |timeout: Duration| {
+        timeouts.borrow_mut().push(timeout);
+        run_rc_bad(timeout)
+    };
+    let sleep = |d: Duration| {
+        sleeps.borrow_mut().push(d);
+        clock.set(clock.get() + Duration::from_secs(40));
+    };
+    let now = || clock.get();
+    let deps = OpenRcDeps {
+        run_rc_status: &rc_status,
+        sleep: &sleep,
+        now: &now,
+        ..deps_bad()
+    };
+    let cfg = HealthConfig {
+        timeout_seconds: 40,
+        poll_interval_ms: 250,
+        ..cfg_required_cron()
+    };
+    let no_cli = CheckOpenrcArgs {
+        config: None,
+        timeout_seconds: None,
+        poll_interval_ms: None,
+    };
+
+    assert!(check_openrc_and_mark_with(&no_cli, &cfg, &deps).is_err());
+    assert_eq!(timeouts.take()[0], Duration::from_secs(40));
+    assert_eq!(sleeps.take(), vec![Duration::from_millis(250)]);
+
+    assert!(check_openrc_and_mark_with(&args(), &cfg, &deps).is_err());
+    assert_eq!(timeouts.take()[0], Duration::from_secs(30));
+    assert_eq!(sleeps.take(), vec![Duration::from_millis(50)]);
+}
+
 #[test]
 fn check_openrc_cfg_variant_works() {
     let res = check_openrc_and_mark_with_cfg(&args(), &cfg_required_cron(), &deps_ok());
     assert!(res.is_ok());
 }
+
+#[test]
+fn check_openrc_records_decision_before_marking() {
+    let recorded = std::cell::RefCell::new(Vec::new());
+    let record = |d: &HealthDecision| {
+        recorded.borrow_mut().push(d.clone());
+        Ok(())
+    };
+    let deps = OpenRcDeps {
+        record_decision: &record,
+        ..deps_bad()
+    };
+
+    let _ = check_openrc_and_mark_with(&args_timeout_immediate(), &cfg_required_cron(), &deps);
+
+    let recorded = recorded.into_inner();
+    assert_eq!(recorded.len(), 1);
+    assert!(matches!(&recorded[0], HealthDecision::Bad(f) if f[0].name == "cron"));
+}
+
+#[test]
+fn check_openrc_record_failure_does_not_abort() {
+    let record = |_d: &HealthDecision| Err("disk full".to_string());
+    let deps = OpenRcDeps {
+        record_decision: &record,
+        ..deps_ok()
+    };
+
+    let res = check_openrc_and_mark_with(&args(), &cfg_required_cron(), &deps);
+    assert!(res.is_ok());
+}
+
+// ------------------------------------------------------------
+// trial / normal boot handling
+// ------------------------------------------------------------
+
+fn cfg_auto(policy: NormalBootPolicy) -> HealthConfig {
+    HealthConfig {
+        trial_boot_detection: TrialBootDetection::Auto,
+        normal_boot_policy: policy,
+        ..cfg_required_cron()
+    }
+}
+
+#[test]
+fn normal_boot_lenient_does_not_mark_bad() {
+    let marker = RecordingMarker::default();
+    let deps = OpenRcDeps {
+        assess_boot: &normal_boot,
+        marker: &marker,
+        ..deps_bad()
+    };
+
+    let res = check_openrc_and_mark_with(
+        &args_timeout_immediate(),
+        &cfg_auto(NormalBootPolicy::Lenient),
+        &deps,
+    );
+
+    assert_eq!(res, Ok(CheckOutcome::NotMarked));
+    assert!(marker.marks.into_inner().is_empty());
+}
+
+#[test]
+fn normal_boot_skip_does_not_check() {
+    let rc_status = |_| -> Result<String, String> { panic!("must not query rc-status") };
+    let deps = OpenRcDeps {
+        assess_boot: &normal_boot,
+        run_rc_status: &rc_status,
+        ..deps_ok()
+    };
+
+    let res = check_openrc_and_mark_with(&args(), &cfg_auto(NormalBootPolicy::Skip), &deps);
+    assert!(res.is_ok());
+}
+
+#[test]
+fn normal_boot_strict_marks_bad() {
+    let deps = OpenRcDeps {
+        assess_boot: &normal_boot,
+        ..deps_bad()
+    };
+
+    let err = check_openrc_and_mark_with(
+        &args_timeout_immediate(),
+        &cfg_auto(NormalBootPolicy::Strict),
+        &deps,
+    )
+    .unwrap_err();
+    assert!(err.contains("OpenRC health check failed"));
+}
+
+#[test]
+fn trial_boot_is_confirmed_after_mark_good() {
+    let confirmed = std::cell::RefCell::new(None);
+    let confirm = |b: &BootAssessment| {
+        *confirmed.borrow_mut() = b.booted_slot.clone();
+        Ok(())
+    };
+    let deps = OpenRcDeps {
+        confirm_boot: &confirm,
+        ..deps_ok()
+    };
+
+    check_openrc_and_mark_with(&args(), &cfg_auto(NormalBootPolicy::Skip), &deps).unwrap();
+    assert_eq!(confirmed.into_inner().as_deref(), Some("rootfs.1"));
+}
+
+#[test]
+fn detection_off_applies_strict_gate_on_normal_boot() {
+    let deps = OpenRcDeps {
+        assess_boot: &normal_boot,
+        ..deps_bad()
+    };
+
+    let err = check_openrc_and_mark_with(&args_timeout_immediate(), &cfg_required_cron(), &deps)
+        .unwrap_err();
+    assert!(err.contains("OpenRC health check failed"));
+}
+
+// ------------------------------------------------------------
+// rollback handling
+// ------------------------------------------------------------
+
+fn rollback_boot() -> BootAssessment {
+    BootAssessment {
+        kind: BootKind::Trial,
+        reason: "test".into(),
+        booted_slot: Some("rootfs.0".into()),
+        installed: None,
+        rollback: Some(Rollback {
+            booted: "A".into(),
+            expected_slot: "rootfs.1".into(),
+            expected_bootname: Some("B".into()),
+        }),
+    }
+}
+
+#[test]
+fn rollback_is_reported_and_not_confirmed() {
+    let handled = std::cell::RefCell::new(None);
+    let handle = |r: &Rollback| {
+        *handled.borrow_mut() = Some(r.expected_slot.clone());
+        Ok(())
+    };
+    let confirm = |_b: &BootAssessment| -> Result<(), String> { panic!("must not confirm") };
+    let deps = OpenRcDeps {
+        assess_boot: &rollback_boot,
+        handle_rollback: &handle,
+        confirm_boot: &confirm,
+        ..deps_ok()
+    };
+
+    let outcome =
+        check_openrc_and_mark_with(&args(), &cfg_auto(NormalBootPolicy::Skip), &deps).unwrap();
+
+    assert!(matches!(&outcome, CheckOutcome::RollbackOccurred(r) if r.booted == "A"));
+    assert_eq!(outcome.exit_code(), EXIT_ROLLBACK);
+    assert_eq!(handled.into_inner().as_deref(), Some("rootfs.1"));
+}
+
+#[test]
+fn failing_rollback_handling_does_not_abort_the_check() {
+    let handle = |_r: &Rollback| -> Result<(), String> { Err("mark-bad failed".into()) };
+    let marker = RecordingMarker::default();
+    let deps = OpenRcDeps {
+        assess_boot: &rollback_boot,
+        handle_rollback: &handle,
+        marker: &marker,
+        ..deps_ok()
+    };
+
+    let outcome =
+        check_openrc_and_mark_with(&args(), &cfg_auto(NormalBootPolicy::Skip), &deps).unwrap();
+
+    assert!(matches!(outcome, CheckOutcome::RollbackOccurred(_)));
+    assert_eq!(
+        marker.marks.into_inner(),
+        vec![(MarkState::Good, SlotIdentifier::Booted)]
+    );
+}
+
+#[test]
+fn unhealthy_fallback_slot_is_not_marked_bad() {
+    let marker = RecordingMarker::default();
+    let deps = OpenRcDeps {
+        assess_boot: &rollback_boot,
+        marker: &marker,
+        ..deps_bad()
+    };
+
+    let outcome = check_openrc_and_mark_with(
+        &args_timeout_immediate(),
+        &cfg_auto(NormalBootPolicy::Lenient),
+        &deps,
+    )
+    .unwrap();
+    assert!(matches!(&outcome, CheckOutcome::RollbackOccurred(r) if r.booted == "A"));
+    assert_eq!(outcome.exit_code(), EXIT_ROLLBACK);
+    assert!(marker.marks.into_inner().is_empty());
+}
+
+// ------------------------------------------------------------
+// optional services / degraded
+// ------------------------------------------------------------
+
+fn cfg_optional_ntpd(policy: DegradedPolicy) -> HealthConfig {
+    HealthConfig {
+        optional_services: vec!["ntpd".into()],
+        degraded_policy: policy,
+        ..cfg_required_cron()
+    }
+}
+
+fn run_rc_degraded(_timeout: Duration) -> Result<String, String> {
+    Ok("Runlevel: default\ncron [ started ]\nntpd [ stopped ]\n".to_string())
+}
+
+#[test]
+fn decide_health_degraded_only_when_required_services_are_healthy() {
+    let cfg = cfg_optional_ntpd(DegradedPolicy::MarkGood);
+
+    assert_eq!(
+        decide_health("cron [ started ]\n", &cfg),
+        HealthDecision::Degraded(vec![FailedService {
+            name: "ntpd".into(),
+            status: ServiceState::Missing,
+        }])
+    );
+    assert_eq!(
+        decide_health("cron [ started ]\nntpd [ started ]\n", &cfg),
+        HealthDecision::Good
+    );
+    assert!(matches!(
+        decide_health("cron [ stopped ]\nntpd [ stopped ]\n", &cfg),
+        HealthDecision::Bad(f) if f.len() == 1 && f[0].name == "cron"
+    ));
+}
+
+#[test]
+fn degraded_boot_is_marked_good_with_own_exit_code() {
+    let marker = RecordingMarker::default();
+    let recorded = std::cell::RefCell::new(Vec::new());
+    let record = |d: &HealthDecision| {
+        recorded.borrow_mut().push(d.clone());
+        Ok(())
+    };
+    let deps = OpenRcDeps {
+        run_rc_status: &run_rc_degraded,
+        marker: &marker,
+        record_decision: &record,
+        ..deps_ok()
+    };
+
+    let outcome = check_openrc_and_mark_with(
+        &args_timeout_immediate(),
+        &cfg_optional_ntpd(DegradedPolicy::MarkGood),
+        &deps,
+    )
+    .unwrap();
+
+    assert!(matches!(&outcome, CheckOutcome::Degraded(f) if f[0].name == "ntpd"));
+    assert_eq!(outcome.exit_code(), EXIT_DEGRADED);
+    assert_eq!(
+        marker.marks.into_inner(),
+        vec![(MarkState::Good, SlotIdentifier::Booted)]
+    );
+    assert!(matches!(&recorded.borrow()[0], HealthDecision::Degraded(_)));
+}
+
+#[test]
+fn init_script_does_not_reboot_on_rollback_or_degraded() {
+    let script = include_str!("../service/rauc-health.initd");
+    for code in [EXIT_ROLLBACK, EXIT_DEGRADED] {
+        let branch = script
+            .split(&format!("if [ ${{rc}} -eq {code} ]; then"))
+            .nth(1)
+            .unwrap_or_else(|| panic!("init script does not handle exit code {code}"));
+        let branch = &branch[..branch.find("fi\n").unwrap()];
+        assert!(branch.contains("return 0"), "exit code {code}");
+        assert!(!branch.contains("reboot"), "exit code {code}");
+    }
+}
+
+#[test]
+fn degraded_policy_can_leave_unmarked_or_mark_bad() {
+    let marker = RecordingMarker::default();
+    let deps = OpenRcDeps {
+        run_rc_status: &run_rc_degraded,
+        marker: &marker,
+        ..deps_ok()
+    };
+    let outcome = check_openrc_and_mark_with(
+        &args_timeout_immediate(),
+        &cfg_optional_ntpd(DegradedPolicy::Unmarked),
+        &deps,
+    )
+    .unwrap();
+    assert_eq!(outcome.exit_code(), EXIT_DEGRADED);
+    assert!(marker.marks.borrow().is_empty());
+
+    let err = check_openrc_and_mark_with(
+        &args_timeout_immediate(),
+        &cfg_optional_ntpd(DegradedPolicy::MarkBad),
+        &deps,
+    )
+    .unwrap_err();
+    assert!(err.contains("1 failing optional services"));
+    assert_eq!(
+        marker.marks.into_inner(),
+        vec![(MarkState::Bad, SlotIdentifier::Booted)]
+    );
+}
+
+#[test]
+fn degraded_fallback_boot_still_reports_the_rollback() {
+    let marker = RecordingMarker::default();
+    let deps = OpenRcDeps {
+        run_rc_status: &run_rc_degraded,
+        assess_boot: &rollback_boot,
+        marker: &marker,
+        ..deps_ok()
+    };
+    for policy in [DegradedPolicy::Unmarked, DegradedPolicy::MarkBad] {
+        let outcome = check_openrc_and_mark_with(
+            &args_timeout_immediate(),
+            &cfg_optional_ntpd(policy),
+            &deps,
+        )
+        .unwrap();
+        assert_eq!(outcome.exit_code(), EXIT_ROLLBACK);
+    }
+    assert!(marker.marks.into_inner().is_empty());
+}
+
+// ------------------------------------------------------------
+// check_mode = "all"
+// ------------------------------------------------------------
+
+const RUNLEVEL_ALL: &str = "Runlevel: default
+ cron        [ started ]
+ sshd        [ stopped ]
+ getty.tty1  [ stopped ]
+ local       [ stopped ]
+ ntpd        [ stopped ]
+";
+
+fn cfg_all() -> HealthConfig {
+    HealthConfig {
+        check_mode: CheckMode::All,
+        required_services: vec!["dbus".into()],
+        optional_services: vec!["ntpd".into()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn all_mode_checks_every_listed_service() {
+    let failed = collect_failed_services(RUNLEVEL_ALL, &cfg_all());
+    let names: Vec<_> = failed.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["dbus", "sshd"]);
+    assert_eq!(failed[0].status, ServiceState::Missing);
+
+    // only required_services in the default mode
+    let cfg = HealthConfig {
+        check_mode: CheckMode::Required,
+        ..cfg_all()
+    };
+    assert_eq!(collect_failed_services(RUNLEVEL_ALL, &cfg).len(), 1);
+
+    let healthy = RUNLEVEL_ALL.replace(
+        "sshd        [ stopped ]",
+        "sshd [ started ]\n dbus [ started ]",
+    );
+    assert!(matches!(
+        decide_health(&healthy, &cfg_all()),
+        HealthDecision::Degraded(f) if f.len() == 1 && f[0].name == "ntpd"
+    ));
+}
+
+#[test]
+fn all_mode_skips_the_calling_service() {
+    let stdout = "Runlevel: default\n sshd [ started ]\n rauc-health [ starting ]\n";
+    let cfg = HealthConfig {
+        check_mode: CheckMode::All,
+        ..Default::default()
+    };
+    assert!(matches!(
+        decide_health(stdout, &cfg),
+        HealthDecision::Bad(f) if f[0].name == "rauc-health" && f[0].status == ServiceState::Starting
+    ));
+
+    let cfg = HealthConfig {
+        caller: Some("rauc-health".into()),
+        ..cfg
+    };
+    assert_eq!(decide_health(stdout, &cfg), HealthDecision::Good);
+    assert_eq!(
+        collect_ignored_services(stdout, &cfg),
+        vec![IgnoredService {
+            name: "rauc-health".into(),
+            status: ServiceState::Starting,
+            rule: IgnoreRule::Caller,
+        }]
+    );
+}
+
+#[test]
+fn ignored_services_name_the_matching_rule() {
+    assert_eq!(
+        collect_ignored_services(RUNLEVEL_ALL, &cfg_all()),
+        vec![
+            IgnoredService {
+                name: "getty.tty1".into(),
+                status: ServiceState::Stopped,
+                rule: IgnoreRule::Prefix("getty.".into()),
+            },
+            IgnoredService {
+                name: "local".into(),
+                status: ServiceState::Stopped,
+                rule: IgnoreRule::Exact("local".into()),
+            },
+        ]
+    );
+    assert_eq!(
+        IgnoreRule::Prefix("getty.".into()).to_string(),
+        "ignore_prefixes `getty.`"
+    );
+}
+
+// ------------------------------------------------------------
+// runlevels
+// ------------------------------------------------------------
+
+const RC_STATUS_ALL: &str = "Runlevel: boot
+ net.eth0    [ started ]
+ hwclock     [ started ]
+Runlevel: default
+ sshd        [ stopped ]
+ cron        [ started ]
+Runlevel: shutdown
+ savecache   [ stopped ]
+Dynamic Runlevel: hotplugged
+ net.wlan0   [ started ]
+Dynamic Runlevel: needed/wanted
+ sshd        [ started ]
+ dbus        [ started ]
+Dynamic Runlevel: manual
+";
+
+#[test]
+fn parse_runlevels_keeps_sections_apart() {
+    let sections = parse_runlevels(RC_STATUS_ALL);
+    let names: Vec<_> = sections
+        .iter()
+        .map(|s| (s.name.as_str(), s.dynamic, s.services.len()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("boot", false, 2),
+            ("default", false, 2),
+            ("shutdown", false, 1),
+            ("hotplugged", true, 1),
+            ("needed/wanted", true, 2),
+            ("manual", true, 0),
+        ]
+    );
+    assert_eq!(
+        sections[1].services.get("sshd"),
+        Some(&ServiceState::Stopped)
+    );
+
+    // output without headers (single runlevel) forms one unnamed section
+    let sections = parse_runlevels("cron [ started ]\n");
+    assert_eq!(sections.len(), 1);
+    assert_eq!(sections[0].name, "");
+}
+
+#[test]
+fn dynamic_runlevels_do_not_hide_checked_services() {
+    // sshd is stopped in default, even though needed/wanted lists it started
+    let failed = collect_failed_services(RC_STATUS_ALL, &SSHDCFG);
+    assert_eq!(failed[0].status, ServiceState::Stopped);
+
+    // a required service started only as a dependency or by hotplug counts
+    let cfg = HealthConfig {
+        required_services: vec!["dbus".into(), "net.wlan0".into(), "savecache".into()],
+        ..Default::default()
+    };
+    let failed = collect_failed_services(RC_STATUS_ALL, &cfg);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(
+        (failed[0].name.as_str(), failed[0].status.as_str()),
+        ("savecache", "missing")
+    );
+}
+
+#[test]
+fn all_mode_checks_only_configured_runlevels() {
+    let cfg = HealthConfig {
+        check_mode: CheckMode::All,
+        ..Default::default()
+    };
+    let names = |cfg: &HealthConfig| -> Vec<String> {
+        collect_failed_services(RC_STATUS_ALL, cfg)
+            .into_iter()
+            .map(|f| f.name)
+            .collect()
+    };
+    assert_eq!(names(&cfg), vec!["sshd"]);
+
+    let cfg = HealthConfig {
+        runlevels: vec!["boot".into(), "shutdown".into()],
+        ..cfg
+    };
+    assert_eq!(names(&cfg), vec!["savecache"]);
+}
+
+// ------------------------------------------------------------
+// service states
+// ------------------------------------------------------------
+
+#[test]
+fn service_states_keep_the_raw_text() {
+    for raw in ["started", "crashed", "hotplugged", "Started", "wedged"] {
+        assert_eq!(ServiceState::parse(raw).as_str(), raw);
+    }
+    assert_eq!(ServiceState::parse("crashed"), ServiceState::Crashed);
+    assert_eq!(
+        ServiceState::parse("Started"),
+        ServiceState::Unknown("Started".into())
+    );
+    assert_eq!(
+        ServiceState::Crashed.policy(&HealthConfig::default()),
+        StatePolicy::Fail
+    );
+}
+
+#[test]
+fn crashed_service_fails_without_waiting_for_the_timeout() {
+    let marker = RecordingMarker::default();
+    let rc_status = |_| Ok("Runlevel: default\ncron [ crashed ]\n".to_string());
+    let sleep = |_| panic!("must not wait for a crashed service");
+    let deps = OpenRcDeps {
+        run_rc_status: &rc_status,
+        marker: &marker,
+        sleep: &sleep,
+        ..deps_ok()
+    };
+
+    let err = check_openrc_and_mark_with(&args(), &cfg_required_cron(), &deps).unwrap_err();
+
+    assert!(err.contains("OpenRC health check failed"));
+    assert_eq!(
+        marker.marks.into_inner(),
+        vec![(MarkState::Bad, SlotIdentifier::Booted)]
+    );
+}
+
+#[test]
+fn state_policies_are_configurable() {
+    let cfg = HealthConfig {
+        service_states: ServiceStatesConfig {
+            hotplugged: StatePolicy::Healthy,
+            stopped: StatePolicy::Fail,
+            crashed: StatePolicy::Wait,
+            ..Default::default()
+        },
+        ..cfg_required_cron()
+    };
+
+    assert_eq!(
+        decide_health("cron [ hotplugged ]\n", &cfg),
+        HealthDecision::Good
+    );
+
+    let stopped = |_| Ok("cron [ stopped ]\n".to_string());
+    let sleep = |_| panic!("stopped must fail immediately");
+    let deps = OpenRcDeps {
+        run_rc_status: &stopped,
+        sleep: &sleep,
+        ..deps_ok()
+    };
+    assert!(check_openrc_and_mark_with(&args(), &cfg, &deps).is_err());
+
+    let crashed = |_| Ok("cron [ crashed ]\n".to_string());
+    let waited = std::cell::Cell::new(0);
+    let sleep = |_| waited.set(waited.get() + 1);
+    let deps = OpenRcDeps {
+        run_rc_status: &crashed,
+        sleep: &sleep,
+        ..deps_ok()
+    };
+    assert!(check_openrc_and_mark_with(&args_timeout_immediate(), &cfg, &deps).is_err());
+    assert_eq!(waited.get(), 0);
+}
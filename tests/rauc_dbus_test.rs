@@ -0,0 +1,253 @@
+// tests/rauc_dbus_test.rs
+//
+// Runs the D-Bus backend against a private session bus with a fake RAUC
+// service object. Skipped if `dbus-daemon` is not installed.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+use rauc_health::dbus::{self, Connection, Message, MessageType, Value};
+use rauc_health::rauc::RetryPolicy;
+use rauc_health::rauc_dbus::{
+    boot_slot, get_slot_status, mark_or_fallback, mark_with, operation, INSTALLER_IFACE, SERVICE,
+};
+
+// ------------------------------------------------------------
+// Private bus
+// ------------------------------------------------------------
+
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+fn start_bus() -> Option<Bus> {
+    let mut daemon = Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address=1"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let mut line = String::new();
+    let stdout = daemon.stdout.take()?;
+    BufReader::new(stdout).read_line(&mut line).ok()?;
+    Some(Bus {
+        daemon,
+        address: line.trim().to_string(),
+    })
+}
+
+// ------------------------------------------------------------
+// Fake RAUC service
+// ------------------------------------------------------------
+
+fn slot(name: &str, bootname: &str, state: &str) -> Value {
+    let entry = |k: &str, v: &str| {
+        Value::DictEntry(
+            Box::new(Value::Str(k.into())),
+            Box::new(Value::Variant(Box::new(Value::Str(v.into())))),
+        )
+    };
+    Value::Struct(vec![
+        Value::Str(name.into()),
+        Value::Array(
+            "{sv}".into(),
+            vec![
+                entry("class", "rootfs"),
+                entry("bootname", bootname),
+                entry("state", state),
+            ],
+        ),
+    ])
+}
+
+fn handle(call: &Message, operation: &str) -> Message {
+    match call.member.as_deref() {
+        Some("Mark") => {
+            let state = call.body[0].as_str().unwrap_or_default();
+            if state == "explode" {
+                return Message::error_reply(call, "org.gtk.GDBus.UnmappedGError.Quark", "nope");
+            }
+            Message::method_return(
+                call,
+                vec![
+                    Value::Str("rootfs.0".into()),
+                    Value::Str(format!("marked slot rootfs.0 as {state}")),
+                ],
+            )
+        }
+        Some("GetSlotStatus") => Message::method_return(
+            call,
+            vec![Value::Array(
+                "(sa{sv})".into(),
+                vec![
+                    slot("rootfs.0", "A", "booted"),
+                    slot("rootfs.1", "B", "inactive"),
+                ],
+            )],
+        ),
+        Some("Get") => {
+            let value = match call.body[1].as_str() {
+                Some("BootSlot") => "A",
+                Some("Operation") => operation,
+                _ => return Message::error_reply(call, "org.freedesktop.DBus.Error", "?"),
+            };
+            Message::method_return(
+                call,
+                vec![Value::Variant(Box::new(Value::Str(value.into())))],
+            )
+        }
+        _ => Message::error_reply(call, "org.freedesktop.DBus.Error.UnknownMethod", "?"),
+    }
+}
+
+/// Hosts the fake service reporting `operation`; forwards each received
+/// call to `seen`.
+fn spawn_fake_rauc(address: &str, operation: &'static str, seen: mpsc::Sender<Message>) {
+    let mut conn = Connection::open(address).unwrap();
+    conn.request_name(SERVICE).unwrap();
+
+    thread::spawn(move || {
+        while let Ok(msg) = conn.read_message() {
+            if msg.msg_type != MessageType::MethodCall {
+                continue;
+            }
+            let reply = handle(&msg, operation);
+            let _ = seen.send(msg);
+            if conn.send(reply).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+// ------------------------------------------------------------
+// Tests
+// ------------------------------------------------------------
+
+#[test]
+#[cfg(unix)]
+fn dbus_backend_talks_to_fake_rauc() {
+    let Some(bus) = start_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+    let (tx, rx) = mpsc::channel();
+    spawn_fake_rauc(&bus.address, "idle", tx);
+
+    let mut conn = Connection::open(&bus.address).unwrap();
+
+    let (slot, message) = mark_with(&mut conn, "good", "booted").unwrap();
+    assert_eq!(slot, "rootfs.0");
+    assert_eq!(message, "marked slot rootfs.0 as good");
+
+    let call = rx.recv().unwrap();
+    assert_eq!(call.interface.as_deref(), Some(INSTALLER_IFACE));
+    assert_eq!(
+        call.body,
+        vec![Value::Str("good".into()), Value::Str("booted".into())]
+    );
+
+    let slots = get_slot_status(&mut conn).unwrap();
+    assert_eq!(slots.len(), 2);
+    assert_eq!(slots[0].name, "rootfs.0");
+    assert_eq!(slots[0].get_str("state"), Some("booted"));
+    assert_eq!(slots[1].get_str("bootname"), Some("B"));
+
+    assert_eq!(boot_slot(&mut conn).unwrap(), "A");
+    assert_eq!(operation(&mut conn).unwrap(), "idle");
+}
+
+#[test]
+#[cfg(unix)]
+fn rejected_mark_is_remote_error_without_fallback() {
+    let Some(bus) = start_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+    let (tx, _rx) = mpsc::channel();
+    spawn_fake_rauc(&bus.address, "idle", tx);
+
+    let address = bus.address.clone();
+    let connect = move || Connection::open(&address);
+    let fallback = || -> Result<(), String> { panic!("fallback must not run") };
+
+    let err = mark_or_fallback(
+        &connect,
+        "explode",
+        "booted",
+        &RetryPolicy::none(),
+        &fallback,
+    )
+    .unwrap_err();
+    assert!(err.contains("RAUC rejected mark-explode"));
+    assert!(err.contains("nope"));
+}
+
+#[test]
+#[cfg(unix)]
+fn nothing_is_marked_while_rauc_is_installing() {
+    let Some(bus) = start_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+    let (tx, rx) = mpsc::channel();
+    spawn_fake_rauc(&bus.address, "installing", tx);
+
+    let address = bus.address.clone();
+    let connect = move || Connection::open(&address);
+    let fallback = || -> Result<(), String> { panic!("fallback must not run") };
+
+    let err =
+        mark_or_fallback(&connect, "good", "booted", &RetryPolicy::none(), &fallback).unwrap_err();
+    assert_eq!(err, "RAUC is busy (installing), not running mark-good");
+    assert!(rx
+        .try_iter()
+        .all(|call| call.member.as_deref() != Some("Mark")));
+}
+
+#[test]
+fn unreachable_bus_uses_cli_fallback() {
+    let used = std::cell::Cell::new(false);
+    let connect = || Connection::open("unix:path=/definitely/not/a/bus");
+    let fallback = || {
+        used.set(true);
+        Ok(())
+    };
+
+    mark_or_fallback(&connect, "good", "booted", &RetryPolicy::none(), &fallback).unwrap();
+    assert!(used.get());
+}
+
+#[test]
+fn missing_service_is_not_reachable_rather_than_rejected() {
+    let Some(bus) = start_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+    let mut conn = Connection::open(&bus.address).unwrap();
+
+    let err = mark_with(&mut conn, "good", "booted").unwrap_err();
+    assert!(matches!(err, dbus::Error::Remote { ref name, .. }
+        if name == "org.freedesktop.DBus.Error.ServiceUnknown"));
+
+    let address = bus.address.clone();
+    let connect = move || Connection::open(&address);
+    let fallback = || -> Result<(), String> { panic!("fallback must not run") };
+    let err =
+        mark_or_fallback(&connect, "good", "booted", &RetryPolicy::none(), &fallback).unwrap_err();
+    assert!(
+        err.starts_with("RAUC not reachable for mark-good: "),
+        "{err}"
+    );
+}
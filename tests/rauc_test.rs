@@ -1,10 +1,16 @@
 // tests/rauc_test.rs
 
-use std::process::ExitStatus;
+use std::cell::{Cell, RefCell};
+use std::process::{ExitStatus, Output};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use rauc_health::cmd::CmdRunner;
-use rauc_health::rauc::{mark_bad_with, mark_good_with};
+use rauc_health::marker::SlotMarker;
+use rauc_health::rauc::{
+    mark_bad_with, mark_good_with, mark_slot_with, retry_with, status_with, try_mark_slot_with,
+    BootStatus, CliMarker, MarkError, MarkState, RetryPolicy, SlotIdentifier,
+};
 
 /// Helper function to construct an [`ExitStatus`] on Unix systems.
 #[cfg(unix)]
@@ -20,6 +26,8 @@ fn exit_status(code: i32) -> ExitStatus {
 struct FakeRunner {
     calls: Mutex<Vec<(String, Vec<String>, String)>>,
     next: Mutex<Result<ExitStatus, String>>,
+    stdout: Mutex<String>,
+    stderr: Mutex<String>,
 }
 
 impl Default for FakeRunner {
@@ -27,6 +35,8 @@ impl Default for FakeRunner {
         Self {
             calls: Mutex::new(Vec::new()),
             next: Mutex::new(Ok(exit_status(0))),
+            stdout: Mutex::new(String::new()),
+            stderr: Mutex::new(String::new()),
         }
     }
 }
@@ -36,6 +46,14 @@ impl FakeRunner {
         *self.next.lock().unwrap() = res;
     }
 
+    fn will_print(&self, stdout: &str) {
+        *self.stdout.lock().unwrap() = stdout.to_string();
+    }
+
+    fn will_complain(&self, stderr: &str) {
+        *self.stderr.lock().unwrap() = stderr.to_string();
+    }
+
     fn take_calls(&self) -> Vec<(String, Vec<String>, String)> {
         std::mem::take(&mut *self.calls.lock().unwrap())
     }
@@ -50,6 +68,15 @@ impl CmdRunner for FakeRunner {
         ));
         self.next.lock().unwrap().clone()
     }
+
+    fn output(&self, program: &str, args: &[&str], ctx: &str) -> Result<Output, String> {
+        let status = self.status(program, args, ctx)?;
+        Ok(Output {
+            status,
+            stdout: self.stdout.lock().unwrap().clone().into_bytes(),
+            stderr: self.stderr.lock().unwrap().clone().into_bytes(),
+        })
+    }
 }
 
 // ------------------------------------------------------------
@@ -102,3 +129,283 @@ fn execution_error_is_propagated() {
     let err = mark_bad_with(&runner).unwrap_err();
     assert_eq!(err, "boom");
 }
+
+const STATUS_JSON: &str = r#"{
+  "compatible": "demo-board",
+  "variant": "",
+  "booted": "A",
+  "boot_primary": "rootfs.0",
+  "slots": [
+    {"rootfs.0": {"class": "rootfs", "device": "/dev/mmcblk0p2", "type": "ext4",
+      "bootname": "A", "state": "booted", "parent": null, "mountpoint": "/",
+      "boot_status": "good",
+      "slot_status": {
+        "bundle": {"compatible": "demo-board", "version": "2024.05"},
+        "installed": {"timestamp": "2024-05-02T10:11:12Z", "count": 3},
+        "activated": {"timestamp": "2024-05-02T10:11:20Z", "count": 3},
+        "status": "ok"}}},
+    {"rootfs.1": {"class": "rootfs", "device": "/dev/mmcblk0p3", "type": "ext4",
+      "bootname": "B", "state": "inactive", "parent": null, "mountpoint": null,
+      "boot_status": "bad"}}
+  ]
+}"#;
+
+#[test]
+#[cfg(unix)]
+fn status_runs_rauc_json_and_parses_slots() {
+    let runner = FakeRunner::default();
+    runner.will_print(STATUS_JSON);
+
+    let st = status_with(&runner).unwrap();
+
+    let calls = runner.take_calls();
+    assert_eq!(
+        calls[0].1,
+        vec!["status", "--detailed", "--output-format=json"]
+    );
+
+    assert_eq!(st.compatible, "demo-board");
+    assert_eq!(st.booted.as_deref(), Some("A"));
+    assert_eq!(st.boot_primary.as_deref(), Some("rootfs.0"));
+    assert_eq!(st.slots.len(), 2);
+
+    let booted = st.booted_slot().unwrap();
+    assert_eq!(booted.name, "rootfs.0");
+    assert_eq!(booted.class, "rootfs");
+    assert_eq!(booted.boot_status, Some(BootStatus::Good));
+    assert_eq!(booted.bundle_version.as_deref(), Some("2024.05"));
+    assert_eq!(
+        booted.installed.unwrap().to_string(),
+        "2024-05-02T10:11:12Z"
+    );
+    assert_eq!(booted.installed_count, Some(3));
+
+    let other = st.slot("rootfs.1").unwrap();
+    assert_eq!(other.boot_status, Some(BootStatus::Bad));
+    assert_eq!(other.bundle_version, None);
+    assert_eq!(other.mountpoint, None);
+}
+
+#[test]
+#[cfg(unix)]
+fn status_failure_is_error() {
+    let runner = FakeRunner::default();
+    runner.will_return(Ok(exit_status(1)));
+
+    let err = status_with(&runner).unwrap_err();
+    assert!(err.contains("exited with"));
+}
+
+#[test]
+#[cfg(unix)]
+fn status_with_invalid_json_is_error() {
+    let runner = FakeRunner::default();
+    runner.will_print("rauc: not json");
+
+    let err = status_with(&runner).unwrap_err();
+    assert!(err.contains("invalid rauc status JSON"));
+}
+
+#[test]
+fn slot_identifier_parses_keywords_and_names() {
+    assert_eq!("booted".parse(), Ok(SlotIdentifier::Booted));
+    assert_eq!("other".parse(), Ok(SlotIdentifier::Other));
+    assert_eq!(
+        "rootfs.1".parse(),
+        Ok(SlotIdentifier::Named("rootfs.1".into()))
+    );
+    assert!("  ".parse::<SlotIdentifier>().is_err());
+}
+
+#[test]
+#[cfg(unix)]
+fn mark_other_passes_slot_argument() {
+    let runner = FakeRunner::default();
+
+    mark_slot_with(&runner, MarkState::Bad, &SlotIdentifier::Other).unwrap();
+
+    let calls = runner.take_calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].1, vec!["status", "mark-bad", "other"]);
+    assert_eq!(calls[0].2, "rauc status mark-bad other");
+}
+
+#[test]
+#[cfg(unix)]
+fn mark_active_named_slot_is_validated_first() {
+    let runner = FakeRunner::default();
+    runner.will_print(STATUS_JSON);
+
+    let slot = SlotIdentifier::Named("rootfs.1".into());
+    mark_slot_with(&runner, MarkState::Active, &slot).unwrap();
+
+    let calls = runner.take_calls();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].1[1], "--detailed");
+    assert_eq!(calls[1].1, vec!["status", "mark-active", "rootfs.1"]);
+}
+
+#[test]
+#[cfg(unix)]
+fn mark_unknown_named_slot_is_rejected_without_marking() {
+    let runner = FakeRunner::default();
+    runner.will_print(STATUS_JSON);
+
+    let slot = SlotIdentifier::Named("rootfs.7".into());
+    let err = mark_slot_with(&runner, MarkState::Good, &slot).unwrap_err();
+
+    assert!(err.contains("unknown RAUC slot `rootfs.7`"));
+    assert!(err.contains("rootfs.0, rootfs.1"));
+    assert_eq!(runner.take_calls().len(), 1);
+}
+
+#[test]
+#[cfg(unix)]
+fn cli_marker_marks_and_reports_boot_states() {
+    let runner = FakeRunner::default();
+    runner.will_print(STATUS_JSON);
+    let marker = CliMarker::new(&runner);
+
+    marker.mark_active(&SlotIdentifier::Other).unwrap();
+    let states = marker.slot_states().unwrap();
+
+    let calls = runner.take_calls();
+    assert_eq!(calls[0].1, vec!["status", "mark-active", "other"]);
+    assert_eq!(states.len(), 2);
+    assert_eq!(
+        (
+            states[0].bootname.as_str(),
+            states[0].order,
+            states[0].remaining_attempts
+        ),
+        ("A", Some(0), 1)
+    );
+    assert_eq!(
+        (
+            states[1].bootname.as_str(),
+            states[1].order,
+            states[1].remaining_attempts
+        ),
+        ("B", None, 0)
+    );
+}
+
+// ------------------------------------------------------------
+// Retry
+// ------------------------------------------------------------
+
+#[test]
+#[cfg(unix)]
+fn unreachable_service_and_rejection_are_told_apart() {
+    let runner = FakeRunner::default();
+    runner.will_return(Ok(exit_status(1)));
+    runner.will_complain(
+        "Error creating proxy: GDBus.Error:org.freedesktop.DBus.Error.ServiceUnknown: \
+         The name de.pengutronix.rauc was not provided by any .service files",
+    );
+
+    let err = try_mark_slot_with(&runner, MarkState::Good, &SlotIdentifier::Booted).unwrap_err();
+    assert!(matches!(err, MarkError::NotReachable(ref e) if e.contains("exited with")));
+
+    runner.will_complain("rauc-Message: Failed marking slot: slot is not bootable");
+    let err = try_mark_slot_with(&runner, MarkState::Good, &SlotIdentifier::Booted).unwrap_err();
+    assert!(matches!(err, MarkError::Rejected(_)));
+}
+
+/// Clock that only advances when slept on.
+struct FakeClock {
+    now: Cell<Instant>,
+    sleeps: RefCell<Vec<Duration>>,
+}
+
+impl FakeClock {
+    fn new() -> Self {
+        Self {
+            now: Cell::new(Instant::now()),
+            sleeps: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn sleep(&self, d: Duration) {
+        self.sleeps.borrow_mut().push(d);
+        self.now.set(self.now.get() + d);
+    }
+}
+
+fn policy(attempts: u32, max_total_ms: u64) -> RetryPolicy {
+    RetryPolicy {
+        attempts,
+        backoff: Duration::from_millis(100),
+        max_total: Duration::from_millis(max_total_ms),
+    }
+}
+
+#[test]
+fn retry_backs_off_until_rauc_is_reachable() {
+    let clock = FakeClock::new();
+    let calls = Cell::new(0);
+
+    let res = retry_with(
+        &policy(5, 10_000),
+        &|d| clock.sleep(d),
+        &|| clock.now.get(),
+        &mut || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(MarkError::NotReachable("not up".into()))
+            } else {
+                Ok(calls.get())
+            }
+        },
+    );
+
+    assert_eq!(res, Ok(3));
+    assert_eq!(
+        clock.sleeps.into_inner(),
+        vec![Duration::from_millis(100), Duration::from_millis(200)]
+    );
+}
+
+#[test]
+fn retry_does_not_repeat_rejected_requests() {
+    let clock = FakeClock::new();
+    let calls = Cell::new(0);
+
+    let res: Result<(), _> = retry_with(
+        &policy(5, 10_000),
+        &|d| clock.sleep(d),
+        &|| clock.now.get(),
+        &mut || {
+            calls.set(calls.get() + 1);
+            Err(MarkError::Rejected("nope".into()))
+        },
+    );
+
+    assert_eq!(res, Err(MarkError::Rejected("nope".into())));
+    assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn retry_gives_up_at_attempt_and_time_limits() {
+    let unreachable = || -> Result<(), MarkError> { Err(MarkError::NotReachable("down".into())) };
+
+    let clock = FakeClock::new();
+    let res = retry_with(
+        &policy(3, 10_000),
+        &|d| clock.sleep(d),
+        &|| clock.now.get(),
+        &mut { unreachable },
+    );
+    assert_eq!(res, Err(MarkError::NotReachable("down".into())));
+    assert_eq!(clock.sleeps.into_inner().len(), 2);
+
+    // 100 + 200 ms fit into 350 ms, the next 400 ms delay does not
+    let clock = FakeClock::new();
+    let _ = retry_with(
+        &policy(10, 350),
+        &|d| clock.sleep(d),
+        &|| clock.now.get(),
+        &mut { unreachable },
+    );
+    assert_eq!(clock.sleeps.into_inner().len(), 2);
+}
@@ -0,0 +1,79 @@
+// tests/state_test.rs
+
+use rauc_health::openrc::{FailedService, HealthDecision, ServiceState};
+use rauc_health::rauc::parse_status_json;
+use rauc_health::state::{
+    read_last_decision, write_last_decision, DecisionRecord, LAST_DECISION_FILE,
+};
+use rauc_health::status::render_status;
+
+fn ts() -> jiff::Timestamp {
+    "2024-05-02T10:15:00Z".parse().unwrap()
+}
+
+fn bad_decision() -> HealthDecision {
+    HealthDecision::Bad(vec![FailedService {
+        name: "cron".into(),
+        status: ServiceState::Stopped,
+    }])
+}
+
+#[test]
+fn missing_last_decision_is_none() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(read_last_decision(dir.path()).unwrap(), None);
+}
+
+#[test]
+fn last_decision_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let state_dir = dir.path().join("nested/state");
+    let rec = DecisionRecord::new(&bad_decision(), ts());
+
+    write_last_decision(&state_dir, &rec).unwrap();
+
+    assert_eq!(read_last_decision(&state_dir).unwrap(), Some(rec));
+}
+
+#[test]
+fn service_states_are_stored_as_text() {
+    let dir = tempfile::tempdir().unwrap();
+    let decision = HealthDecision::Bad(vec![
+        FailedService {
+            name: "cron".into(),
+            status: ServiceState::Stopped,
+        },
+        FailedService {
+            name: "dbus".into(),
+            status: ServiceState::Missing,
+        },
+    ]);
+    let rec = DecisionRecord::new(&decision, ts());
+
+    write_last_decision(dir.path(), &rec).unwrap();
+
+    let text = std::fs::read_to_string(dir.path().join(LAST_DECISION_FILE)).unwrap();
+    assert!(text.contains("status = \"stopped\""));
+    assert!(text.contains("status = \"missing\""));
+    assert_eq!(read_last_decision(dir.path()).unwrap(), Some(rec));
+}
+
+#[test]
+fn render_status_includes_slots_and_last_decision() {
+    let status = parse_status_json(
+        r#"{"compatible":"demo","booted":"A","boot_primary":"rootfs.0","slots":[
+            {"rootfs.0":{"class":"rootfs","device":"/dev/a","bootname":"A","state":"booted","boot_status":"good"}}]}"#,
+    )
+    .unwrap();
+    let rec = DecisionRecord::new(&bad_decision(), ts());
+
+    let out = render_status(&status, Some(&rec));
+
+    assert!(out.contains("Compatible: demo"));
+    assert!(out.contains("rootfs.0 [rootfs] bootname=A state=booted boot-status=good"));
+    assert!(out.contains("BAD at 2024-05-02T10:15:00Z"));
+    assert!(out.contains("failed: cron (stopped)"));
+
+    let out = render_status(&status, None);
+    assert!(out.contains("none recorded"));
+}
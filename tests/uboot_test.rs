@@ -0,0 +1,195 @@
+// tests/uboot_test.rs
+//
+// U-Boot environment backend against plain image files.
+
+use rauc_health::marker::{parse_system_conf_bootnames, SlotMarker, SlotNames};
+use rauc_health::rauc::{MarkState, SlotIdentifier};
+use rauc_health::uboot::{
+    crc32, parse_fw_env_config, EnvLocation, EnvStorage, Environment, UbootMarker,
+};
+use std::path::Path;
+
+const ENV_SIZE: usize = 0x400;
+
+fn env(vars: &[(&str, &str)]) -> Environment {
+    let mut env = Environment::default();
+    for (k, v) in vars {
+        env.set(k, v);
+    }
+    env
+}
+
+/// Builds one redundant copy (CRC, flags, data).
+fn redundant_copy(env: &Environment, flags: u8) -> Vec<u8> {
+    let data = env.to_data(ENV_SIZE - 5).unwrap();
+    let mut buf = crc32(&data).to_le_bytes().to_vec();
+    buf.push(flags);
+    buf.extend_from_slice(&data);
+    buf
+}
+
+fn redundant_storage(image: &Path) -> EnvStorage {
+    EnvStorage::new(vec![
+        EnvLocation {
+            device: image.to_path_buf(),
+            offset: 0,
+            size: ENV_SIZE,
+        },
+        EnvLocation {
+            device: image.to_path_buf(),
+            offset: ENV_SIZE as u64,
+            size: ENV_SIZE,
+        },
+    ])
+    .unwrap()
+}
+
+fn flags_of(image: &Path) -> (u8, u8) {
+    let bytes = std::fs::read(image).unwrap();
+    (bytes[4], bytes[ENV_SIZE + 4])
+}
+
+fn booted_a() -> SlotNames {
+    SlotNames {
+        booted: Some("A".into()),
+        bootnames: parse_system_conf_bootnames(
+            "[system]\ncompatible=x\n\n[slot.rootfs.0]\nbootname=A\n\n[slot.rootfs.1]\nbootname=B\n",
+        ),
+    }
+}
+
+fn image_with(first: &Environment, second: &Environment) -> tempfile::NamedTempFile {
+    let image = tempfile::NamedTempFile::new().unwrap();
+    let mut bytes = redundant_copy(first, 1);
+    bytes.extend(redundant_copy(second, 2));
+    std::fs::write(image.path(), bytes).unwrap();
+    image
+}
+
+#[test]
+fn fw_env_config_is_parsed() {
+    let locs = parse_fw_env_config(
+        "# comment\n/dev/mmcblk0boot1 0x3e0000 0x20000\n/dev/mmcblk0boot1 0x3c0000 0x20000 0x1000 2\n",
+    )
+    .unwrap();
+
+    assert_eq!(locs.len(), 2);
+    assert_eq!(locs[0].offset, 0x3e_0000);
+    assert_eq!(locs[1].size, 0x2_0000);
+
+    assert!(parse_fw_env_config("").is_err());
+    assert!(parse_fw_env_config("/dev/x 0 0x100\n/dev/x 0x100 0x200\n").is_err());
+    assert!(parse_fw_env_config("/dev/x zero 0x100\n").is_err());
+}
+
+#[test]
+fn newer_redundant_copy_wins_and_store_writes_the_other() {
+    let old = env(&[("BOOT_ORDER", "A B"), ("BOOT_A_LEFT", "0")]);
+    let new = env(&[("BOOT_ORDER", "A B"), ("BOOT_A_LEFT", "1")]);
+    let image = image_with(&old, &new);
+    let storage = redundant_storage(image.path());
+
+    assert_eq!(storage.load().unwrap(), new);
+
+    let mut next = new.clone();
+    next.set("BOOT_A_LEFT", "3");
+    storage.store(&next).unwrap();
+
+    assert_eq!(flags_of(image.path()), (3, 2));
+    assert_eq!(storage.load().unwrap(), next);
+}
+
+#[test]
+fn corrupted_copy_falls_back_to_valid_one() {
+    let good = env(&[("BOOT_ORDER", "A B")]);
+    let image = image_with(&good, &good);
+    let mut bytes = std::fs::read(image.path()).unwrap();
+    bytes[ENV_SIZE + 10] ^= 0xff;
+    std::fs::write(image.path(), bytes).unwrap();
+
+    assert_eq!(redundant_storage(image.path()).load().unwrap(), good);
+}
+
+#[test]
+fn single_environment_roundtrip() {
+    let image = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(image.path(), vec![0xffu8; 0x200]).unwrap();
+    let storage = EnvStorage::new(vec![EnvLocation {
+        device: image.path().to_path_buf(),
+        offset: 0x100,
+        size: 0x100,
+    }])
+    .unwrap();
+
+    assert!(storage.load().unwrap_err().contains("CRC mismatch"));
+
+    let e = env(&[("bootcmd", "run rauc"), ("BOOT_ORDER", "B A")]);
+    storage.store(&e).unwrap();
+    assert_eq!(storage.load().unwrap(), e);
+    // bytes before the environment are untouched
+    assert_eq!(std::fs::read(image.path()).unwrap()[0], 0xff);
+}
+
+#[test]
+fn marker_uses_rauc_boot_variables() {
+    let e = env(&[
+        ("BOOT_ORDER", "A B"),
+        ("BOOT_A_LEFT", "1"),
+        ("BOOT_B_LEFT", "3"),
+    ]);
+    let image = image_with(&e, &e);
+    let marker = UbootMarker::new(redundant_storage(image.path()), 3, booted_a());
+    let load = || redundant_storage(image.path()).load().unwrap();
+
+    marker
+        .mark(MarkState::Good, &SlotIdentifier::Booted)
+        .unwrap();
+    assert_eq!(load().get("BOOT_A_LEFT"), Some("3"));
+
+    marker.mark(MarkState::Bad, &SlotIdentifier::Other).unwrap();
+    assert_eq!(load().get("BOOT_B_LEFT"), Some("0"));
+
+    marker
+        .mark(MarkState::Active, &SlotIdentifier::Named("rootfs.1".into()))
+        .unwrap();
+    assert_eq!(load().get("BOOT_ORDER"), Some("B A"));
+    assert_eq!(load().get("BOOT_B_LEFT"), Some("3"));
+
+    let states = marker.slot_states().unwrap();
+    assert_eq!(states[0].bootname, "B");
+    assert_eq!(states[0].order, Some(0));
+    assert_eq!(states[1].remaining_attempts, 3);
+}
+
+#[test]
+fn unchanged_environment_is_not_rewritten() {
+    let e = env(&[("BOOT_ORDER", "A B"), ("BOOT_A_LEFT", "3")]);
+    let image = image_with(&e, &e);
+    let marker = UbootMarker::new(redundant_storage(image.path()), 3, booted_a());
+
+    marker
+        .mark(MarkState::Good, &SlotIdentifier::Booted)
+        .unwrap();
+
+    assert_eq!(flags_of(image.path()), (1, 2));
+}
+
+#[test]
+fn unknown_slot_and_missing_boot_order_are_errors() {
+    let e = env(&[("BOOT_ORDER", "A B")]);
+    let image = image_with(&e, &e);
+    let marker = UbootMarker::new(redundant_storage(image.path()), 3, booted_a());
+
+    let err = marker
+        .mark(MarkState::Good, &SlotIdentifier::Named("rootfs.7".into()))
+        .unwrap_err();
+    assert_eq!(err, "unknown slot `rootfs.7`");
+
+    // the newer copy lacks BOOT_ORDER
+    let image = image_with(&e, &env(&[("bootdelay", "0")]));
+    let marker = UbootMarker::new(redundant_storage(image.path()), 3, booted_a());
+    let err = marker
+        .mark(MarkState::Good, &SlotIdentifier::Booted)
+        .unwrap_err();
+    assert_eq!(err, "BOOT_ORDER not set in U-Boot environment");
+}
@@ -0,0 +1,188 @@
+// tests/validate_test.rs
+//
+// `validate-config` against a fake OpenRC root file system.
+
+use rauc_health::config::Severity;
+use rauc_health::validate::validate_config_with;
+use std::path::Path;
+
+fn service(root: &Path, name: &str, runlevel: Option<&str>) {
+    let init_d = root.join("etc/init.d");
+    std::fs::create_dir_all(&init_d).unwrap();
+    std::fs::write(init_d.join(name), "#!/sbin/openrc-run\n").unwrap();
+    if let Some(runlevel) = runlevel {
+        let dir = root.join("etc/runlevels").join(runlevel);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::os::unix::fs::symlink(Path::new("/etc/init.d").join(name), dir.join(name)).unwrap();
+    }
+}
+
+fn write_config(dir: &Path, toml: &str) -> std::path::PathBuf {
+    let path = dir.join("rauc-health.toml");
+    std::fs::write(&path, toml).unwrap();
+    path
+}
+
+#[test]
+fn enabled_services_are_valid() {
+    let root = tempfile::tempdir().unwrap();
+    service(root.path(), "sshd", Some("default"));
+    service(root.path(), "ntpd", Some("default"));
+    let cfg = write_config(
+        root.path(),
+        "required_services = [\"sshd\"]\noptional_services = [\"ntpd\"]\n",
+    );
+
+    let report = validate_config_with(Some(&cfg), root.path());
+
+    assert!(report.valid);
+    assert!(report.issues.is_empty());
+}
+
+#[test]
+fn missing_and_disabled_services_are_reported_with_location() {
+    let root = tempfile::tempdir().unwrap();
+    service(root.path(), "net.eth0", Some("boot"));
+    service(root.path(), "cron", None);
+    let cfg = write_config(
+        root.path(),
+        "required_services = [\"net.eth0\", \"sshd\"]\noptional_services = [\"cron\"]\n",
+    );
+
+    let report = validate_config_with(Some(&cfg), root.path());
+
+    assert!(!report.valid);
+    let issues: Vec<_> = report
+        .issues
+        .iter()
+        .map(|i| (i.severity, i.to_string()))
+        .collect();
+    assert_eq!(
+        issues,
+        [
+            (
+                Severity::Error,
+                format!("{}: line 1, column 22: required_services: `net.eth0` is enabled in runlevel boot but not in default", cfg.display())
+            ),
+            (
+                Severity::Error,
+                format!("{}: line 1, column 34: required_services: `sshd` has no init script /etc/init.d/sshd", cfg.display())
+            ),
+            (
+                Severity::Warning,
+                format!("{}: line 2, column 22: optional_services: `cron` is not enabled in runlevel default", cfg.display())
+            ),
+        ]
+    );
+}
+
+#[test]
+fn services_may_be_enabled_in_any_checked_runlevel() {
+    let root = tempfile::tempdir().unwrap();
+    service(root.path(), "net.eth0", Some("boot"));
+    service(root.path(), "sshd", Some("default"));
+    let cfg = write_config(
+        root.path(),
+        "runlevels = [\"boot\", \"default\"]\nrequired_services = [\"net.eth0\", \"sshd\"]\n",
+    );
+
+    let report = validate_config_with(Some(&cfg), root.path());
+    assert!(report.valid);
+    assert!(report.issues.is_empty());
+
+    let cfg = write_config(
+        root.path(),
+        "runlevels = [\"sysinit\", \"default\"]\nrequired_services = [\"net.eth0\"]\n",
+    );
+    let report = validate_config_with(Some(&cfg), root.path());
+    assert_eq!(
+        report.issues[0].message,
+        "required_services: `net.eth0` is enabled in runlevel boot but not in sysinit, default"
+    );
+}
+
+#[test]
+fn syntax_errors_and_shadowed_services_are_reported_as_json() {
+    let root = tempfile::tempdir().unwrap();
+    let cfg = write_config(root.path(), "required_services = [\"sshd\"\n");
+
+    let report = validate_config_with(Some(&cfg), root.path());
+    assert!(!report.valid);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].line, Some(2));
+
+    service(root.path(), "local", Some("default"));
+    let cfg = write_config(root.path(), "required_services = [\"local\"]\n");
+    let report = validate_config_with(Some(&cfg), root.path());
+    assert!(!report.valid);
+
+    let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["valid"], false);
+    assert_eq!(json["issues"][0]["severity"], "error");
+    assert_eq!(
+        json["issues"][0]["message"],
+        "required service `local` is ignored by ignore_exact"
+    );
+    assert_eq!(json["issues"][0]["line"], 1);
+}
+
+#[test]
+fn config_and_dropins_are_found_below_root() {
+    let root = tempfile::tempdir().unwrap();
+    service(root.path(), "sshd", Some("default"));
+    let etc = root.path().join("etc");
+    std::fs::write(
+        etc.join("rauc-health.toml"),
+        "required_services = [\"sshd\"]\n\n[[variant]]\nname = \"any\"\nmatch = { slot = \"*\" }\nconfig = { required_services = [\"modem\"] }\n",
+    )
+    .unwrap();
+    std::fs::create_dir_all(etc.join("rauc-health.d")).unwrap();
+    std::fs::write(
+        etc.join("rauc-health.d/10-can.toml"),
+        "required_services = [\"can0\"]\n",
+    )
+    .unwrap();
+
+    let report = validate_config_with(None, root.path());
+
+    assert_eq!(report.config, Some(etc.join("rauc-health.toml")));
+    // the drop-in below root is merged; the variant matches no build host
+    let messages: Vec<_> = report.issues.iter().map(|i| i.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        ["required_services: `can0` has no init script /etc/init.d/can0"]
+    );
+}
+
+#[test]
+fn dropin_issues_are_located_in_the_dropin() {
+    let root = tempfile::tempdir().unwrap();
+    service(root.path(), "sshd", Some("default"));
+    let cfg = write_config(root.path(), "required_services = [\"sshd\"]\n");
+    let dropin = root.path().join("rauc-health.d/10-can.toml");
+    std::fs::create_dir_all(dropin.parent().unwrap()).unwrap();
+    std::fs::write(
+        &dropin,
+        "# CAN bus\nrequired_services = { extend = [\"can0\", \"local\"] }\n",
+    )
+    .unwrap();
+
+    let report = validate_config_with(Some(&cfg), root.path());
+
+    let issues: Vec<_> = report.issues.iter().map(ToString::to_string).collect();
+    let file = dropin.display();
+    assert_eq!(
+        issues,
+        [
+            format!(
+                "{file}: line 2, column 41: required service `local` is ignored by ignore_exact"
+            ),
+            format!(
+                "{file}: line 2, column 33: required_services: `can0` has no init script /etc/init.d/can0"
+            ),
+            format!(
+                "{file}: line 2, column 41: required_services: `local` has no init script /etc/init.d/local"
+            ),
+        ]
+    );
+}
@@ -0,0 +1,148 @@
+// tests/variant_test.rs
+//
+// `[[variant]]` sections matched against fake system facts.
+
+use rauc_health::config::from_toml_str;
+use rauc_health::dropin::load_layered;
+use rauc_health::variant::{matches, parse_system_conf_compatible, render_explain, SystemFacts};
+use std::path::{Path, PathBuf};
+
+const CONFIG: &str = r#"required_services = ["sshd"]
+
+[[variant]]
+name = "board-b"
+match = { device_tree_compatible = "acme,board-b*" }
+config = { required_services = { extend = ["can0"] }, timeout_seconds = 60 }
+
+[[variant]]
+name = "board-c"
+match = { device_tree_compatible = "acme,board-c" }
+config = { required_services = ["modem"] }
+
+[[variant]]
+name = "recovery"
+match = { slot = "rootfs.1", rauc_compatible = "acme-board" }
+config = { optional_services = ["ntpd"] }
+"#;
+
+fn write_config(dir: &Path, toml: &str) -> PathBuf {
+    let path = dir.join("rauc-health.toml");
+    std::fs::write(&path, toml).unwrap();
+    path
+}
+
+fn board_b() -> SystemFacts {
+    SystemFacts {
+        device_tree_compatible: vec!["acme,board-b-rev2".into(), "acme,soc".into()],
+        rauc_compatible: Some("acme-board".into()),
+        slot: Some("rootfs.0".into()),
+        bootname: Some("A".into()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn patterns_and_system_conf() {
+    assert!(matches("acme,board-b*", "acme,board-b-rev2"));
+    assert!(matches("1.2.0", "1.2.0"));
+    assert!(!matches("1.2", "1.2.0"));
+
+    let conf =
+        "[system]\ncompatible=acme-board\nbootloader=uboot\n\n[slot.rootfs.0]\ncompatible=x\n";
+    assert_eq!(
+        parse_system_conf_compatible(conf).as_deref(),
+        Some("acme-board")
+    );
+    assert_eq!(parse_system_conf_compatible("[slot.rootfs.0]\n"), None);
+}
+
+#[test]
+fn matching_sections_are_merged_over_the_base() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_config(dir.path(), CONFIG);
+
+    let layered = load_layered(Some(&path), &|_| None, &|_| Ok(board_b())).unwrap();
+
+    assert_eq!(layered.applied, vec!["board-b"]);
+    assert_eq!(layered.config.required_services, vec!["sshd", "can0"]);
+    assert_eq!(layered.config.timeout_seconds, 60);
+    assert!(layered.config.optional_services.is_empty());
+
+    let out = render_explain(&layered);
+    assert!(out.contains("#   device-tree compatible: acme,board-b-rev2, acme,soc\n"));
+    assert!(out.contains("#   board-b: applied\n"));
+    assert!(out.contains("#   board-c: not applied (device_tree_compatible differs)\n"));
+    assert!(out.contains("#   recovery: not applied (slot differs)\n"));
+    assert!(out.contains("timeout_seconds = 60 # variant board-b\n"));
+    assert!(out.contains("    \"can0\", # variant board-b\n"));
+}
+
+#[test]
+fn slot_matches_slot_name_or_bootname() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_config(dir.path(), CONFIG);
+    let facts = SystemFacts {
+        slot: Some("rootfs.1".into()),
+        bootname: Some("B".into()),
+        ..board_b()
+    };
+
+    let layered = load_layered(Some(&path), &|_| None, &|_| Ok(facts.clone())).unwrap();
+    assert_eq!(layered.applied, vec!["board-b", "recovery"]);
+    assert_eq!(layered.config.optional_services, vec!["ntpd"]);
+
+    let by_bootname = CONFIG.replace("slot = \"rootfs.1\"", "slot = \"B\"");
+    let path = write_config(dir.path(), &by_bootname);
+    let layered = load_layered(Some(&path), &|_| None, &|_| Ok(facts.clone())).unwrap();
+    assert_eq!(layered.applied, vec!["board-b", "recovery"]);
+}
+
+#[test]
+fn invalid_sections_are_rejected_on_every_board() {
+    let dir = tempfile::tempdir().unwrap();
+    let typo = CONFIG.replace("required_services = [\"modem\"]", "requried_services = []");
+    let path = write_config(dir.path(), &typo);
+
+    let err = load_layered(Some(&path), &|_| None, &|_| Ok(board_b())).unwrap_err();
+    assert_eq!(err, "variant board-c: unknown field `requried_services`");
+
+    let err = from_toml_str("[[variant]]\nname = \"x\"\nmatch = {}\n").unwrap_err();
+    assert_eq!(err, "line 2, column 8: variant `x`: no match conditions");
+
+    let src = "[[variant]]\nname = \"x\"\nmatch = { slot = \"A\" }\n\n[[variant]]\nname = \"x\"\nmatch = { slot = \"B\" }\n";
+    let err = from_toml_str(src).unwrap_err();
+    assert_eq!(err, "line 6, column 8: variant: duplicate `x`");
+}
+
+#[test]
+fn unknown_bundle_version_fails_the_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_config(dir.path(), CONFIG);
+
+    let err = load_layered(Some(&path), &|_| None, &|_| Err("no rauc".into())).unwrap_err();
+    assert_eq!(err, "no rauc");
+}
+
+#[test]
+fn bundle_version_is_taken_from_the_pending_record() {
+    let dir = tempfile::tempdir().unwrap();
+    let cfg = rauc_health::config::HealthConfig {
+        state_dir: dir.path().to_path_buf(),
+        ..Default::default()
+    };
+    rauc_health::state::write_pending_update(
+        dir.path(),
+        &rauc_health::state::PendingUpdate {
+            slot: "rootfs.1".into(),
+            bootname: Some("B".into()),
+            bundle_version: Some("2.1".into()),
+            timestamp: "2024-05-02T10:15:00Z".into(),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        SystemFacts::bundle_version(&cfg, Some("rootfs.1")).unwrap(),
+        "2.1"
+    );
+}